@@ -0,0 +1,128 @@
+//! Benchmarks of the general simulation, used to compare map and voter set representations.
+//!
+//! Run with `cargo bench`, and `cargo bench --no-default-features` to compare map backends.
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use gossip::arena::RoundArena;
+use gossip::quorum::{Majority, QuorumRule};
+use gossip::{
+    cast_scheduled_votes, construct_voting_schedule, gossip_round, run_simulation,
+    DefaultStrategy, Node, NodeId, Params, PhaseTimes, Strategy,
+};
+use rand::{SeedableRng, XorShiftRng};
+use std::sync::Arc;
+
+fn bench_run_simulation(c: &mut Criterion) {
+    let quorum: Arc<dyn QuorumRule> = Arc::new(Majority);
+    let mut group = c.benchmark_group("run_simulation");
+    group.sample_size(10);
+
+    for &n in &[100, 1000, 5000] {
+        let params = Params {
+            n,
+            k: n * 2 / 3,
+            voting_steps: 3,
+            script: None,
+            quorum: None,
+            partners: None,
+            timings: false,
+            mode: None,
+            seed: None,
+            label: None,
+            loss: 0.0,
+            max_messages_per_round: None,
+            rate_limit: None,
+            rate_limit_policy: None,
+            cost: None,
+            cohorts: None,
+            subnets: None,
+            max_rounds: None,
+            membership: None,
+            election: None,
+            mobility: None,
+        };
+        group.bench_with_input(BenchmarkId::from_parameter(n), &params, |b, params| {
+            let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+            b.iter(|| {
+                run_simulation(params, &DefaultStrategy, &quorum, &mut rng, &mut |_| ()).unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Copy `nodes` without sharing any voter sets with them, so that the copies can be changed
+/// without copying sets on write.
+fn deep_clone(nodes: &[Node]) -> Vec<Node> {
+    nodes
+        .iter()
+        .map(|node| {
+            let mut copy = Node::new(node.id, node.num_nodes, node.quorum.clone());
+            for (&vote_id, vote_info) in &node.votes {
+                copy.add_voters(vote_id, vote_info.voters());
+            }
+            copy
+        })
+        .collect()
+}
+
+/// Single rounds in the middle of a simulation, once the round's buffers have grown to fit.
+///
+/// Rounds like these make up most of a long simulation, and shouldn't allocate at all, so a
+/// regression here often means something has gone back to allocating every round.
+fn bench_gossip_round(c: &mut Criterion) {
+    let quorum: Arc<dyn QuorumRule> = Arc::new(Majority);
+    let mut group = c.benchmark_group("gossip_round");
+
+    for &n in &[1000, 5000] {
+        let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+        let mut nodes: Vec<Node> =
+            (0..n as NodeId).map(|i| Node::new(i, n, quorum.clone())).collect();
+        let voting_schedule = construct_voting_schedule(n * 2 / 3, 3);
+        let mut arenas = vec![RoundArena::new(n)];
+        let mut partners = vec![];
+        let mut times = PhaseTimes::default();
+
+        // Run until every voter has voted, but most nodes don't yet have a quorum.
+        for round in 0..3 {
+            cast_scheduled_votes(&mut nodes, &voting_schedule, round);
+            DefaultStrategy.choose_partners(n, round, &mut rng, &mut partners).unwrap();
+            let strategy = &DefaultStrategy;
+            gossip_round::<XorShiftRng>(
+                &mut nodes,
+                &partners,
+                &[],
+                &[],
+                strategy,
+                &mut arenas,
+                &mut times,
+            )
+            .unwrap();
+        }
+        DefaultStrategy.choose_partners(n, 3, &mut rng, &mut partners).unwrap();
+
+        group.bench_function(BenchmarkId::from_parameter(n), |b| {
+            b.iter_batched_ref(
+                || deep_clone(&nodes),
+                |nodes| {
+                    let strategy = &DefaultStrategy;
+                    gossip_round::<XorShiftRng>(
+                        nodes,
+                        &partners,
+                        &[],
+                        &[],
+                        strategy,
+                        &mut arenas,
+                        &mut times,
+                    )
+                    .unwrap()
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_run_simulation, bench_gossip_round);
+criterion_main!(benches);
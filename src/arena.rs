@@ -0,0 +1,252 @@
+//! Reusable storage for the updates produced during a gossip round.
+//!
+//! Every round sends each node a handful of voter sets, which are merged and applied once the
+//! round is over. Instead of building fresh maps and sets for them every round, they're taken
+//! from a slab of sets that's reset at the start of the next round, so a long simulation stops
+//! allocating once the slab has grown to fit its busiest round.
+//!
+//! A node with no voters for a vote is sent all of its partner's voters, so rather than copying
+//! them, the slot shares the partner's set, as the node will once the update is applied.
+//!
+//! The arena can also keep a log of the messages sent, for working out what they cost.
+
+use std::sync::Arc;
+
+use crate::voters::VoterSet;
+use crate::{NodeId, VoteId};
+
+const NONE: usize = usize::MAX;
+
+/// One vote ID sent to one node, in a linked list of the node's slots. The voters for slot `i`
+/// are in set `i` of the slab, which might be shared with a node.
+#[derive(Clone, Copy)]
+struct Slot {
+    vote_id: VoteId,
+    /// Index of the receiver's next slot, or `NONE`.
+    next: usize,
+}
+
+pub struct RoundArena {
+    num_nodes: usize,
+    /// Index of each node's first slot this round, or `NONE`.
+    heads: Vec<usize>,
+    slots: Vec<Slot>,
+    /// Nodes with at least one slot this round, in the order they were first sent something.
+    receivers: Vec<NodeId>,
+    /// Slab of voter sets, of which the first `slots.len()` belong to this round's slots.
+    sets: Vec<Arc<VoterSet>>,
+    /// Whether to log the messages sent in `sent`.
+    pub record_sent: bool,
+    sent: Vec<(NodeId, NodeId, usize)>,
+}
+
+impl RoundArena {
+    pub fn new(num_nodes: usize) -> Self {
+        RoundArena {
+            num_nodes,
+            heads: vec![NONE; num_nodes],
+            slots: vec![],
+            receivers: vec![],
+            sets: vec![],
+            record_sent: false,
+            sent: vec![],
+        }
+    }
+
+    /// The indices and vote IDs of the slots for `receiver` this round.
+    fn slots_for(&self, receiver: NodeId) -> impl Iterator<Item = (usize, VoteId)> + '_ {
+        let mut next = self.heads[receiver as usize];
+        std::iter::from_fn(move || {
+            let index = next;
+            let slot = self.slots.get(index)?;
+            next = slot.next;
+            Some((index, slot.vote_id))
+        })
+    }
+
+    /// The index of the slot for `vote_id` sent to `receiver` this round, and whether it's new.
+    fn slot(&mut self, receiver: NodeId, vote_id: VoteId) -> (usize, bool) {
+        if let Some((index, _)) = self.slots_for(receiver).find(|&(_, id)| id == vote_id) {
+            return (index, false);
+        }
+        let head = &mut self.heads[receiver as usize];
+        if *head == NONE {
+            self.receivers.push(receiver);
+        }
+        let index = self.slots.len();
+        self.slots.push(Slot { vote_id, next: *head });
+        *head = index;
+        (index, true)
+    }
+
+    fn set_voters(&mut self, index: usize, voters: Arc<VoterSet>) {
+        if index == self.sets.len() {
+            self.sets.push(voters);
+        } else {
+            self.sets[index] = voters;
+        }
+    }
+
+    /// The set collecting the voters for `vote_id` sent to `receiver` this round.
+    pub fn voters_mut(&mut self, receiver: NodeId, vote_id: VoteId) -> &mut VoterSet {
+        let (index, new) = self.slot(receiver, vote_id);
+        if new {
+            // Reuse the slab's set, unless it was shared with a node that still holds it.
+            match self.sets.get_mut(index).and_then(Arc::get_mut) {
+                Some(voters) => voters.clear(),
+                None => self.set_voters(index, Arc::new(VoterSet::new(self.num_nodes))),
+            }
+        }
+        Arc::make_mut(&mut self.sets[index])
+    }
+
+    /// Send all of `voters` for `vote_id` to `receiver`, sharing the set if nothing else has been
+    /// sent for `vote_id` this round. It's only copied if more voters are sent after it.
+    pub fn share(&mut self, receiver: NodeId, vote_id: VoteId, voters: &Arc<VoterSet>) {
+        match self.slot(receiver, vote_id) {
+            (index, true) => self.set_voters(index, voters.clone()),
+            (index, false) => {
+                Arc::make_mut(&mut self.sets[index]).union_with(voters);
+            }
+        }
+    }
+
+    /// Every update sent this round, as `(receiver, vote ID, voters)`.
+    pub fn updates(&self) -> impl Iterator<Item = (NodeId, VoteId, &Arc<VoterSet>)> + '_ {
+        self.receivers.iter().flat_map(move |&receiver| {
+            self.slots_for(receiver)
+                .map(move |(index, vote_id)| (receiver, vote_id, &self.sets[index]))
+        })
+    }
+
+    /// Log a message carrying `voters` voters from `sender` to `receiver`, if `record_sent` is set.
+    pub fn record(&mut self, sender: NodeId, receiver: NodeId, voters: usize) {
+        if self.record_sent {
+            self.sent.push((sender, receiver, voters));
+        }
+    }
+
+    /// The messages logged this round, as `(sender, receiver, voters)`. Unlike the updates,
+    /// these aren't merged, so every arena of a round has its own.
+    pub fn sent(&self) -> &[(NodeId, NodeId, usize)] {
+        &self.sent
+    }
+
+    /// Add every update in `other` to this round's updates.
+    pub fn merge(&mut self, other: &RoundArena) {
+        for (receiver, vote_id, voters) in other.updates() {
+            // Sets that `other` owns are copied rather than shared, so it can reuse them.
+            if Arc::strong_count(voters) > 1 {
+                self.share(receiver, vote_id, voters);
+            } else {
+                self.voters_mut(receiver, vote_id).union_with(voters);
+            }
+        }
+    }
+
+    /// Forget this round's updates, keeping their memory for the next round.
+    pub fn reset(&mut self) {
+        for &receiver in &self.receivers {
+            self.heads[receiver as usize] = NONE;
+        }
+        self.receivers.clear();
+        self.slots.clear();
+        self.sent.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn voters(ids: &[NodeId]) -> Arc<VoterSet> {
+        Arc::new(ids.iter().cloned().collect())
+    }
+
+    /// The updates of `arena`, sorted, with their voters listed.
+    fn updates(arena: &RoundArena) -> Vec<(NodeId, VoteId, Vec<NodeId>)> {
+        let mut updates: Vec<_> = arena
+            .updates()
+            .map(|(receiver, vote_id, voters)| (receiver, vote_id, voters.iter().collect()))
+            .collect();
+        updates.sort();
+        updates
+    }
+
+    #[test]
+    fn collects_voters_by_receiver_and_vote() {
+        let mut arena = RoundArena::new(10);
+        arena.voters_mut(3, 0).insert(1);
+        arena.voters_mut(3, 1).insert(2);
+        arena.voters_mut(3, 0).insert(4);
+        arena.voters_mut(5, 0).insert(1);
+        assert_eq!(
+            updates(&arena),
+            vec![(3, 0, vec![1, 4]), (3, 1, vec![2]), (5, 0, vec![1])]
+        );
+    }
+
+    #[test]
+    fn shared_sets_are_copied_before_changing() {
+        let mut arena = RoundArena::new(10);
+        let held = voters(&[1, 2]);
+        arena.share(4, 0, &held);
+        assert_eq!(Arc::strong_count(&held), 2);
+
+        // More voters for the same vote are added to a copy, leaving the node's set alone.
+        arena.share(4, 0, &voters(&[3]));
+        arena.voters_mut(4, 0).insert(5);
+        assert_eq!(held.iter().collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(updates(&arena), vec![(4, 0, vec![1, 2, 3, 5])]);
+    }
+
+    #[test]
+    fn reset_forgets_updates_but_not_shared_sets() {
+        let mut arena = RoundArena::new(10);
+        let held = voters(&[1, 2]);
+        arena.share(4, 0, &held);
+        arena.voters_mut(6, 0).insert(7);
+        arena.reset();
+        assert!(updates(&arena).is_empty());
+
+        // The first slot's set is still held by a node, so it mustn't be cleared for reuse.
+        arena.voters_mut(8, 0).insert(3);
+        arena.voters_mut(9, 0).insert(4);
+        assert_eq!(held.iter().collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(updates(&arena), vec![(8, 0, vec![3]), (9, 0, vec![4])]);
+    }
+
+    #[test]
+    fn merge_combines_the_updates_of_both_arenas() {
+        let held = voters(&[1]);
+        let mut ours = RoundArena::new(10);
+        ours.voters_mut(2, 0).insert(3);
+        let mut theirs = RoundArena::new(10);
+        theirs.voters_mut(2, 0).insert(4);
+        theirs.voters_mut(2, 1).insert(5);
+        theirs.share(6, 0, &held);
+
+        ours.merge(&theirs);
+        assert_eq!(
+            updates(&ours),
+            vec![(2, 0, vec![3, 4]), (2, 1, vec![5]), (6, 0, vec![1])]
+        );
+
+        // Sets `theirs` owns were copied, so resetting and reusing it leaves `ours` alone.
+        theirs.reset();
+        theirs.voters_mut(2, 1).insert(9);
+        assert_eq!(updates(&ours)[1], (2, 1, vec![5]));
+    }
+
+    #[test]
+    fn only_records_messages_when_asked() {
+        let mut arena = RoundArena::new(10);
+        arena.record(1, 2, 3);
+        assert!(arena.sent().is_empty());
+        arena.record_sent = true;
+        arena.record(1, 2, 3);
+        assert_eq!(arena.sent(), &[(1, 2, 3)]);
+        arena.reset();
+        assert!(arena.sent().is_empty());
+    }
+}
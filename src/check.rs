@@ -0,0 +1,342 @@
+//! Exhaustive model checking of tiny configurations.
+//!
+//! Instead of sampling partners at random, every possible choice of partners is explored in every
+//! round, up to a bound on the number of rounds. Identical network states reached by different
+//! schedules are merged, carrying the probability of reaching them when partners are chosen
+//! uniformly at random, which keeps the search tractable for up to `MAX_NODES` nodes.
+//!
+//! A network state is the set of voters each node knows of, as a bitmask. Each node's diff for
+//! each possible partner is computed once per state with the simulator's `compute_push_gossip`,
+//! and then every schedule just combines those diffs. Every diff is checked against two safety
+//! properties: nodes only send votes they hold, and only votes that were actually cast.
+
+use rand::weak_rng;
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::quorum::QuorumRule;
+use crate::voters::VoterSet;
+use crate::{
+    cast_scheduled_votes, compute_push_gossip, construct_voting_schedule, load_quorum_rule,
+    load_strategy, Node, NodeId, Params, PartnerMode, VoteInfo,
+};
+
+/// Largest network that can be checked, beyond which the `(n - 1)^n` schedules per round explode.
+pub const MAX_NODES: usize = 6;
+
+/// Voters for the rumour known to each node, as bitmasks of node IDs.
+type State = [u8; MAX_NODES];
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CheckResult {
+    n: usize,
+    k: usize,
+    voting_steps: usize,
+    max_rounds: usize,
+    /// Distinct unconverged network states explored across all rounds.
+    states_explored: usize,
+    /// Fewest rounds any schedule takes for every node to reach a quorum.
+    best_case_rounds: Option<usize>,
+    /// Most rounds any schedule takes, if every schedule converges within `max_rounds`.
+    worst_case_rounds: Option<usize>,
+    /// Probability of converging within `max_rounds` under uniformly random partner choice.
+    convergence_probability: f64,
+    /// Expected rounds to converge, over the schedules that converge within `max_rounds`, if any
+    /// do.
+    expected_rounds: Option<f64>,
+    /// Number of votes sent in violation of a safety property.
+    safety_violations: usize,
+    /// Why the check failed, in which case the measurements are all zero.
+    error: Option<String>,
+}
+
+impl CheckResult {
+    /// A result recording that checking `params` for `max_rounds` rounds failed with `error`.
+    pub fn failed(params: &Params, max_rounds: usize, error: String) -> Self {
+        CheckResult {
+            n: params.n,
+            k: params.k,
+            voting_steps: params.voting_steps,
+            max_rounds,
+            states_explored: 0,
+            best_case_rounds: None,
+            worst_case_rounds: None,
+            convergence_probability: 0.0,
+            expected_rounds: None,
+            safety_violations: 0,
+            error: Some(error),
+        }
+    }
+}
+
+fn to_voter_set(n: usize, voters: u8) -> VoterSet {
+    (0..n as NodeId).filter(|v| voters & (1 << v) != 0).collect()
+}
+
+fn to_node(id: NodeId, n: usize, voters: u8, quorum: &Arc<dyn QuorumRule>) -> Node {
+    let mut node = Node::new(id, n, quorum.clone());
+    if voters != 0 {
+        node.votes.insert(0, VoteInfo::with_voters(Arc::new(to_voter_set(n, voters))));
+    }
+    node
+}
+
+/// Advance `choices` to the next schedule, returning false once every schedule has been visited.
+fn next_schedule(choices: &mut [usize], num_options: usize) -> bool {
+    for choice in choices.iter_mut() {
+        *choice += 1;
+        if *choice < num_options {
+            return true;
+        }
+        *choice = 0;
+    }
+    false
+}
+
+/// Check that `params` is small enough and random enough to be model checked.
+pub fn validate(params: &Params) -> Result<(), String> {
+    if params.n > MAX_NODES {
+        return Err(format!("can't check n = {}, the limit is {} nodes", params.n, MAX_NODES));
+    }
+    if params.partners == Some(PartnerMode::Matched) {
+        return Err("can only check uniformly random partner choice".to_string());
+    }
+    if params.script.is_some() {
+        return Err("can only check uniformly random partner choice, not scripts".to_string());
+    }
+    if params.loss > 0.0 {
+        return Err("can't check runs in which messages are lost".to_string());
+    }
+    if params.max_messages_per_round.is_some() {
+        return Err("can't check runs with a rate limit".to_string());
+    }
+    if params.cohorts.is_some() {
+        return Err("can only check nodes that all run push-pull".to_string());
+    }
+    if params.subnets.is_some() {
+        return Err("can only check networks without subnets".to_string());
+    }
+    if params.election.is_some() {
+        return Err("can only check the vote workload, not elections".to_string());
+    }
+    if params.mobility.is_some() {
+        return Err("can only check nodes that stay in range of each other".to_string());
+    }
+    if params.membership.is_some() {
+        return Err("can only check networks in which no nodes crash".to_string());
+    }
+    if params.max_rounds.is_some() {
+        return Err("can't check runs with a deadline, only up to --max-rounds".to_string());
+    }
+    Ok(())
+}
+
+/// Explore every schedule of partner choices for `params`, for up to `max_rounds` rounds.
+pub fn check(params: &Params, max_rounds: usize) -> Result<CheckResult, Box<dyn Error>> {
+    let n = params.n;
+    validate(params)?;
+
+    let strategy = load_strategy(params, &mut weak_rng())?;
+    let quorum = load_quorum_rule(params)?;
+    // Whether each possible set of voters forms a quorum.
+    let is_quorum: Vec<bool> = (0..1 << n)
+        .map(|voters| quorum.has_quorum(&to_voter_set(n, voters), n))
+        .collect();
+    let voting_schedule = construct_voting_schedule(params.k, params.voting_steps);
+    let schedule_probability = 1.0 / ((n - 1) as f64).powi(n as i32);
+
+    let mut frontier: HashMap<State, f64> = HashMap::new();
+    frontier.insert([0; MAX_NODES], 1.0);
+    // Tracks which nodes have cast their votes, alongside the states.
+    let mut voting_nodes: Vec<Node> =
+        (0..n as NodeId).map(|i| Node::new(i, n, quorum.clone())).collect();
+
+    let mut states_explored = 0;
+    let mut best_case_rounds = None;
+    let mut latest_convergence = 0;
+    let mut convergence_probability = 0.0;
+    let mut total_rounds = 0.0;
+    let mut safety_violations = 0;
+
+    for round in 0..max_rounds {
+        cast_scheduled_votes(&mut voting_nodes, &voting_schedule, round);
+        let cast: u8 = (0..n).filter(|&i| voting_nodes[i].has_voted_for(0)).map(|i| 1 << i).sum();
+
+        let mut next_frontier = HashMap::new();
+
+        for (mut state, probability) in frontier {
+            for (i, voters) in state.iter_mut().enumerate().take(n) {
+                *voters |= cast & (1 << i);
+            }
+            let nodes: Vec<_> =
+                (0..n).map(|i| to_node(i as NodeId, n, state[i], &quorum)).collect();
+
+            // `diffs[i][j]` holds the votes that `i` would send to `j`.
+            let mut diffs = [[0u8; MAX_NODES]; MAX_NODES];
+            for i in 0..n {
+                for j in (0..n).filter(|&j| j != i) {
+                    let diff = match compute_push_gossip(&nodes[i], &nodes[j], &*strategy)? {
+                        Some(diff) => diff,
+                        None => continue,
+                    };
+                    for (vote_id, diff_voters) in diff {
+                        for voter in diff_voters.iter() {
+                            let violation = if vote_id != 0 || cast & (1 << voter) == 0 {
+                                Some("was never cast")
+                            } else if state[i] & (1 << voter) == 0 {
+                                Some("it doesn't hold")
+                            } else {
+                                None
+                            };
+                            if let Some(violation) = violation {
+                                if safety_violations == 0 {
+                                    warn!(
+                                        "safety violation in round {}: node {} sent node {} a vote \
+                                         for {} from node {}, which {}",
+                                        round, i, j, vote_id, voter, violation
+                                    );
+                                }
+                                safety_violations += 1;
+                            } else {
+                                diffs[i][j] |= 1 << voter;
+                            }
+                        }
+                    }
+                }
+            }
+
+            let mut choices = vec![0; n];
+            loop {
+                // Each node `i` pulls from and pushes to its partner, chosen from the other nodes.
+                let mut next = state;
+                for (i, &c) in choices.iter().enumerate() {
+                    let partner = if c < i { c } else { c + 1 };
+                    next[i] |= diffs[partner][i];
+                    next[partner] |= diffs[i][partner];
+                }
+
+                let p = probability * schedule_probability;
+                if next[..n].iter().all(|&voters| is_quorum[voters as usize]) {
+                    best_case_rounds = best_case_rounds.or(Some(round + 1));
+                    latest_convergence = round + 1;
+                    convergence_probability += p;
+                    total_rounds += p * (round + 1) as f64;
+                } else {
+                    *next_frontier.entry(next).or_insert(0.0) += p;
+                }
+
+                if !next_schedule(&mut choices, n - 1) {
+                    break;
+                }
+            }
+        }
+
+        states_explored += next_frontier.len();
+        frontier = next_frontier;
+        if frontier.is_empty() {
+            break;
+        }
+    }
+
+    Ok(CheckResult {
+        n,
+        k: params.k,
+        voting_steps: params.voting_steps,
+        max_rounds,
+        states_explored,
+        best_case_rounds,
+        worst_case_rounds: if frontier.is_empty() { Some(latest_convergence) } else { None },
+        convergence_probability,
+        expected_rounds: (convergence_probability > 0.0)
+            .then(|| total_rounds / convergence_probability),
+        safety_violations,
+        error: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    /// 3 nodes that each need the vote of a single voter, and the parameters in `extra`.
+    fn params(extra: Value) -> Params {
+        let mut params = json!({"n": 3, "k": 1, "voting_steps": 1, "quorum": "count:1"});
+        params.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(params).unwrap()
+    }
+
+    #[test]
+    fn visits_every_schedule_once() {
+        for n in 2..=MAX_NODES {
+            let mut choices = vec![0; n];
+            let mut seen = std::collections::HashSet::new();
+            seen.insert(choices.clone());
+            while next_schedule(&mut choices, n - 1) {
+                assert!(choices.iter().all(|&choice| choice < n - 1));
+                assert!(seen.insert(choices.clone()), "{:?} again", choices);
+            }
+            assert_eq!(seen.len(), (n - 1).pow(n as u32));
+            assert_eq!(choices, vec![0; n]);
+        }
+    }
+
+    #[test]
+    fn finds_the_best_and_worst_schedules() {
+        // Node 0 reaches one of the others in the first round, and the other with probability
+        // 1/2 by choosing node 0 itself. Failing that, it has a choice of two informed nodes.
+        let result = check(&params(json!({})), 10).unwrap();
+        assert_eq!(result.best_case_rounds, Some(1));
+        assert_eq!(result.worst_case_rounds, Some(2));
+        assert_eq!(result.convergence_probability, 1.0);
+        assert_eq!(result.expected_rounds, Some(1.5));
+        assert_eq!(result.safety_violations, 0);
+        assert_eq!(result.error, None);
+
+        // Stopping after the first round leaves the schedules that haven't converged.
+        let result = check(&params(json!({})), 1).unwrap();
+        assert_eq!(result.best_case_rounds, Some(1));
+        assert_eq!(result.worst_case_rounds, None);
+        assert_eq!(result.convergence_probability, 0.5);
+        assert_eq!(result.expected_rounds, Some(1.0));
+        assert!(result.states_explored > 0);
+    }
+
+    #[test]
+    fn converges_for_certain_given_enough_rounds() {
+        for max_rounds in [2, 3, 10] {
+            let result = check(&params(json!({})), max_rounds).unwrap();
+            assert_eq!(result.convergence_probability, 1.0, "within {}", max_rounds);
+            assert_eq!(result.worst_case_rounds, Some(2));
+        }
+        // With every node voting, each has a majority once it hears from its partner.
+        let result = check(&params(json!({"k": 3, "quorum": "majority"})), 3).unwrap();
+        assert_eq!((result.best_case_rounds, result.worst_case_rounds), (Some(1), Some(1)));
+        assert_eq!(result.convergence_probability, 1.0);
+    }
+
+    #[test]
+    fn rejects_unsupported_columns() {
+        for (extra, error) in [
+            (json!({"n": 7}), "the limit is 6 nodes"),
+            (json!({"partners": "matched"}), "uniformly random partner choice"),
+            (json!({"script": "strategy.rhai"}), "not scripts"),
+            (json!({"loss": 0.1}), "messages are lost"),
+            (json!({"max_messages_per_round": 2}), "rate limit"),
+            (json!({"cohorts": "push:0.5"}), "all run push-pull"),
+            (json!({"subnets": "2:0.1"}), "without subnets"),
+            (json!({"election": "max"}), "not elections"),
+            (json!({"mobility": "waypoint:0.3:0.05"}), "stay in range"),
+            (json!({"membership": "0.1:5"}), "no nodes crash"),
+            (json!({"max_rounds": 5}), "with a deadline"),
+        ] {
+            let e = validate(&params(extra.clone())).err().unwrap();
+            assert!(e.contains(error), "{} gave {:?}, expected {:?}", extra, e, error);
+            let e = check(&params(extra.clone()), 5).err().unwrap().to_string();
+            assert!(e.contains(error), "{} gave {:?}, expected {:?}", extra, e, error);
+        }
+        assert_eq!(validate(&params(json!({"n": MAX_NODES, "k": 4}))), Ok(()));
+    }
+}
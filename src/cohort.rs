@@ -0,0 +1,298 @@
+//! Cohorts of nodes running different versions of the exchange, for modelling a partial rollout.
+//!
+//! A row's `cohorts` column assigns the nodes to protocols, using one of these specs:
+//!
+//! * `<protocol>:<fraction>`, joined with `+` for several, such as `pull:0.2` or
+//!   `push:0.1+pull:0.1`. The nodes left over run `push-pull`.
+//! * `file:<path>`, naming the protocol of node `i` on line `i` of the file.
+//!
+//! The protocols are `push-pull`, which sends votes both ways as every node does without
+//! cohorts, `push`, which sends the node's votes to its partner without asking for the
+//! partner's, and `pull`, which asks for the partner's without sending its own. A protocol only
+//! changes the exchanges that a node starts, and every node answers the ones started with it.
+//! Push and pull nodes never exchange votes with each other, so they need some push-pull nodes
+//! between them.
+//!
+//! Fractions are rounded to whole nodes, taken in an order shuffled by node ID, so that the
+//! cohorts don't line up with the voters (nodes `0..k`) and every run has the same ones.
+
+use std::fs;
+
+use crate::{splitmix64, Params, SimulationResult};
+
+/// The version of the exchange that a node runs when it starts one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    PushPull,
+    Push,
+    Pull,
+}
+
+impl Protocol {
+    pub const ALL: [Protocol; 3] = [Protocol::PushPull, Protocol::Push, Protocol::Pull];
+
+    /// The name of the protocol, as parsed by `from_str`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Protocol::PushPull => "push-pull",
+            Protocol::Push => "push",
+            Protocol::Pull => "pull",
+        }
+    }
+
+    /// Whether the exchanges the node starts send in `direction`, in the order that `is_lost`
+    /// looks messages up: 0 for the partner's votes to the node, 1 for the node's to the partner.
+    pub fn sends(self, direction: usize) -> bool {
+        match self {
+            Protocol::PushPull => true,
+            Protocol::Push => direction == 1,
+            Protocol::Pull => direction == 0,
+        }
+    }
+}
+
+impl std::str::FromStr for Protocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "push-pull" => Ok(Protocol::PushPull),
+            "push" => Ok(Protocol::Push),
+            "pull" => Ok(Protocol::Pull),
+            _ => Err(format!("unknown protocol {:?}, expected push-pull, push or pull", s)),
+        }
+    }
+}
+
+fn read_protocols(filename: &str) -> Result<Vec<Protocol>, String> {
+    let contents = fs::read_to_string(filename)
+        .map_err(|e| format!("failed to read cohorts file {}: {}", filename, e))?;
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| line.parse().map_err(|e| format!("{} in {}", e, filename)))
+        .collect()
+}
+
+/// Check that the votes of every cohort can reach every other.
+fn validate(protocols: &[Protocol]) -> Result<(), String> {
+    let runs = |protocol| protocols.contains(&protocol);
+    if runs(Protocol::Push) && runs(Protocol::Pull) && !runs(Protocol::PushPull) {
+        return Err(
+            "the votes of pull nodes never reach push nodes without push-pull nodes to relay them"
+                .to_string(),
+        );
+    }
+    Ok(())
+}
+
+/// Parse a cohorts spec for `n` nodes, in the format described in the module docs, into the
+/// protocol of every node.
+pub fn parse_cohorts(spec: &str, n: usize) -> Result<Vec<Protocol>, String> {
+    if let Some(filename) = spec.trim().strip_prefix("file:") {
+        let protocols = read_protocols(filename)?;
+        if protocols.len() != n {
+            let count = protocols.len();
+            return Err(format!("got {} protocols in {} for {} nodes", count, filename, n));
+        }
+        validate(&protocols)?;
+        return Ok(protocols);
+    }
+
+    // Shuffled by node ID, so that the same nodes join the same cohorts in every run.
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by_key(|&node| splitmix64(&mut (node as u64)));
+    let mut protocols = vec![Protocol::PushPull; n];
+    let mut assigned = 0;
+    for cohort in spec.split('+') {
+        let (protocol, fraction) = cohort
+            .trim()
+            .split_once(':')
+            .ok_or_else(|| format!("cohort {:?} should be <protocol>:<fraction>", cohort))?;
+        let protocol: Protocol = protocol.parse()?;
+        let fraction: f64 = fraction
+            .parse()
+            .map_err(|_| format!("invalid fraction in cohort {:?}", cohort))?;
+        if !(0.0..=1.0).contains(&fraction) {
+            return Err(format!("cohort fraction {} should be in [0, 1]", fraction));
+        }
+        let size = (fraction * n as f64).round() as usize;
+        let nodes = order
+            .get(assigned..assigned + size)
+            .ok_or_else(|| format!("the fractions of cohorts {:?} add up to more than 1", spec))?;
+        for &node in nodes {
+            protocols[node] = protocol;
+        }
+        assigned += size;
+    }
+    validate(&protocols)?;
+    Ok(protocols)
+}
+
+/// The protocols of the nodes of a run, and the round each node first had a quorum in.
+pub struct Cohorts {
+    protocols: Vec<Protocol>,
+    quorum_rounds: Vec<Option<usize>>,
+}
+
+impl Cohorts {
+    /// The cohorts of `params`, if they have any.
+    pub fn new(params: &Params) -> Result<Option<Self>, String> {
+        let Some(spec) = &params.cohorts else {
+            return Ok(None);
+        };
+        Ok(Some(Cohorts {
+            protocols: parse_cohorts(spec, params.n)?,
+            quorum_rounds: vec![None; params.n],
+        }))
+    }
+
+    /// Mark the messages that the nodes' protocols don't send in `unsent`, in the order that
+    /// `is_lost` looks them up.
+    pub fn mark_unsent(&self, unsent: &mut Vec<bool>) {
+        unsent.resize(2 * self.protocols.len(), false);
+        for (node, protocol) in self.protocols.iter().enumerate() {
+            for direction in 0..2 {
+                unsent[2 * node + direction] |= !protocol.sends(direction);
+            }
+        }
+    }
+
+    /// Record which nodes have a quorum after `rounds` rounds.
+    pub fn record(&mut self, rounds: usize, has_quorum: impl Fn(usize) -> bool) {
+        for (node, round) in self.quorum_rounds.iter_mut().enumerate() {
+            if round.is_none() && has_quorum(node) {
+                *round = Some(rounds);
+            }
+        }
+    }
+
+    /// The rounds until every node running `protocol` had a quorum, and their mean rounds, unless
+    /// none run it or some don't have a quorum yet.
+    fn rounds(&self, protocol: Protocol) -> (Option<f64>, Option<f64>) {
+        let rounds: Option<Vec<usize>> = self
+            .protocols
+            .iter()
+            .zip(&self.quorum_rounds)
+            .filter(|&(&node_protocol, _)| node_protocol == protocol)
+            .map(|(_, round)| *round)
+            .collect();
+        match rounds {
+            Some(rounds) if !rounds.is_empty() => (
+                rounds.iter().max().map(|&max| max as f64),
+                Some(rounds.iter().sum::<usize>() as f64 / rounds.len() as f64),
+            ),
+            _ => (None, None),
+        }
+    }
+
+    /// `result` with the convergence of every cohort filled in.
+    pub fn apply_to(&self, result: SimulationResult) -> SimulationResult {
+        let [push_pull, push, pull] = Protocol::ALL.map(|protocol| self.rounds(protocol));
+        SimulationResult {
+            push_pull_quorum_rounds: push_pull.0,
+            push_pull_mean_quorum_rounds: push_pull.1,
+            push_quorum_rounds: push.0,
+            push_mean_quorum_rounds: push.1,
+            pull_quorum_rounds: pull.0,
+            pull_mean_quorum_rounds: pull.1,
+            ..result
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(protocols: &[Protocol], protocol: Protocol) -> usize {
+        protocols.iter().filter(|&&p| p == protocol).count()
+    }
+
+    /// Whether the votes of a node running `from` reach a partner running `to`, in an exchange
+    /// started by either of them.
+    fn reach(from: Protocol, to: Protocol) -> bool {
+        from.sends(1) || to.sends(0)
+    }
+
+    #[test]
+    fn parses_fractions() {
+        let protocols = parse_cohorts("push:0.1+pull:0.1", 100).unwrap();
+        assert_eq!(count(&protocols, Protocol::Push), 10);
+        assert_eq!(count(&protocols, Protocol::Pull), 10);
+        assert_eq!(count(&protocols, Protocol::PushPull), 80);
+        // The same nodes every time, but not just the first ones.
+        assert_eq!(parse_cohorts("push:0.1+pull:0.1", 100).unwrap(), protocols);
+        assert!(protocols[..20].contains(&Protocol::PushPull));
+        // Fractions round to whole nodes, and may cover every node.
+        assert_eq!(count(&parse_cohorts("pull:0.25", 10).unwrap(), Protocol::Pull), 3);
+        assert_eq!(count(&parse_cohorts("push:0.5+push-pull:0.5", 10).unwrap(), Protocol::Push), 5);
+        assert_eq!(parse_cohorts("pull:1", 4).unwrap(), vec![Protocol::Pull; 4]);
+    }
+
+    #[test]
+    fn reads_files() {
+        let name = format!("gossip-cohorts-{}.txt", std::process::id());
+        let path = std::env::temp_dir().join(name);
+        fs::write(&path, "push\n\npull\n push-pull\n").unwrap();
+        let spec = format!("file:{}", path.display());
+        let protocols = parse_cohorts(&spec, 3).unwrap();
+        assert_eq!(protocols, vec![Protocol::Push, Protocol::Pull, Protocol::PushPull]);
+        let e = parse_cohorts(&spec, 4).err().unwrap();
+        assert!(e.contains("got 3 protocols"), "{}", e);
+
+        fs::write(&path, "push\npull\n").unwrap();
+        let e = parse_cohorts(&spec, 2).err().unwrap();
+        assert!(e.contains("never reach push nodes"), "{}", e);
+        fs::write(&path, "push\nsideways\n").unwrap();
+        let e = parse_cohorts(&spec, 2).err().unwrap();
+        assert!(e.contains("unknown protocol \"sideways\""), "{}", e);
+        fs::remove_file(&path).unwrap();
+
+        let e = parse_cohorts(&spec, 2).err().unwrap();
+        assert!(e.contains("failed to read cohorts file"), "{}", e);
+    }
+
+    #[test]
+    fn rejects_malformed_specs() {
+        for (spec, error) in [
+            ("push", "should be <protocol>:<fraction>"),
+            ("sideways:0.1", "unknown protocol"),
+            ("push:", "invalid fraction"),
+            ("push:-0.1", "should be in [0, 1]"),
+            ("push:1.5", "should be in [0, 1]"),
+            ("push:0.6+pull:0.6", "add up to more than 1"),
+            ("push:0.5+pull:0.5", "never reach push nodes"),
+        ] {
+            let e = parse_cohorts(spec, 10).err().unwrap();
+            assert!(e.contains(error), "{:?} gave {:?}, expected {:?}", spec, e, error);
+        }
+    }
+
+    #[test]
+    fn push_and_pull_nodes_only_pass_votes_one_way() {
+        // A push node sends its votes to a pull node in an exchange it starts, but a pull node
+        // starting one only asks for the push node's votes, so a pull node's never reach it.
+        assert!(reach(Protocol::Push, Protocol::Pull));
+        assert!(!reach(Protocol::Pull, Protocol::Push));
+        for from in Protocol::ALL {
+            for to in Protocol::ALL {
+                let relayed = from == Protocol::PushPull || to == Protocol::PushPull;
+                assert!(!relayed || reach(from, to), "{:?} to {:?}", from, to);
+            }
+        }
+
+        let cohorts = "push:0.34+pull:0.34";
+        let params = serde_json::json!({"n": 3, "k": 1, "voting_steps": 1, "cohorts": cohorts});
+        let params: Params = serde_json::from_value(params).unwrap();
+        let cohorts = Cohorts::new(&params).unwrap().unwrap();
+        let mut unsent = vec![];
+        cohorts.mark_unsent(&mut unsent);
+        for (node, protocol) in cohorts.protocols.iter().enumerate() {
+            assert_eq!(unsent[2 * node], !protocol.sends(0));
+            assert_eq!(unsent[2 * node + 1], !protocol.sends(1));
+        }
+        assert_eq!(unsent.iter().filter(|&&unsent| unsent).count(), 2);
+    }
+}
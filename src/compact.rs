@@ -0,0 +1,340 @@
+//! A compact simulation mode with the nodes stored as a struct of arrays.
+//!
+//! The general simulation gives every node its own map of votes, each with a voter set that has
+//! room for the whole network, plus per-round update sets on top. For the single rumour that the
+//! simulation actually votes on, all of that can be replaced by flat arrays: a voter set, a `u32`
+//! vote count and a partner per node. Keeping only those is much cheaper than a `Node` each, so
+//! `simulate_params` uses this mode for every set of parameters that it `supports`. It gives
+//! exactly the same results as the general simulation for the same seed.
+//!
+//! Each node's voters are a `VoterSet`, which holds up to 8 voters inline and then switches to a
+//! bitset of `k` bits (or with the `roaring` feature, a Roaring bitmap), since the voters are
+//! always nodes `0..k`. Sets are shared copy-on-write: a node sent all of a partner's voters
+//! shares the partner's set, and a node that doesn't change in a round shares its set between
+//! the round's start and end, so only the nodes that change have a second copy while a round
+//! runs. Each node holding more than 8 voters takes about `k / 8` bytes, or twice that while it
+//! changes, and at the end of a run every node holds at least a quorum of voters. The peak memory
+//! use of a run of a few sizes is:
+//!
+//! | `n`       | `k`       | `quorum`     | memory  |
+//! |-----------|-----------|--------------|---------|
+//! | 1,000,000 | 1,000     | `count:667`  | 0.5 GB  |
+//! | 1,000,000 | 10,000    | `count:6667` | 2.7 GB  |
+//! | 100,000   | 60,000    | majority     | 1.5 GB  |
+//! | 1,000,000 | 510,000   | majority     | > 64 GB |
+//!
+//! A majority of a million nodes needs more than 500,000 voters, so the last row is too big for
+//! most machines however the nodes are stored: a million-node run needs a quorum rule that is
+//! met by a few thousand voters, like the committees of the `eth-attestation` preset.
+//!
+//! Statistics are accumulated as the simulation runs rather than kept per node. This mode only
+//! supports the default strategy (with either partner mode, subnets or mobility), without a
+//! membership layer, and quorum rules that depend only on the number of voters.
+
+use rand::Rng;
+use std::error::Error;
+use std::mem;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::cohort::Cohorts;
+use crate::cost::Costs;
+use crate::mobility::Mobility;
+use crate::rate_limit::RateLimiter;
+use crate::subnet::SubnetStats;
+use crate::voters::VoterSet;
+use crate::{
+    average_results, choose_losses, construct_voting_schedule, is_lost, load_quorum_rule,
+    load_strategy, num_node_ids, Milestones, NodeId, Params, PhaseTimes, RoundMetrics,
+    SimulationMode, SimulationResult,
+};
+
+/// Whether `params` can be simulated in compact mode.
+pub fn supports(params: &Params) -> bool {
+    params.script.is_none()
+        && params.membership.is_none()
+        && params.election.is_none()
+        && num_node_ids(params.n).is_ok()
+        && load_quorum_rule(params).is_ok_and(|quorum| quorum.min_voters(params.n).is_some())
+}
+
+/// Run `params` for `repetitions` runs in compact mode and average the results.
+pub fn simulate_params<R: Rng>(
+    params: &Params,
+    repetitions: usize,
+    rng: &mut R,
+    on_round: &mut dyn FnMut(&RoundMetrics),
+) -> Result<SimulationResult, Box<dyn Error>> {
+    let sim_results = simulate_runs(params, repetitions, rng, on_round)?;
+    Ok(average_results(params, sim_results))
+}
+
+/// Run `params` for `repetitions` runs in compact mode, returning the result of every run.
+pub fn simulate_runs<R: Rng>(
+    params: &Params,
+    repetitions: usize,
+    rng: &mut R,
+    on_round: &mut dyn FnMut(&RoundMetrics),
+) -> Result<Vec<SimulationResult>, Box<dyn Error>> {
+    (0..repetitions)
+        .map(|repetition| {
+            let result = run_simulation(params, rng, &mut |metrics: &RoundMetrics| {
+                on_round(&RoundMetrics { repetition, ..metrics.clone() })
+            })?;
+            let mode = Some(SimulationMode::Compact);
+            Ok(SimulationResult { mode, ..result }.with_predictions())
+        })
+        .collect()
+}
+
+/// Run a single simulation of `params` in compact mode.
+pub fn run_simulation<R: Rng>(
+    params: &Params,
+    rng: &mut R,
+    on_round: &mut dyn FnMut(&RoundMetrics),
+) -> Result<SimulationResult, Box<dyn Error>> {
+    let n = params.n;
+    let k = params.k;
+    num_node_ids(n)?;
+    if params.script.is_some() {
+        return Err(From::from("compact mode doesn't support strategy scripts"));
+    }
+    let quorum = load_quorum_rule(params)?;
+    let min_voters = quorum.min_voters(n).ok_or_else(|| {
+        format!("compact mode needs a quorum rule that only counts voters, not {:?}", quorum)
+    })? as u32;
+    let strategy = load_strategy(params, rng)?;
+    let voting_schedule = construct_voting_schedule(k, params.voting_steps);
+
+    // Every node starts out sharing one empty set, which has room for the `k` voters.
+    let empty = Arc::new(VoterSet::new(k));
+    let mut current = vec![empty; n];
+    let mut next: Vec<Arc<VoterSet>> = Vec::with_capacity(n);
+    // Number of voters each node knows of.
+    let mut counts = vec![0u32; n];
+    let mut partners = Vec::with_capacity(n);
+    let mut lost = vec![];
+    let mut unsent = vec![];
+    let mut rate_limiter = RateLimiter::new(params);
+    let mut costs = Costs::new(params)?;
+    let mut cohorts = Cohorts::new(params)?;
+    let mut subnets = SubnetStats::new(params)?;
+    let mut mobility = Mobility::new(params)?;
+
+    let mut num_iterations = 0;
+    let mut num_exchanges = 0;
+    let mut num_vote_exchanges = 0;
+    let mut nodes_with_quorum = 0;
+    let mut milestones = Milestones::default();
+    let mut next_voter = 0;
+
+    while nodes_with_quorum < n && Some(num_iterations) != params.max_rounds {
+        // The first `k` nodes vote in order, as in the general simulation.
+        let num_voters = voting_schedule.get(&num_iterations).cloned().unwrap_or(0);
+        for voter in next_voter..next_voter + num_voters {
+            Arc::make_mut(&mut current[voter]).insert(voter as NodeId);
+            counts[voter] += 1;
+        }
+        next_voter += num_voters;
+
+        let mut times = PhaseTimes::default();
+        let start = Instant::now();
+        strategy.choose_partners(n, num_iterations, rng, &mut partners)?;
+        if let Some(mobility) = &mut mobility {
+            mobility.choose_partners(&mut partners, rng);
+        }
+        if let Some(limiter) = &rate_limiter {
+            limiter.retry_deferred(&mut partners);
+        }
+        if partners.len() != n {
+            return Err(From::from(format!(
+                "chose {} gossip partners for {} nodes", partners.len(), n
+            )));
+        }
+        for (node, &partner) in partners.iter().enumerate() {
+            if partner as usize >= n || partner as usize == node {
+                return Err(From::from(format!(
+                    "node {} chose invalid gossip partner {}", node, partner
+                )));
+            }
+        }
+        if let Some(subnets) = &mut subnets {
+            subnets.record_partners(&partners);
+        }
+        choose_losses(params.loss, n, rng, &mut lost);
+        unsent.clear();
+        if let Some(cohorts) = &cohorts {
+            cohorts.mark_unsent(&mut unsent);
+        }
+        if let Some(mobility) = &mobility {
+            mobility.mark_unsent(&mut unsent);
+        }
+        if let Some(limiter) = &mut rate_limiter {
+            limiter.limit(&partners, rng, &mut lost, &mut unsent);
+        }
+        times.gossip += start.elapsed().as_secs_f64();
+
+        // Every node starts the round sharing its current voters, and only gets a copy of its
+        // own once it's sent more.
+        let start = Instant::now();
+        next.clone_from(&current);
+        times.apply += start.elapsed().as_secs_f64();
+
+        let start = Instant::now();
+        let mut round_exchanges = 0;
+        let mut round_vote_exchanges = 0;
+        for (node, &partner) in partners.iter().enumerate() {
+            let partner = partner as usize;
+            // Push-pull: we receive the partner's votes, and they receive ours.
+            let messages = [(partner, node), (node, partner)];
+            for (direction, (sender, receiver)) in messages.into_iter().enumerate() {
+                if counts[receiver] >= min_voters || is_lost(&unsent, node, direction) {
+                    continue;
+                }
+                let missing = current[sender].difference(&current[receiver]).len();
+                if missing > 0 {
+                    round_exchanges += 1;
+                    round_vote_exchanges += missing;
+                    if let Some(costs) = &mut costs {
+                        costs.add(sender, receiver, missing);
+                    }
+                    if is_lost(&lost, node, direction) {
+                        continue;
+                    }
+                    // A node with no voters yet is sent all of the sender's, so can share them,
+                    // unless it's been sent others this round already.
+                    let unchanged = Arc::ptr_eq(&next[receiver], &current[receiver]);
+                    if current[receiver].is_empty() && unchanged {
+                        next[receiver] = current[sender].clone();
+                    } else {
+                        Arc::make_mut(&mut next[receiver]).union_with(&current[sender]);
+                    }
+                }
+            }
+        }
+        times.gossip += start.elapsed().as_secs_f64();
+
+        // Exchanges are added to `next` as they happen, so there's nothing to merge.
+        let start = Instant::now();
+        for (node, count) in counts.iter_mut().enumerate() {
+            if *count < min_voters {
+                *count = next[node].len() as u32;
+            }
+        }
+        times.apply += start.elapsed().as_secs_f64();
+
+        let start = Instant::now();
+        nodes_with_quorum = counts.iter().filter(|&&count| count >= min_voters).count();
+        times.convergence += start.elapsed().as_secs_f64();
+        let with_all_voters = counts.iter().filter(|&&count| count as usize == k).count();
+        milestones.record(n, num_iterations + 1, nodes_with_quorum, with_all_voters);
+        if let Some(cohorts) = &mut cohorts {
+            cohorts.record(num_iterations + 1, |node| counts[node] >= min_voters);
+        }
+        if let Some(subnets) = &mut subnets {
+            subnets.record(num_iterations + 1, |node| counts[node] >= min_voters);
+        }
+        // Drop the sets from the start of the round, so that nodes only they shared with can be
+        // changed in place next round.
+        mem::swap(&mut current, &mut next);
+        next.clear();
+
+        num_exchanges += round_exchanges;
+        num_vote_exchanges += round_vote_exchanges;
+        on_round(&RoundMetrics {
+            repetition: 0,
+            round: num_iterations,
+            nodes_with_quorum,
+            num_exchanges: round_exchanges,
+            num_vote_exchanges: round_vote_exchanges,
+            times: params.timings.then_some(times),
+        });
+
+        num_iterations += 1;
+    }
+
+    let total_votes_held: u64 = counts.iter().map(|&count| count as u64).sum();
+
+    let result = milestones.apply_to(SimulationResult {
+        num_iterations: num_iterations as f64,
+        num_exchanges,
+        num_vote_exchanges,
+        average_votes_held: total_votes_held as f64 / n as f64,
+        missed_deadline: if nodes_with_quorum < n { 1.0 } else { 0.0 },
+        num_rate_limited: rate_limiter.map_or(0, |limiter| limiter.num_limited),
+        ..SimulationResult::for_params(params)
+    });
+    let result = match &costs {
+        Some(costs) => costs.apply_to(result),
+        None => result,
+    };
+    let result = match &cohorts {
+        Some(cohorts) => cohorts.apply_to(result),
+        None => result,
+    };
+    let result = match &subnets {
+        Some(subnets) => subnets.apply_to(result),
+        None => result,
+    };
+    Ok(match &mobility {
+        Some(mobility) => mobility.apply_to(result),
+        None => result,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::seeded_rng;
+    use serde_json::{json, Value};
+
+    /// 100 nodes with 70 voters, and the parameters in `extra`.
+    fn params(extra: Value) -> Params {
+        let mut params = json!({"n": 100, "k": 70, "voting_steps": 3});
+        params.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(params).unwrap()
+    }
+
+    /// The results of two runs of `params` in `mode` from the same seed, without their `mode`.
+    fn results(params: &Params, mode: SimulationMode) -> Vec<Value> {
+        let params = Params { mode: Some(mode), ..params.clone() };
+        let runs = crate::simulate_runs(&params, 2, &mut seeded_rng(7), &mut |_| {}).unwrap();
+        runs.into_iter()
+            .map(|result| {
+                let mut result = serde_json::to_value(result).unwrap();
+                result.as_object_mut().unwrap().remove("mode");
+                result
+            })
+            .collect()
+    }
+
+    #[test]
+    fn matches_the_general_simulation() {
+        for extra in [
+            json!({}),
+            json!({"partners": "matched"}),
+            json!({"loss": 0.2}),
+            json!({"quorum": "count:20"}),
+            json!({"quorum": "threshold:0.6"}),
+            json!({"max_messages_per_round": 2}),
+            json!({"max_messages_per_round": 2, "rate_limit_policy": "defer"}),
+            json!({"cost": "1:0.01:3:5:0.05"}),
+            json!({"cohorts": "push:0.1+pull:0.1"}),
+            json!({"subnets": "4:0.1"}),
+            json!({"max_rounds": 3}),
+            json!({"mobility": "waypoint:0.3:0.05", "max_rounds": 50}),
+        ] {
+            let params = params(extra.clone());
+            assert!(supports(&params), "{}", extra);
+            let compact = results(&params, SimulationMode::Compact);
+            assert_eq!(compact, results(&params, SimulationMode::General), "{}", extra);
+        }
+    }
+
+    #[test]
+    fn supports_only_quorums_of_a_number_of_voters() {
+        assert!(!supports(&params(json!({"membership": "0.1:5"}))));
+        assert!(!supports(&params(json!({"election": "max"}))));
+        assert!(!supports(&params(json!({"quorum": "regions:3:2"}))));
+    }
+}
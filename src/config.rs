@@ -0,0 +1,398 @@
+//! Structured config files, as an alternative to a parameters CSV.
+//!
+//! A config file lists the parameter sets to run along with the options for running them, in
+//! TOML or YAML depending on its extension:
+//!
+//! ```toml
+//! seed = 42
+//! repetitions = 10
+//!
+//! [output]
+//! path = "results.csv"
+//! format = "csv"
+//!
+//! # Fields shared by every run, unless the run sets them itself.
+//! [defaults]
+//! voting_steps = 3
+//! partners = "matched"
+//!
+//! [[runs]]
+//! n = 1000
+//! k = 700
+//!
+//! [[runs]]
+//! n = 1000
+//! k = 700
+//! quorum = "threshold:0.67"
+//! ```
+//!
+//! Runs have the same fields as the columns of a parameters CSV, and any other field is an error,
+//! so that a misspelt field isn't silently ignored.
+//!
+//! Any field can be a list to sweep over, and the counts `n`, `k`, `voting_steps`,
+//! `max_messages_per_round` and `max_rounds` can also be ranges like `"100..1000 step 100"`
+//! (which includes 1000). `k` can be a percentage of `n`, alone or in a range, like
+//! `"50%..90% step 10%"`. A run with lists or ranges is expanded into every combination of their
+//! values, with the later fields in `PARAM_FIELDS` varying fastest, and combinations that can't
+//! run are skipped:
+//!
+//! ```toml
+//! [[runs]]
+//! n = [100, 1000, 10000]
+//! k = "50%..90% step 10%"
+//! voting_steps = "1..3"
+//! ```
+
+use serde_json::{Map, Value};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use tracing::warn;
+
+use crate::{read_param_rows, validate_params, OutputFormat, Params, PARAM_FIELDS};
+
+/// Fields that take counts, which can be given as ranges.
+pub const NUMERIC_FIELDS: &[&str] =
+    &["n", "k", "voting_steps", "max_messages_per_round", "max_rounds"];
+
+/// The parameter sets and options from a config file or parameters CSV.
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    /// Seed for the random number generator.
+    pub seed: Option<u64>,
+    /// Number of runs to average for each parameter set.
+    pub repetitions: Option<usize>,
+    pub output: OutputConfig,
+    pub runs: Vec<Params>,
+}
+
+/// Where and how to write the results.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputConfig {
+    pub path: Option<String>,
+    pub format: Option<OutputFormat>,
+}
+
+/// A config file as written, before the defaults are applied to each run.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawConfig {
+    #[serde(default)]
+    seed: Option<u64>,
+    #[serde(default)]
+    repetitions: Option<usize>,
+    #[serde(default)]
+    output: OutputConfig,
+    #[serde(default)]
+    defaults: Map<String, Value>,
+    runs: Vec<Map<String, Value>>,
+}
+
+/// Whether `filename` is a config file rather than a parameters CSV, going by its extension.
+pub fn is_config_file(filename: &str) -> bool {
+    matches!(
+        Path::new(filename).extension().and_then(|ext| ext.to_str()),
+        Some("toml" | "yaml" | "yml")
+    )
+}
+
+/// Load a TOML or YAML config file, or a parameters CSV with no other options. Stdin (`-`) is
+/// read as a CSV.
+pub fn load(filename: &str) -> Result<Config, Box<dyn Error>> {
+    read(filename, false)
+}
+
+/// Like `load`, but skip the rows of a CSV or runs of a config file that can't be read, reporting
+/// them on stderr.
+pub fn load_skipping_invalid(filename: &str) -> Result<Config, Box<dyn Error>> {
+    read(filename, true)
+}
+
+fn read(filename: &str, skip_invalid: bool) -> Result<Config, Box<dyn Error>> {
+    if !is_config_file(filename) {
+        let mut runs = vec![];
+        for row in read_param_rows(filename)? {
+            match row {
+                Ok(params) => runs.push(params),
+                Err(e) if skip_invalid => warn!("skipping {}", e),
+                Err(e) => return Err(From::from(e)),
+            }
+        }
+        return Ok(Config { runs, ..Config::default() });
+    }
+    let contents =
+        fs::read_to_string(filename).map_err(|e| format!("failed to read {}: {}", filename, e))?;
+    let raw: RawConfig = if filename.ends_with(".toml") {
+        toml::from_str(&contents).map_err(|e| format!("invalid config {}: {}", filename, e))?
+    } else {
+        serde_yaml::from_str(&contents).map_err(|e| format!("invalid config {}: {}", filename, e))?
+    };
+
+    let mut runs = vec![];
+    for (i, run) in raw.runs.into_iter().enumerate() {
+        let mut fields = raw.defaults.clone();
+        fields.extend(run);
+        match expand_run(&fields) {
+            Ok(expanded) => runs.extend(expanded),
+            Err(e) if skip_invalid => warn!("skipping run {} of {}: {}", i + 1, filename, e),
+            Err(e) => return Err(From::from(format!("run {} of {}: {}", i + 1, filename, e))),
+        }
+    }
+
+    Ok(Config { seed: raw.seed, repetitions: raw.repetitions, output: raw.output, runs })
+}
+
+/// A count, or a percentage of `n`.
+#[derive(Clone, Copy)]
+enum Number {
+    Count(u64),
+    Percent(f64),
+}
+
+impl Number {
+    fn parse(field: &str, spec: &str) -> Result<Number, String> {
+        let number = match spec.strip_suffix('%') {
+            Some(_) if field != "k" => {
+                return Err(format!("only k can be a percentage of n, not {}", field));
+            }
+            Some(percent) => percent.trim().parse().ok().map(Number::Percent),
+            None => spec.parse().ok().map(Number::Count),
+        };
+        number.ok_or_else(|| format!("invalid {} {:?}", field, spec))
+    }
+
+    /// The number as a field value, with percentages left as strings until `n` is known.
+    fn into_value(self) -> Value {
+        match self {
+            Number::Count(count) => Value::from(count),
+            Number::Percent(percent) => Value::from(format!("{}%", percent)),
+        }
+    }
+}
+
+/// Parse a number, or a range of them like `100..1000 step 100`, into the values it covers. The
+/// step defaults to 1, or 1% for percentages.
+fn parse_numbers(field: &str, spec: &str) -> Result<Vec<Value>, String> {
+    let (range, step) = match spec.split_once(" step ") {
+        Some((range, step)) => (range, Some(step.trim())),
+        None => (spec, None),
+    };
+    let (start, end) = match range.split_once("..") {
+        Some((start, end)) => (start.trim(), end.trim()),
+        None if step.is_none() => return Ok(vec![Number::parse(field, spec.trim())?.into_value()]),
+        None => return Err(format!("invalid range {:?} for {}", spec, field)),
+    };
+    let start = Number::parse(field, start)?;
+    let end = Number::parse(field, end)?;
+    let step = match step {
+        Some(step) => Number::parse(field, step)?,
+        None if matches!(start, Number::Percent(_)) => Number::Percent(1.0),
+        None => Number::Count(1),
+    };
+
+    match (start, end, step) {
+        (Number::Count(_), Number::Count(_), Number::Count(0)) => {
+            Err(format!("range {:?} for {} needs a positive step", spec, field))
+        }
+        (Number::Count(start), Number::Count(end), Number::Count(step)) => {
+            Ok((start..=end).step_by(step as usize).map(Value::from).collect())
+        }
+        (Number::Percent(_), Number::Percent(_), Number::Percent(step)) if step <= 0.0 => {
+            Err(format!("range {:?} for {} needs a positive step", spec, field))
+        }
+        (Number::Percent(start), Number::Percent(end), Number::Percent(step)) => {
+            // Multiplying rather than adding up the steps, so that they don't drift past `end`.
+            let num_values = if end < start { 0 } else { ((end - start) / step + 1e-9) as u64 + 1 };
+            Ok((0..num_values)
+                .map(|i| Number::Percent(start + i as f64 * step).into_value())
+                .collect())
+        }
+        _ => Err(format!("range {:?} for {} mixes counts and percentages", spec, field)),
+    }
+}
+
+/// Every value of a field: the elements of a list, the numbers in a range, or just the value.
+fn field_values(field: &str, value: &Value) -> Result<Vec<Value>, String> {
+    match value {
+        Value::Array(values) => {
+            let mut all_values = vec![];
+            for value in values {
+                all_values.extend(field_values(field, value)?);
+            }
+            Ok(all_values)
+        }
+        Value::String(spec) if NUMERIC_FIELDS.contains(&field) => parse_numbers(field, spec),
+        _ => Ok(vec![value.clone()]),
+    }
+}
+
+/// Replace a percentage `k` with that percentage of `n`, rounded to the nearest node.
+fn resolve_percentage(fields: &mut Map<String, Value>) -> Result<(), String> {
+    let percent = match fields.get("k").and_then(Value::as_str).and_then(|k| k.strip_suffix('%')) {
+        Some(percent) => percent.parse::<f64>().map_err(|e| e.to_string())?,
+        None => return Ok(()),
+    };
+    let n = fields.get("n").and_then(Value::as_u64).ok_or("k is a percentage of n, so needs n")?;
+    fields.insert("k".into(), Value::from((n as f64 * percent / 100.0).round() as u64));
+    Ok(())
+}
+
+/// Expand the lists and ranges in the fields of a run into the parameter sets they cover, as
+/// described in the module docs. A run without any is a single parameter set, and is returned
+/// even if it can't run, so that the error isn't lost.
+pub fn expand_run(fields: &Map<String, Value>) -> Result<Vec<Params>, String> {
+    if let Some(field) = fields.keys().find(|field| !PARAM_FIELDS.contains(&field.as_str())) {
+        return Err(format!("unknown field {:?}", field));
+    }
+
+    let mut combinations = vec![Map::new()];
+    for &field in PARAM_FIELDS {
+        let values = match fields.get(field) {
+            Some(value) => field_values(field, value)?,
+            None => continue,
+        };
+        combinations = combinations
+            .iter()
+            .flat_map(|combination| {
+                values.iter().map(move |value| {
+                    let mut combination = combination.clone();
+                    combination.insert(field.into(), value.clone());
+                    combination
+                })
+            })
+            .collect();
+    }
+
+    let sweep = combinations.len() > 1;
+    let mut all_params = vec![];
+    for mut combination in combinations {
+        resolve_percentage(&mut combination)?;
+        let description = PARAM_FIELDS
+            .iter()
+            .filter_map(|&field| Some((field, combination.get(field)?)))
+            .map(|(field, value)| match value {
+                Value::String(value) => format!("{} = {}", field, value),
+                _ => format!("{} = {}", field, value),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let params: Params =
+            serde_json::from_value(Value::Object(combination)).map_err(|e| e.to_string())?;
+        match validate_params(&params) {
+            Err(e) if sweep => warn!("skipping {}: {}", description, e),
+            _ => all_params.push(params),
+        }
+    }
+    Ok(all_params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn run(fields: Value) -> Result<Vec<Params>, String> {
+        match fields {
+            Value::Object(fields) => expand_run(&fields),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn expands_ranges_of_counts() {
+        assert_eq!(parse_numbers("n", "7").unwrap(), vec![json!(7)]);
+        assert_eq!(parse_numbers("n", "1..3").unwrap(), vec![json!(1), json!(2), json!(3)]);
+        assert_eq!(
+            parse_numbers("n", "100..1000 step 300").unwrap(),
+            vec![json!(100), json!(400), json!(700), json!(1000)]
+        );
+        assert_eq!(
+            parse_numbers("n", " 100 .. 250 step 100 ").unwrap(),
+            vec![json!(100), json!(200)]
+        );
+        assert!(parse_numbers("n", "5..1").unwrap().is_empty());
+        assert_eq!(parse_numbers("max_rounds", "10..20 step 5").unwrap().len(), 3);
+    }
+
+    #[test]
+    fn expands_ranges_of_percentages() {
+        assert_eq!(parse_numbers("k", "50%").unwrap(), vec![json!("50%")]);
+        assert_eq!(
+            parse_numbers("k", "50%..52%").unwrap(),
+            vec![json!("50%"), json!("51%"), json!("52%")]
+        );
+        // Steps that don't add up exactly in floating point still reach the end.
+        assert_eq!(parse_numbers("k", "60%..90% step 10%").unwrap().len(), 4);
+        assert_eq!(parse_numbers("k", "0.1%..0.3% step 0.1%").unwrap().len(), 3);
+    }
+
+    #[test]
+    fn rejects_bad_ranges() {
+        for (field, spec, error) in [
+            ("n", "1..3 step 0", "needs a positive step"),
+            ("k", "50%..60% step 0%", "needs a positive step"),
+            ("n", "50%", "only k can be a percentage"),
+            ("k", "1..50%", "mixes counts and percentages"),
+            ("k", "50%..60% step 1", "mixes counts and percentages"),
+            ("n", "1 step 2", "invalid range"),
+            ("n", "1..x", "invalid n"),
+            ("voting_steps", "-1", "invalid voting_steps"),
+        ] {
+            let e = parse_numbers(field, spec).unwrap_err();
+            assert!(e.contains(error), "{} = {:?} gave {:?}, expected {:?}", field, spec, e, error);
+        }
+    }
+
+    #[test]
+    fn expands_every_combination_with_later_fields_fastest() {
+        let runs = run(json!({
+            "n": [100, 200],
+            "k": "60%..80% step 10%",
+            "voting_steps": "1..2",
+            "quorum": ["majority", "threshold:0.55"],
+        }))
+        .unwrap();
+        let described: Vec<_> = runs
+            .iter()
+            .map(|params| (params.n, params.k, params.voting_steps, params.quorum.clone().unwrap()))
+            .collect();
+        assert_eq!(described.len(), 24);
+        assert_eq!(described[0], (100, 60, 1, "majority".to_string()));
+        assert_eq!(described[1], (100, 60, 1, "threshold:0.55".to_string()));
+        assert_eq!(described[2], (100, 60, 2, "majority".to_string()));
+        assert_eq!(described[4], (100, 70, 1, "majority".to_string()));
+        // Percentages are of each run's own n.
+        assert_eq!(described[12], (200, 120, 1, "majority".to_string()));
+        assert_eq!(described[23], (200, 160, 2, "threshold:0.55".to_string()));
+    }
+
+    #[test]
+    fn nested_lists_and_ranges_are_flattened() {
+        let runs = run(json!({ "n": [10, "20..22", [30]], "k": "100%", "voting_steps": 1 }));
+        let runs = runs.unwrap();
+        let ns: Vec<_> = runs.iter().map(|params| params.n).collect();
+        assert_eq!(ns, vec![10, 20, 21, 22, 30]);
+        assert!(runs.iter().all(|params| params.k == params.n));
+    }
+
+    #[test]
+    fn skips_combinations_that_cant_run_only_in_sweeps() {
+        // No majority can be reached with k at half of n or less.
+        let runs = run(json!({ "n": 100, "k": "40%..60% step 10%", "voting_steps": 1 })).unwrap();
+        assert_eq!(runs.iter().map(|params| params.k).collect::<Vec<_>>(), vec![60]);
+
+        // A single run is kept, so validating it reports the error.
+        let runs = run(json!({ "n": 100, "k": 40, "voting_steps": 1 })).unwrap();
+        assert_eq!(runs.len(), 1);
+        assert!(validate_params(&runs[0]).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_fields_and_percentages_without_n() {
+        let e = run(json!({ "n": 100, "k": 60, "voting_step": 1 })).unwrap_err();
+        assert!(e.contains("unknown field \"voting_step\""), "{}", e);
+        let e = run(json!({ "k": "60%", "voting_steps": 1 })).unwrap_err();
+        assert!(e.contains("needs n"), "{}", e);
+    }
+}
@@ -0,0 +1,233 @@
+//! Prices for the messages that a run sends, so that protocols can be compared by what they cost
+//! an operator rather than by counts of messages.
+//!
+//! A row's `cost` column gives the prices, using one of these specs:
+//!
+//! * `<per message>:<per byte>`: every message costs the same, plus its size times the price per
+//!   byte.
+//! * `<per message>:<per byte>:<clusters>:<per message>:<per byte>`: node `i` is in cluster
+//!   `i % clusters`, as in the `regions` quorum rule, and messages between clusters cost the
+//!   second pair of prices.
+//!
+//! Either spec can end in `:aggregate` to price the messages as aggregates rather than lists of
+//! voters.
+//!
+//! A message carrying `v` voters is `daemon::encoded_size(1, v)` bytes, as the daemon encodes it,
+//! or as an aggregate, the same header followed by a bitfield of all `k` voters and one BLS
+//! signature standing for all of their votes, however many it has. Its sender pays for it, even
+//! if it's lost on the way.
+
+use crate::daemon::encoded_size;
+use crate::{Params, SimulationResult};
+
+/// Bytes of a BLS signature, compressed.
+pub const SIGNATURE_SIZE: usize = 96;
+
+/// Bytes of an aggregate of the votes of some of `num_voters` voters.
+pub fn aggregate_size(num_voters: usize) -> usize {
+    encoded_size(1, 0) + num_voters.div_ceil(8) + SIGNATURE_SIZE
+}
+
+/// The prices of the messages within and between clusters.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CostModel {
+    /// The prices per message and per byte within a cluster, and between clusters.
+    pub intra: (f64, f64),
+    pub inter: (f64, f64),
+    pub num_clusters: usize,
+    /// Whether messages are priced as aggregates.
+    pub aggregate: bool,
+}
+
+impl CostModel {
+    /// Bytes of a message carrying `voters` of `num_voters` voters.
+    pub fn message_size(&self, voters: usize, num_voters: usize) -> usize {
+        match self.aggregate {
+            true => aggregate_size(num_voters),
+            false => encoded_size(1, voters),
+        }
+    }
+
+    /// The price of a message of `size` bytes from `sender` to `receiver`.
+    pub fn message_cost(&self, sender: usize, receiver: usize, size: usize) -> f64 {
+        let same_cluster = sender % self.num_clusters == receiver % self.num_clusters;
+        let (per_message, per_byte) = if same_cluster { self.intra } else { self.inter };
+        per_message + per_byte * size as f64
+    }
+
+    /// Check that the clusters make sense for a network of `num_nodes` nodes.
+    pub fn validate(&self, num_nodes: usize) -> Result<(), String> {
+        if self.num_clusters == 0 || self.num_clusters > num_nodes {
+            return Err(format!(
+                "can't split {} nodes into {} clusters",
+                num_nodes, self.num_clusters
+            ));
+        }
+        let prices = [self.intra.0, self.intra.1, self.inter.0, self.inter.1];
+        if prices.iter().any(|&price| price.is_nan() || price < 0.0) {
+            return Err("message and byte costs can't be negative".to_string());
+        }
+        Ok(())
+    }
+}
+
+fn parse_price(field: &str, name: &str, spec: &str) -> Result<f64, String> {
+    field.parse().map_err(|_| format!("invalid {} in cost {:?}", name, spec))
+}
+
+/// Parse a cost model from a spec, in the format described in the module docs.
+pub fn parse_cost_model(spec: &str) -> Result<CostModel, String> {
+    let mut fields: Vec<&str> = spec.trim().split(':').collect();
+    let aggregate = fields.last() == Some(&"aggregate");
+    if aggregate {
+        fields.pop();
+    }
+    let intra = match fields[..] {
+        [message, byte, ..] => (
+            parse_price(message, "cost per message", spec)?,
+            parse_price(byte, "cost per byte", spec)?,
+        ),
+        _ => return Err(format!("cost {:?} needs a cost per message and per byte", spec)),
+    };
+    match fields[2..] {
+        [] => Ok(CostModel { intra, inter: intra, num_clusters: 1, aggregate }),
+        [clusters, message, byte] => Ok(CostModel {
+            intra,
+            inter: (
+                parse_price(message, "cost per message between clusters", spec)?,
+                parse_price(byte, "cost per byte between clusters", spec)?,
+            ),
+            num_clusters: clusters
+                .parse()
+                .map_err(|_| format!("invalid number of clusters in cost {:?}", spec))?,
+            aggregate,
+        }),
+        _ => Err(format!(
+            "cost {:?} needs a number of clusters and the costs between them after its first two",
+            spec
+        )),
+    }
+}
+
+/// The cost model selected by `params`, if they have one.
+pub fn load_cost_model(params: &Params) -> Result<Option<CostModel>, String> {
+    params.cost.as_deref().map(parse_cost_model).transpose()
+}
+
+/// The cost that each node of a run has paid so far.
+pub struct Costs {
+    model: CostModel,
+    num_voters: usize,
+    node_costs: Vec<f64>,
+}
+
+impl Costs {
+    /// Costs for the nodes of `params`, if they have a cost model.
+    pub fn new(params: &Params) -> Result<Option<Self>, String> {
+        let costs = load_cost_model(params)?.map(|model| Costs {
+            model,
+            num_voters: params.k,
+            node_costs: vec![0.0; params.n],
+        });
+        Ok(costs)
+    }
+
+    /// Charge `sender` for a message carrying `voters` voters to `receiver`.
+    pub fn add(&mut self, sender: usize, receiver: usize, voters: usize) {
+        let size = self.model.message_size(voters, self.num_voters);
+        self.node_costs[sender] += self.model.message_cost(sender, receiver, size);
+    }
+
+    /// `result` with the total cost and the mean and largest cost of a node.
+    pub fn apply_to(&self, result: SimulationResult) -> SimulationResult {
+        let total_cost: f64 = self.node_costs.iter().sum();
+        SimulationResult {
+            total_cost,
+            mean_node_cost: total_cost / self.node_costs.len() as f64,
+            max_node_cost: self.node_costs.iter().copied().fold(0.0, f64::max),
+            ..result
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_flat_prices() {
+        let model = parse_cost_model("1:0.01").unwrap();
+        assert_eq!(
+            model,
+            CostModel { intra: (1.0, 0.01), inter: (1.0, 0.01), num_clusters: 1, aggregate: false }
+        );
+        assert_eq!(model.message_cost(0, 1, 100), 2.0);
+        assert_eq!(model.message_cost(3, 3, 0), 1.0);
+    }
+
+    #[test]
+    fn parses_prices_between_clusters() {
+        let model = parse_cost_model("1:0.01:3:5:0.05").unwrap();
+        assert_eq!(
+            model,
+            CostModel { intra: (1.0, 0.01), inter: (5.0, 0.05), num_clusters: 3, aggregate: false }
+        );
+        // Nodes 1 and 4 are both in cluster 1, and node 2 in cluster 2.
+        assert_eq!(model.message_cost(1, 4, 100), 2.0);
+        assert_eq!(model.message_cost(1, 2, 100), 10.0);
+        assert_eq!(model.validate(3), Ok(()));
+        assert!(model.validate(2).unwrap_err().contains("can't split 2 nodes into 3 clusters"));
+    }
+
+    #[test]
+    fn rejects_malformed_specs() {
+        for (spec, error) in [
+            ("aggregate", "needs a cost per message and per byte"),
+            ("1:aggregate", "needs a cost per message and per byte"),
+            ("1:0.01:", "needs a number of clusters and the costs between them"),
+            ("1:0.01:3:5:0.05:7", "needs a number of clusters and the costs between them"),
+            ("1:0.01:aggregate:3:5:0.05", "needs a number of clusters"),
+            ("1:0.01:-3:5:0.05", "invalid number of clusters"),
+            ("1:0.01:3:five:0.05", "invalid cost per message between clusters"),
+        ] {
+            let e = parse_cost_model(spec).unwrap_err();
+            assert!(e.contains(error), "{:?} gave {:?}, expected {:?}", spec, e, error);
+        }
+        // Specs that parse, but can't price the messages of 10 nodes.
+        for (spec, error) in [
+            ("1:0.01:0:5:0.05", "can't split 10 nodes into 0 clusters"),
+            ("1:0.01:11:5:0.05", "can't split 10 nodes into 11 clusters"),
+            ("-1:0.01", "can't be negative"),
+            ("1:NaN", "can't be negative"),
+            ("1:0.01:2:5:-0.05", "can't be negative"),
+        ] {
+            let e = parse_cost_model(spec).unwrap().validate(10).unwrap_err();
+            assert!(e.contains(error), "{:?} gave {:?}, expected {:?}", spec, e, error);
+        }
+        // Free messages, and a cluster per node, are fine.
+        for spec in ["0:0", "-0:0:aggregate", "1:0.01:10:5:0.05", "1:0.01:1:5:0.05"] {
+            assert_eq!(parse_cost_model(spec).unwrap().validate(10), Ok(()), "{:?}", spec);
+        }
+    }
+
+    #[test]
+    fn sizes_aggregates_by_the_voters_rather_than_the_votes() {
+        assert_eq!(aggregate_size(0), 17 + SIGNATURE_SIZE);
+        assert_eq!(aggregate_size(8), 17 + 1 + SIGNATURE_SIZE);
+        assert_eq!(aggregate_size(2048), 17 + 256 + SIGNATURE_SIZE);
+
+        let lists = parse_cost_model("0:1").unwrap();
+        assert_eq!(lists.message_size(10, 2048), 17 + 4 * 10);
+        let aggregates = parse_cost_model("0:1:aggregate").unwrap();
+        assert!(aggregates.aggregate);
+        assert_eq!((aggregates.intra, aggregates.num_clusters), ((0.0, 1.0), 1));
+        // However many votes it carries, an aggregate is the same size.
+        assert_eq!(aggregates.message_size(1, 2048), aggregate_size(2048));
+        assert_eq!(aggregates.message_size(2048, 2048), aggregate_size(2048));
+        assert_eq!(aggregates.message_cost(0, 1, aggregates.message_size(1, 9)), 115.0);
+
+        let clustered = parse_cost_model("1:0.01:3:5:0.05:aggregate").unwrap();
+        assert!(clustered.aggregate);
+        assert_eq!(clustered.num_clusters, 3);
+    }
+}
@@ -0,0 +1,197 @@
+//! A real gossip node speaking the push-pull protocol over UDP.
+//!
+//! Every round a node sends its full vote state to a random peer in a `SYNC` message. The peer
+//! applies whatever it was missing and replies with a `DIFF` of the votes the sender is missing,
+//! computed with the same `compute_push_gossip` as the simulator.
+//!
+//! Messages are encoded as a one byte message type followed by little-endian `u32`s:
+//!
+//! ```text
+//! type, sender ID, number of votes, then for each vote: vote ID, number of voters, voters...
+//! ```
+//!
+//! Sending the full state in place of a proper summary keeps things simple, but limits a
+//! message to roughly 16k voters before it exceeds the maximum UDP datagram size.
+
+use rand::{weak_rng, XorShiftRng};
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+use crate::quorum::{Majority, QuorumRule};
+use crate::voters::VoterSet;
+use crate::{
+    compute_push_gossip, num_node_ids, num_votes_in_update, DefaultStrategy, Node, NodeId,
+    Strategy, VoteDiff,
+};
+
+const SYNC: u8 = 0;
+const DIFF: u8 = 1;
+
+/// Time between a node initiating exchanges.
+const ROUND_LENGTH: Duration = Duration::from_millis(200);
+
+fn put_u32(buf: &mut Vec<u8>, x: u32) {
+    buf.extend_from_slice(&x.to_le_bytes());
+}
+
+fn take_u32(buf: &mut &[u8]) -> Option<u32> {
+    if buf.len() < 4 {
+        return None;
+    }
+    let (x, rest) = buf.split_at(4);
+    *buf = rest;
+    Some(u32::from_le_bytes([x[0], x[1], x[2], x[3]]))
+}
+
+/// Write a count, which is bounded by the number of nodes, so always fits in a `u32`.
+fn put_len(buf: &mut Vec<u8>, len: usize) {
+    put_u32(buf, len as u32);
+}
+
+fn encode(kind: u8, from: NodeId, votes: &VoteDiff) -> Vec<u8> {
+    let mut buf = vec![kind];
+    put_u32(&mut buf, from);
+    put_len(&mut buf, votes.len());
+    for (&vote_id, voters) in votes {
+        put_u32(&mut buf, vote_id);
+        put_len(&mut buf, voters.len());
+        for voter in voters.iter() {
+            put_u32(&mut buf, voter);
+        }
+    }
+    buf
+}
+
+/// Bytes that `messages` messages of the vote, carrying `voters` voters between them, take in the
+/// encoding of `encode`.
+pub fn encoded_size(messages: usize, voters: usize) -> usize {
+    // Type, sender and number of votes, then the vote ID and number of voters.
+    messages * (1 + 4 * 4) + voters * 4
+}
+
+/// Decode a message from a network of `n` nodes, rejecting any out of range node IDs.
+fn decode(mut buf: &[u8], n: usize) -> Option<(u8, NodeId, VoteDiff)> {
+    let (&kind, rest) = buf.split_first()?;
+    buf = rest;
+    let from = take_u32(&mut buf).filter(|&from| (from as usize) < n)?;
+    let mut votes = VoteDiff::default();
+    for _ in 0..take_u32(&mut buf)? {
+        let vote_id = take_u32(&mut buf)?;
+        let voters = votes.entry(vote_id).or_insert_with(|| VoterSet::new(n));
+        for _ in 0..take_u32(&mut buf)? {
+            voters.insert(take_u32(&mut buf).filter(|&voter| (voter as usize) < n)?);
+        }
+    }
+    Some((kind, from, votes))
+}
+
+/// All of a node's votes, in the form they're sent over the wire.
+fn vote_state(node: &Node) -> VoteDiff {
+    node.votes
+        .iter()
+        .map(|(&vote_id, vote_info)| (vote_id, VoterSet::clone(vote_info.voters())))
+        .collect()
+}
+
+fn read_peers(filename: &str) -> Result<Vec<SocketAddr>, Box<dyn Error>> {
+    let mut peers = vec![];
+    for line in fs::read_to_string(filename)?.lines().map(str::trim) {
+        if !line.is_empty() {
+            peers.push(line.parse().map_err(|e| format!("bad peer address {:?}: {}", line, e))?);
+        }
+    }
+    Ok(peers)
+}
+
+/// Run node `id` of the network listed in `peers_file` (one `host:port` per line, node `i` on
+/// line `i`), casting a vote for rumour 0 on startup if `vote` is set. Runs until killed.
+pub fn run(peers_file: &str, id: NodeId, vote: bool) -> Result<(), Box<dyn Error>> {
+    let peers = read_peers(peers_file)?;
+    let n = peers.len();
+    num_node_ids(n)?;
+    if id as usize >= n {
+        return Err(From::from(format!("node ID {} out of range for {} peers", id, n)));
+    }
+    let address = peers[id as usize];
+
+    let socket = UdpSocket::bind(address)?;
+    let strategy: &dyn Strategy<XorShiftRng> = &DefaultStrategy;
+    let mut rng = weak_rng();
+
+    let quorum: Arc<dyn QuorumRule> = Arc::new(Majority);
+    let mut node = Node::new(id, n, quorum.clone());
+    if vote {
+        node.vote_for(0);
+    }
+    println!("node {} listening on {} ({} peers)", id, address, n);
+
+    let mut round = 0;
+    let mut next_round = Instant::now();
+    let mut num_exchanges = 0;
+    let mut num_vote_exchanges = 0;
+    let mut converged = false;
+    let mut buf = [0; 65536];
+
+    loop {
+        if Instant::now() >= next_round {
+            let partner = strategy.choose_partner(id, n, round, &mut rng)?;
+            let partner = peers[partner as usize];
+            if let Err(e) = socket.send_to(&encode(SYNC, id, &vote_state(&node)), partner) {
+                warn!("node {}: failed to send to {}: {}", id, partner, e);
+            }
+            round += 1;
+            next_round += ROUND_LENGTH;
+        }
+
+        let timeout = next_round.saturating_duration_since(Instant::now());
+        socket.set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+
+        let (len, src) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock
+                || e.kind() == io::ErrorKind::TimedOut
+                || e.kind() == io::ErrorKind::ConnectionRefused => continue,
+            Err(e) => return Err(From::from(e)),
+        };
+
+        let (kind, from, votes) = match decode(&buf[..len], n) {
+            Some(message) => message,
+            _ => {
+                warn!("node {}: ignoring malformed message from {}", id, src);
+                continue;
+            }
+        };
+
+        if kind == SYNC {
+            let mut remote = Node::new(from, n, quorum.clone());
+            remote.apply_diff(votes);
+            if let Some(diff) = compute_push_gossip(&remote, &node, strategy)? {
+                num_exchanges += 1;
+                num_vote_exchanges += num_votes_in_update(&diff);
+                node.apply_diff(diff);
+            }
+            if let Some(reply) = compute_push_gossip(&node, &remote, strategy)? {
+                if let Err(e) = socket.send_to(&encode(DIFF, id, &reply), src) {
+                    warn!("node {}: failed to reply to {}: {}", id, src, e);
+                }
+            }
+        } else if kind == DIFF {
+            num_exchanges += 1;
+            num_vote_exchanges += num_votes_in_update(&votes);
+            node.apply_diff(votes);
+        }
+
+        if !converged && node.has_quorum_for(0) {
+            converged = true;
+            println!(
+                "node {}: quorum for rumour 0 after {} rounds ({} exchanges, {} votes received)",
+                id, round, num_exchanges, num_vote_exchanges
+            );
+        }
+    }
+}
@@ -0,0 +1,279 @@
+//! Running parameter sweeps across several machines.
+//!
+//! Workers (`gossip worker`) accept TCP connections and run one parameter set per request line.
+//! The coordinator (`gossip coordinate`) connects to every worker listed in a file, hands out rows
+//! to whichever worker is free, and collects the results in input order. Rows whose worker goes
+//! away are retried on the remaining workers, while rows that can't run or whose simulation fails
+//! get a result recording the error.
+//!
+//! Requests and responses are single lines holding a header-less CSV record. Requests start
+//! with the number of runs to average, and always give a seed, which is the row's own or one
+//! derived from the coordinator's `--seed` as `run` would use, so that a distributed sweep gives
+//! the same results as running it locally:
+//!
+//! ```text
+//! coordinator: <repetitions> n,k,voting_steps,script,quorum,partners,timings,mode,seed
+//! worker:      OK n,k,voting_steps,script,quorum,...,num_iterations,...
+//!          or: ERR <message>
+//! ```
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::panic;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use tracing::{error, warn};
+
+use crate::{
+    row_seed, seeded_rng, simulate_params, validate_params, Params, SimulationResult,
+};
+
+/// Number of times a row is attempted before the sweep is abandoned.
+const MAX_ATTEMPTS: usize = 3;
+
+fn to_csv_line<T: Serialize>(record: &T) -> Result<String, Box<dyn Error>> {
+    let mut writer = csv::WriterBuilder::new().has_headers(false).from_writer(vec![]);
+    writer.serialize(record)?;
+    let bytes = writer.into_inner().map_err(|e| e.to_string())?;
+    Ok(String::from_utf8(bytes)?.trim_end().to_string())
+}
+
+fn from_csv_line<T: DeserializeOwned>(line: &str) -> Result<T, Box<dyn Error>> {
+    let mut reader = csv::ReaderBuilder::new().has_headers(false).from_reader(line.as_bytes());
+    match reader.deserialize().next() {
+        Some(record) => Ok(record?),
+        None => Err(From::from("empty record")),
+    }
+}
+
+fn run_request(line: &str) -> Result<String, Box<dyn Error>> {
+    let (repetitions, record) = line.split_once(' ').ok_or("request has no repetitions")?;
+    let repetitions: usize =
+        repetitions.parse().map_err(|_| format!("invalid repetitions {:?}", repetitions))?;
+    if repetitions == 0 {
+        return Err(From::from("no runs to average, as the repetitions are 0"));
+    }
+    let params: Params = from_csv_line(record)?;
+    validate_params(&params)?;
+    let seed = params.seed.ok_or("request has no seed")?;
+    let result = simulate_params(&params, repetitions, &mut seeded_rng(seed), &mut |_| ())?;
+    to_csv_line(&result)
+}
+
+fn handle_coordinator(stream: TcpStream) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        // A panic fails just the row, rather than looking like the worker went away.
+        let line = line?;
+        let result = panic::catch_unwind(|| run_request(&line)).unwrap_or_else(|_| {
+            Err(From::from("simulation panicked"))
+        });
+        match result {
+            Ok(result) => writeln!(writer, "OK {}", result)?,
+            Err(e) => writeln!(writer, "ERR {}", e.to_string().replace('\n', " "))?,
+        }
+    }
+    Ok(())
+}
+
+/// Run simulations requested by coordinators connecting to `addr`, until killed.
+pub fn serve_worker(addr: &str) -> Result<(), Box<dyn Error>> {
+    let listener = TcpListener::bind(addr)?;
+    println!("Worker listening on {}", listener.local_addr()?);
+
+    for stream in listener.incoming() {
+        let stream = stream?;
+        thread::spawn(move || {
+            if let Err(e) = handle_coordinator(stream) {
+                warn!("lost connection to coordinator: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Work shared between the coordinator's per-worker threads.
+struct Sweep {
+    /// Indices of rows waiting for a worker, with the number of failed attempts so far.
+    pending: VecDeque<(usize, usize)>,
+    in_flight: usize,
+    results: Vec<Option<SimulationResult>>,
+    error: Option<String>,
+}
+
+struct SharedSweep {
+    sweep: Mutex<Sweep>,
+    changed: Condvar,
+}
+
+impl SharedSweep {
+    /// Wait for a row to run, returning `None` once there's nothing left to do.
+    fn next_row(&self) -> Option<(usize, usize)> {
+        let mut sweep = self.sweep.lock().unwrap();
+        loop {
+            if sweep.error.is_some() {
+                return None;
+            }
+            if let Some(row) = sweep.pending.pop_front() {
+                sweep.in_flight += 1;
+                return Some(row);
+            }
+            if sweep.in_flight == 0 {
+                return None;
+            }
+            sweep = self.changed.wait(sweep).unwrap();
+        }
+    }
+
+    fn finish_row<F: FnOnce(&mut Sweep)>(&self, f: F) {
+        let mut sweep = self.sweep.lock().unwrap();
+        sweep.in_flight -= 1;
+        f(&mut sweep);
+        self.changed.notify_all();
+    }
+}
+
+enum RowError {
+    /// The worker couldn't be reached, so the row should be tried elsewhere.
+    Worker(String),
+    /// The simulation itself failed, which would happen on any worker.
+    Simulation(String),
+}
+
+fn request_row(
+    stream: &mut BufReader<TcpStream>,
+    params: &Params,
+    repetitions: usize,
+) -> Result<SimulationResult, RowError> {
+    let request = to_csv_line(params).map_err(|e| RowError::Simulation(e.to_string()))?;
+    let worker_err = |e: io::Error| RowError::Worker(e.to_string());
+
+    writeln!(stream.get_mut(), "{} {}", repetitions, request).map_err(worker_err)?;
+    let mut response = String::new();
+    if stream.read_line(&mut response).map_err(worker_err)? == 0 {
+        return Err(RowError::Worker("connection closed".to_string()));
+    }
+
+    let response = response.trim_end();
+    if let Some(result) = response.strip_prefix("OK ") {
+        from_csv_line(result).map_err(|e| RowError::Worker(format!("bad response: {}", e)))
+    } else if let Some(message) = response.strip_prefix("ERR ") {
+        Err(RowError::Simulation(message.to_string()))
+    } else {
+        Err(RowError::Worker(format!("bad response: {:?}", response)))
+    }
+}
+
+fn drive_worker(addr: &str, all_params: &[Params], repetitions: usize, shared: &SharedSweep) {
+    let mut stream = match TcpStream::connect(addr) {
+        Ok(stream) => BufReader::new(stream),
+        Err(e) => {
+            warn!("worker {} unavailable: {}", addr, e);
+            return;
+        }
+    };
+
+    while let Some((row, attempts)) = shared.next_row() {
+        match request_row(&mut stream, &all_params[row], repetitions) {
+            Ok(result) => shared.finish_row(|sweep| sweep.results[row] = Some(result)),
+            Err(RowError::Simulation(e)) => {
+                error!("row {} failed: {}", row + 1, e);
+                let result = SimulationResult::failed(&all_params[row], e);
+                shared.finish_row(|sweep| sweep.results[row] = Some(result));
+            }
+            Err(RowError::Worker(e)) => {
+                warn!("worker {} failed on row {}: {}", addr, row + 1, e);
+                shared.finish_row(|sweep| {
+                    if attempts + 1 < MAX_ATTEMPTS {
+                        sweep.pending.push_back((row, attempts + 1));
+                    } else {
+                        sweep.error = Some(format!("row {} failed {} times", row + 1, MAX_ATTEMPTS));
+                    }
+                });
+                // Stop using this worker.
+                return;
+            }
+        }
+    }
+}
+
+fn read_workers(filename: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    Ok(fs::read_to_string(filename)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect())
+}
+
+/// Run every parameter set in `rows` on the workers listed in `workers_file`, averaging
+/// `repetitions` runs of each, returning the results in the same order. Rows without a seed of
+/// their own are seeded from `seed` and their row number.
+pub fn coordinate(
+    rows: Vec<(usize, Params)>,
+    workers_file: &str,
+    repetitions: usize,
+    seed: u64,
+) -> Result<Vec<SimulationResult>, Box<dyn Error>> {
+    if repetitions == 0 {
+        return Err(From::from("no runs to average, as --repetitions is 0"));
+    }
+    let workers = read_workers(workers_file)?;
+    let all_params: Vec<Params> = rows
+        .into_iter()
+        .map(|(row, params)| {
+            let seed = params.seed.unwrap_or_else(|| row_seed(seed, row));
+            Params { seed: Some(seed), ..params }
+        })
+        .collect();
+
+    let mut pending = VecDeque::new();
+    let mut results = vec![];
+    for (row, params) in all_params.iter().enumerate() {
+        match validate_params(params) {
+            Ok(()) => {
+                pending.push_back((row, 0));
+                results.push(None);
+            }
+            Err(e) => {
+                error!("row {} failed: {}", row + 1, e);
+                results.push(Some(SimulationResult::failed(params, e)));
+            }
+        }
+    }
+
+    let shared = Arc::new(SharedSweep {
+        sweep: Mutex::new(Sweep { pending, in_flight: 0, results, error: None }),
+        changed: Condvar::new(),
+    });
+    let all_params = Arc::new(all_params);
+
+    let threads: Vec<_> = workers
+        .into_iter()
+        .map(|addr| {
+            let shared = shared.clone();
+            let all_params = all_params.clone();
+            thread::spawn(move || drive_worker(&addr, &all_params, repetitions, &shared))
+        })
+        .collect();
+    for thread in threads {
+        thread.join().map_err(|_| "worker thread panicked")?;
+    }
+
+    let sweep = shared.sweep.lock().unwrap();
+    if let Some(ref e) = sweep.error {
+        return Err(From::from(e.clone()));
+    }
+    let results = sweep
+        .results
+        .iter()
+        .cloned()
+        .collect::<Option<Vec<_>>>()
+        .ok_or("ran out of workers before every row finished")?;
+    Ok(results)
+}
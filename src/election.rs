@@ -0,0 +1,265 @@
+//! Leader election, a second workload gossiped the same way as the votes.
+//!
+//! A row with an `election` column elects a leader instead of gathering votes. The `k` nodes that
+//! would vote stand as candidates instead, in the same steps as the voting schedule, and every node
+//! follows the best candidate it has heard of. Each exchange sends the sender's leader to the
+//! partner if it's better than the partner's, so that the partner follows it from the next round,
+//! and a run ends once every node follows the best of all the candidates.
+//!
+//! `max` ranks candidates by node ID, as in the bully algorithm, so the last to stand always wins
+//! and late candidates overthrow the leaders that won the earlier steps. `hash` ranks them by a
+//! hash of their ID, so the winner can stand in any step. A node that moves from one leader to
+//! another has flip-flopped, unlike one following its first leader, and the results count how
+//! often that happened across the network and for the most fickle node.
+//!
+//! Partners are chosen by the row's strategy, including subnets, and messages are lost at the
+//! row's rate, but scripts only choose partners. The quorum columns of the results count the nodes
+//! following the eventual leader, and `num_exchanges` and `num_vote_exchanges` both count the
+//! leaders sent.
+
+use rand::Rng;
+use std::error::Error;
+
+use crate::{
+    choose_losses, construct_voting_schedule, is_lost, splitmix64, Milestones, NodeId, Params,
+    RoundMetrics, SimulationMode, SimulationResult, Strategy,
+};
+
+/// How candidates are ranked against each other.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ElectionRank {
+    /// The highest node ID wins.
+    Max,
+    /// The highest hash of the node ID wins.
+    Hash,
+}
+
+impl ElectionRank {
+    /// The name of the ranking, as parsed by `from_str`.
+    pub fn name(self) -> &'static str {
+        match self {
+            ElectionRank::Max => "max",
+            ElectionRank::Hash => "hash",
+        }
+    }
+
+    /// The rank of `candidate`, the highest of which wins.
+    fn rank(self, candidate: NodeId) -> u64 {
+        match self {
+            ElectionRank::Max => candidate as u64,
+            ElectionRank::Hash => splitmix64(&mut (candidate as u64)),
+        }
+    }
+
+    /// The best of the `k` candidates, which wins the election.
+    fn winner(self, k: usize) -> Option<NodeId> {
+        (0..k as NodeId).max_by_key(|&candidate| self.rank(candidate))
+    }
+}
+
+impl std::str::FromStr for ElectionRank {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "max" => Ok(ElectionRank::Max),
+            "hash" => Ok(ElectionRank::Hash),
+            _ => Err(format!("unknown election ranking {:?}, expected max or hash", s)),
+        }
+    }
+}
+
+/// Check that an election can be held for `params`.
+pub fn validate(params: &Params) -> Result<(), String> {
+    if params.k == 0 {
+        return Err("an election needs k >= 1 candidates".to_string());
+    }
+    if params.quorum.is_some() {
+        return Err("an election has no quorum, so can't have a quorum rule".to_string());
+    }
+    let unsupported = [
+        (params.max_messages_per_round.is_some(), "rate limits"),
+        (params.cost.is_some(), "costs"),
+        (params.cohorts.is_some(), "cohorts"),
+        (params.membership.is_some(), "membership"),
+        (params.mobility.is_some(), "mobility"),
+    ];
+    match unsupported.iter().find(|(set, _)| *set) {
+        Some((_, name)) => Err(format!("elections don't support {}", name)),
+        None => Ok(()),
+    }
+}
+
+/// Hold `repetitions` elections for `params`, returning the result of each.
+pub fn simulate_runs<R: Rng>(
+    params: &Params,
+    rank: ElectionRank,
+    strategy: &dyn Strategy<R>,
+    repetitions: usize,
+    rng: &mut R,
+    on_round: &mut dyn FnMut(&RoundMetrics),
+) -> Result<Vec<SimulationResult>, Box<dyn Error>> {
+    (0..repetitions)
+        .map(|repetition| {
+            let result = run_election(params, rank, strategy, rng, &mut |metrics| {
+                on_round(&RoundMetrics { repetition, ..metrics.clone() })
+            })?;
+            let mode = Some(SimulationMode::General);
+            Ok(SimulationResult { mode, ..result }.with_predictions())
+        })
+        .collect()
+}
+
+/// Hold a single election for `params`, until every node follows the winner or the deadline.
+pub fn run_election<R: Rng>(
+    params: &Params,
+    rank: ElectionRank,
+    strategy: &dyn Strategy<R>,
+    rng: &mut R,
+    on_round: &mut dyn FnMut(&RoundMetrics),
+) -> Result<SimulationResult, Box<dyn Error>> {
+    let (n, k) = (params.n, params.k);
+    let schedule = construct_voting_schedule(k, params.voting_steps);
+    let better = |candidate: NodeId, leader: Option<NodeId>| {
+        leader.is_none_or(|leader| rank.rank(candidate) > rank.rank(leader))
+    };
+    let winner = rank.winner(k).ok_or("an election needs k >= 1 candidates")?;
+
+    let mut leaders: Vec<Option<NodeId>> = vec![None; n];
+    let mut next = leaders.clone();
+    let mut leader_changes = vec![0usize; n];
+    let mut partners = Vec::with_capacity(n);
+    let mut lost = vec![];
+    let mut milestones = Milestones::default();
+    let mut num_candidates = 0;
+    let mut num_following = 0;
+    let mut num_exchanges = 0;
+    let mut round = 0;
+
+    while num_following < n && Some(round) != params.max_rounds {
+        // Candidates stand at the start of their step, following themselves unless they already
+        // follow someone better.
+        let standing = schedule.get(&round).copied().unwrap_or(0);
+        for candidate in num_candidates..num_candidates + standing {
+            if better(candidate as NodeId, leaders[candidate]) {
+                leader_changes[candidate] += usize::from(leaders[candidate].is_some());
+                leaders[candidate] = Some(candidate as NodeId);
+            }
+        }
+        num_candidates += standing;
+
+        strategy.choose_partners(n, round, rng, &mut partners)?;
+        for (node, &partner) in partners.iter().enumerate() {
+            if partner as usize >= n || partner as usize == node {
+                return Err(From::from(format!(
+                    "node {} chose invalid gossip partner {}",
+                    node, partner
+                )));
+            }
+        }
+        choose_losses(params.loss, n, rng, &mut lost);
+
+        // Push-pull, with every exchange seeing the leaders from the start of the round.
+        next.copy_from_slice(&leaders);
+        let mut round_exchanges = 0;
+        for (node, &partner) in partners.iter().enumerate() {
+            let partner = partner as usize;
+            let messages = [(partner, node), (node, partner)];
+            for (direction, (sender, receiver)) in messages.into_iter().enumerate() {
+                let Some(leader) = leaders[sender] else {
+                    continue;
+                };
+                if !better(leader, leaders[receiver]) {
+                    continue;
+                }
+                round_exchanges += 1;
+                if !is_lost(&lost, node, direction) && better(leader, next[receiver]) {
+                    next[receiver] = Some(leader);
+                }
+            }
+        }
+        for (node, (&old, &new)) in leaders.iter().zip(&next).enumerate() {
+            if old.is_some() && old != new {
+                leader_changes[node] += 1;
+            }
+        }
+        std::mem::swap(&mut leaders, &mut next);
+
+        num_exchanges += round_exchanges;
+        num_following = leaders.iter().filter(|&&leader| leader == Some(winner)).count();
+        milestones.record(n, round + 1, num_following, 0);
+        on_round(&RoundMetrics {
+            repetition: 0,
+            round,
+            nodes_with_quorum: num_following,
+            num_exchanges: round_exchanges,
+            num_vote_exchanges: round_exchanges,
+            times: None,
+        });
+        round += 1;
+    }
+
+    Ok(milestones.apply_to(SimulationResult {
+        num_iterations: round as f64,
+        num_exchanges,
+        num_vote_exchanges: num_exchanges,
+        average_votes_held: 0.0,
+        missed_deadline: if num_following < n { 1.0 } else { 0.0 },
+        leader_changes: leader_changes.iter().sum::<usize>() as f64,
+        max_leader_changes: leader_changes.iter().copied().max().unwrap_or(0) as f64,
+        ..SimulationResult::for_params(params)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{load_strategy, seeded_rng};
+    use serde_json::json;
+
+    fn params(n: usize, k: usize, voting_steps: usize, rank: &str) -> Params {
+        let params = json!({"n": n, "k": k, "voting_steps": voting_steps, "election": rank});
+        serde_json::from_value(params).unwrap()
+    }
+
+    #[test]
+    fn ranks_candidates() {
+        assert_eq!(ElectionRank::Max.winner(0), None);
+        assert_eq!(ElectionRank::Max.winner(1), Some(0));
+        assert_eq!(ElectionRank::Max.winner(10), Some(9));
+        for k in 1..20 {
+            let winner = ElectionRank::Hash.winner(k).unwrap();
+            assert!((0..k as NodeId).all(|candidate| {
+                candidate == winner
+                    || ElectionRank::Hash.rank(candidate) < ElectionRank::Hash.rank(winner)
+            }));
+        }
+        // Hashing doesn't just pick the last candidate.
+        assert!((1..20).any(|k| ElectionRank::Hash.winner(k) != Some(k as NodeId - 1)));
+    }
+
+    #[test]
+    fn every_node_follows_the_winner() {
+        for rank in [ElectionRank::Max, ElectionRank::Hash] {
+            for voting_steps in [1, 4] {
+                let params = params(200, 20, voting_steps, rank.name());
+                let mut rng = seeded_rng(1);
+                let strategy = load_strategy(&params, &mut rng).unwrap();
+                let mut following = vec![];
+                let result = run_election(&params, rank, &*strategy, &mut rng, &mut |metrics| {
+                    following.push(metrics.nodes_with_quorum)
+                })
+                .unwrap();
+                // Nodes only count as following once they follow `winner`.
+                assert_eq!(following.last(), Some(&200), "{:?} in {} steps", rank, voting_steps);
+                assert_eq!(result.num_iterations, following.len() as f64);
+                assert_eq!(result.missed_deadline, 0.0);
+                if rank == ElectionRank::Max && voting_steps > 1 {
+                    // Later candidates outrank every earlier one, so leaders get overthrown.
+                    assert!(result.leader_changes > 0.0);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,91 @@
+//! Predictions of the standard epidemic (SI) model of push-pull gossip, for comparison with the
+//! simulation.
+//!
+//! The model follows a single rumour starting at one node, in a network where every node starts
+//! an exchange with a partner chosen uniformly at random each round. A node that doesn't have the
+//! rumour yet learns it if its own partner has it (the pull) or if any node with it picks the node
+//! as a partner (the push), so if `u` is the fraction of the nodes without the rumour, after the
+//! round the fraction is `u * u * (1 - 1/(n - 1))^(n * (1 - u))` in expectation. If each message
+//! is lost with probability `loss`, the pull fails too unless it arrives, and the fraction is
+//! `u * (1 - (1 - u) * (1 - loss)) * (1 - (1 - loss)/(n - 1))^(n * (1 - u))`.
+//!
+//! The simulation spreads a vote from every voter and stops at a quorum rather than when every
+//! node has every vote, so the ratio of its measurements to these predictions shows how much the
+//! voting schedule and the quorum change the cost of dissemination.
+
+/// The expected cost of spreading a rumour to every node.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Prediction {
+    /// Rounds until fewer than half a node is expected to lack the rumour.
+    pub rounds: f64,
+    /// Messages carrying the rumour to a node without it, counted like the simulation's exchanges.
+    pub exchanges: f64,
+}
+
+/// Predict the rounds and messages for push-pull dissemination of a rumour among `n` nodes, each
+/// message being lost with probability `loss`.
+pub fn predict(n: usize, loss: f64) -> Prediction {
+    let mut prediction = Prediction { rounds: 0.0, exchanges: 0.0 };
+    // Without any chance of a message arriving, the rumour never spreads.
+    if n < 2 || !(0.0..1.0).contains(&loss) {
+        return prediction;
+    }
+
+    let nodes = n as f64;
+    let delivered = 1.0 - loss;
+    let missed_by_one = 1.0 - delivered / (nodes - 1.0);
+    let mut uninformed = 1.0 - 1.0 / nodes;
+    while uninformed * nodes >= 0.5 {
+        // Each node's exchange carries the rumour if exactly one of the pair has it, and the
+        // sender counts it whether or not it arrives.
+        prediction.exchanges += 2.0 * nodes * uninformed * (1.0 - uninformed);
+        let missed_pull = 1.0 - (1.0 - uninformed) * delivered;
+        uninformed *= missed_pull * missed_by_one.powf(nodes * (1.0 - uninformed));
+        prediction.rounds += 1.0;
+    }
+    prediction
+}
+
+/// The ratio of a measurement to its prediction, unless nothing was predicted.
+pub fn ratio(measured: f64, predicted: f64) -> Option<f64> {
+    (predicted > 0.0).then(|| measured / predicted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn predicts_small_networks() {
+        // Of 2 nodes, the second always learns the rumour in the first round, from one of the 2
+        // exchanges that carry it half the time.
+        assert_eq!(predict(2, 0.0), Prediction { rounds: 1.0, exchanges: 1.0 });
+        // Of 3, 2/9 are expected to lack it after the first round, which is still more than half
+        // a node, and 0.029 after the second.
+        let prediction = predict(3, 0.0);
+        assert_eq!(prediction.rounds, 2.0);
+        let exchanges = 6.0 * (2.0 / 3.0) * (1.0 / 3.0) + 6.0 * (2.0 / 9.0) * (7.0 / 9.0);
+        assert!((prediction.exchanges - exchanges).abs() < 1e-12, "{:?}", prediction);
+        // Larger networks take longer, though only around log n rounds.
+        let rounds = [64, 128, 256, 512, 1024].map(|n| predict(n, 0.0).rounds);
+        assert!(rounds.windows(2).all(|pair| pair[1] >= pair[0]), "{:?}", rounds);
+        assert!(rounds[4] > rounds[0] && rounds[4] <= rounds[0] + 4.0, "{:?}", rounds);
+    }
+
+    #[test]
+    fn loss_slows_the_spread() {
+        let (lossless, lossy) = (predict(1000, 0.0), predict(1000, 0.5));
+        assert!(lossy.rounds > lossless.rounds);
+        assert!(lossy.exchanges > lossless.exchanges);
+        // No message ever arrives, or there's no one to send one to.
+        assert_eq!(predict(1000, 1.0), Prediction { rounds: 0.0, exchanges: 0.0 });
+        assert_eq!(predict(1, 0.0), Prediction { rounds: 0.0, exchanges: 0.0 });
+    }
+
+    #[test]
+    fn has_no_ratio_to_nothing() {
+        assert_eq!(ratio(6.0, 4.0), Some(1.5));
+        assert_eq!(ratio(0.0, 4.0), Some(0.0));
+        assert_eq!(ratio(6.0, 0.0), None);
+    }
+}
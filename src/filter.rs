@@ -0,0 +1,480 @@
+//! Expressions selecting parameter sets, like `n >= 1000 && k > n / 2`.
+//!
+//! An expression can refer to any parameter by name, with the effective value of those left out
+//! of the input:
+//!
+//! * `n`, `k`, `voting_steps`, `loss`, `max_messages_per_round` and `max_rounds` are numbers, the
+//!   last two of them 0 without a limit.
+//! * `script`, `quorum`, `partners`, `mode`, `label`, `rate_limit`, `rate_limit_policy`, `cost`,
+//!   `cohorts`, `subnets`, `membership`, `election` and `mobility` are strings, such as
+//!   `quorum == "majority"`.
+//! * `timings` is `true` or `false`.
+//!
+//! Numbers support `+`, `-`, `*`, `/` and `%`, all of them compare with `==` and `!=`, numbers
+//! and strings also with `<`, `<=`, `>` and `>=`, and conditions combine with `&&`, `||` and `!`
+//! as in Rust, grouped with parentheses.
+
+use std::cmp::Ordering;
+
+use crate::election::ElectionRank;
+use crate::{simulation_mode, PartnerMode, Params};
+
+/// A parsed expression, ready to test parameter sets against.
+#[derive(Clone, Debug)]
+pub struct Filter {
+    source: String,
+    expr: Expr,
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Number(f64),
+    Text(String),
+    Bool(bool),
+    Field(Field),
+    Not(Box<Expr>),
+    Negate(Box<Expr>),
+    Binary(Box<Expr>, Op, Box<Expr>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+    N,
+    K,
+    VotingSteps,
+    Loss,
+    MaxMessagesPerRound,
+    MaxRounds,
+    Script,
+    Quorum,
+    Partners,
+    Mode,
+    Label,
+    RateLimit,
+    RateLimitPolicy,
+    Cost,
+    Cohorts,
+    Subnets,
+    Membership,
+    Election,
+    Mobility,
+    Timings,
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Field> {
+        Some(match name {
+            "n" => Field::N,
+            "k" => Field::K,
+            "voting_steps" => Field::VotingSteps,
+            "loss" => Field::Loss,
+            "max_messages_per_round" => Field::MaxMessagesPerRound,
+            "max_rounds" => Field::MaxRounds,
+            "script" => Field::Script,
+            "quorum" => Field::Quorum,
+            "partners" => Field::Partners,
+            "mode" => Field::Mode,
+            "label" => Field::Label,
+            "rate_limit" => Field::RateLimit,
+            "rate_limit_policy" => Field::RateLimitPolicy,
+            "cost" => Field::Cost,
+            "cohorts" => Field::Cohorts,
+            "subnets" => Field::Subnets,
+            "membership" => Field::Membership,
+            "election" => Field::Election,
+            "mobility" => Field::Mobility,
+            "timings" => Field::Timings,
+            _ => return None,
+        })
+    }
+
+    fn value(self, params: &Params) -> Value {
+        match self {
+            Field::N => Value::Number(params.n as f64),
+            Field::K => Value::Number(params.k as f64),
+            Field::VotingSteps => Value::Number(params.voting_steps as f64),
+            Field::Loss => Value::Number(params.loss),
+            Field::MaxMessagesPerRound => {
+                Value::Number(params.max_messages_per_round.unwrap_or(0) as f64)
+            }
+            Field::MaxRounds => Value::Number(params.max_rounds.unwrap_or(0) as f64),
+            Field::Script => Value::Text(params.script.clone().unwrap_or_default()),
+            Field::Quorum => {
+                Value::Text(params.quorum.clone().unwrap_or_else(|| "majority".to_string()))
+            }
+            Field::Partners => {
+                Value::Text(params.partners.unwrap_or(PartnerMode::Random).name().to_string())
+            }
+            Field::Mode => Value::Text(simulation_mode(params).name().to_string()),
+            Field::Label => Value::Text(params.label.clone().unwrap_or_default()),
+            Field::RateLimit => {
+                Value::Text(params.rate_limit.unwrap_or_default().name().to_string())
+            }
+            Field::RateLimitPolicy => {
+                Value::Text(params.rate_limit_policy.unwrap_or_default().name().to_string())
+            }
+            Field::Cost => Value::Text(params.cost.clone().unwrap_or_default()),
+            Field::Cohorts => Value::Text(params.cohorts.clone().unwrap_or_default()),
+            Field::Subnets => Value::Text(params.subnets.clone().unwrap_or_default()),
+            Field::Membership => Value::Text(params.membership.clone().unwrap_or_default()),
+            Field::Election => {
+                Value::Text(params.election.map_or("", ElectionRank::name).to_string())
+            }
+            Field::Mobility => Value::Text(params.mobility.clone().unwrap_or_default()),
+            Field::Timings => Value::Bool(params.timings),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl Op {
+    fn token(self) -> &'static str {
+        Op::LEVELS.iter().flat_map(|ops| ops.iter()).find(|&&(_, op)| op == self).unwrap().0
+    }
+
+    /// Operators binding tighter come later, with `||` loosest.
+    const LEVELS: &'static [&'static [(&'static str, Op)]] = &[
+        &[("||", Op::Or)],
+        &[("&&", Op::And)],
+        &[
+            ("==", Op::Eq),
+            ("!=", Op::Ne),
+            ("<=", Op::Le),
+            (">=", Op::Ge),
+            ("<", Op::Lt),
+            (">", Op::Gt),
+        ],
+        &[("+", Op::Add), ("-", Op::Sub)],
+        &[("*", Op::Mul), ("/", Op::Div), ("%", Op::Rem)],
+    ];
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Number(f64),
+    Text(String),
+    Bool(bool),
+}
+
+impl Value {
+    fn describe(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "a number",
+            Value::Text(_) => "a string",
+            Value::Bool(_) => "true or false",
+        }
+    }
+}
+
+/// A recursive descent parser over the characters of an expression.
+struct Parser<'a> {
+    source: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.source[self.position..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    /// Consume `token` if it comes next.
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        // `!` alone is negation, not the start of `!=`, and likewise for the comparisons.
+        let rest = self.rest();
+        if !rest.starts_with(token) {
+            return false;
+        }
+        if matches!(token, "!" | "<" | ">") && rest[token.len()..].starts_with('=') {
+            return false;
+        }
+        self.position += token.len();
+        true
+    }
+
+    fn error(&self, message: &str) -> String {
+        match self.rest() {
+            "" => format!("{} at the end", message),
+            rest => format!("{} at {:?}", message, rest),
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        let Some(ops) = Op::LEVELS.get(level) else {
+            return self.unary();
+        };
+        let mut expr = self.binary(level + 1)?;
+        'outer: loop {
+            for &(token, op) in ops.iter() {
+                if self.eat(token) {
+                    let right = self.binary(level + 1)?;
+                    expr = Expr::Binary(Box::new(expr), op, Box::new(right));
+                    continue 'outer;
+                }
+            }
+            return Ok(expr);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("-") {
+            return Ok(Expr::Negate(Box::new(self.unary()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        self.skip_whitespace();
+        if self.eat("(") {
+            let expr = self.binary(0)?;
+            if !self.eat(")") {
+                return Err(self.error("expected )"));
+            }
+            return Ok(expr);
+        }
+
+        let rest = self.rest();
+        let first = rest.chars().next().ok_or_else(|| self.error("expected a value"))?;
+        if first == '"' || first == '\'' {
+            let end = rest[1..].find(first).ok_or_else(|| self.error("unterminated string"))?;
+            self.position += end + 2;
+            return Ok(Expr::Text(rest[1..end + 1].to_string()));
+        }
+
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+            .unwrap_or(rest.len());
+        let word = &rest[..len];
+        let expr = if first.is_ascii_digit() || first == '.' {
+            Expr::Number(word.parse().map_err(|_| self.error("invalid number"))?)
+        } else {
+            match word {
+                "true" => Expr::Bool(true),
+                "false" => Expr::Bool(false),
+                "" => return Err(self.error("expected a value")),
+                _ => match Field::parse(word) {
+                    Some(field) => Expr::Field(field),
+                    None => return Err(format!("unknown parameter {:?}", word)),
+                },
+            }
+        };
+        self.position += len;
+        Ok(expr)
+    }
+}
+
+impl Filter {
+    pub fn parse(source: &str) -> Result<Filter, String> {
+        let mut parser = Parser { source, position: 0 };
+        let expr = parser.binary(0)?;
+        parser.skip_whitespace();
+        if !parser.rest().is_empty() {
+            return Err(parser.error("expected an operator"));
+        }
+        Ok(Filter { source: source.to_string(), expr })
+    }
+
+    /// Whether `params` satisfy the expression, which must come out as `true` or `false`.
+    pub fn matches(&self, params: &Params) -> Result<bool, String> {
+        match evaluate(&self.expr, params)? {
+            Value::Bool(matches) => Ok(matches),
+            value => Err(format!("{:?} is {}, not true or false", self.source, value.describe())),
+        }
+    }
+}
+
+fn evaluate(expr: &Expr, params: &Params) -> Result<Value, String> {
+    Ok(match expr {
+        Expr::Number(x) => Value::Number(*x),
+        Expr::Text(text) => Value::Text(text.clone()),
+        Expr::Bool(b) => Value::Bool(*b),
+        Expr::Field(field) => field.value(params),
+        Expr::Not(expr) => match evaluate(expr, params)? {
+            Value::Bool(b) => Value::Bool(!b),
+            value => return Err(format!("can't apply ! to {}", value.describe())),
+        },
+        Expr::Negate(expr) => match evaluate(expr, params)? {
+            Value::Number(x) => Value::Number(-x),
+            value => return Err(format!("can't negate {}", value.describe())),
+        },
+        Expr::Binary(left, op @ (Op::And | Op::Or), right) => {
+            match (evaluate(left, params)?, op) {
+                (Value::Bool(false), Op::And) => Value::Bool(false),
+                (Value::Bool(true), Op::Or) => Value::Bool(true),
+                (Value::Bool(_), _) => match evaluate(right, params)? {
+                    Value::Bool(b) => Value::Bool(b),
+                    value => return Err(format!("expected a condition, not {}", value.describe())),
+                },
+                (value, _) => return Err(format!("expected a condition, not {}", value.describe())),
+            }
+        }
+        Expr::Binary(left, op, right) => {
+            binary(*op, evaluate(left, params)?, evaluate(right, params)?)?
+        }
+    })
+}
+
+fn binary(op: Op, left: Value, right: Value) -> Result<Value, String> {
+    let same_type = left.describe() == right.describe();
+    let ordering = match (&left, &right) {
+        (Value::Number(a), Value::Number(b)) => a.partial_cmp(b),
+        (Value::Text(a), Value::Text(b)) => Some(a.cmp(b)),
+        _ => None,
+    };
+    Ok(match (op, &left, &right, ordering) {
+        (Op::Eq, _, _, _) if same_type => Value::Bool(left == right),
+        (Op::Ne, _, _, _) if same_type => Value::Bool(left != right),
+        (Op::Lt, _, _, Some(ordering)) => Value::Bool(ordering == Ordering::Less),
+        (Op::Le, _, _, Some(ordering)) => Value::Bool(ordering != Ordering::Greater),
+        (Op::Gt, _, _, Some(ordering)) => Value::Bool(ordering == Ordering::Greater),
+        (Op::Ge, _, _, Some(ordering)) => Value::Bool(ordering != Ordering::Less),
+        (Op::Add, Value::Number(a), Value::Number(b), _) => Value::Number(a + b),
+        (Op::Sub, Value::Number(a), Value::Number(b), _) => Value::Number(a - b),
+        (Op::Mul, Value::Number(a), Value::Number(b), _) => Value::Number(a * b),
+        (Op::Div, Value::Number(a), Value::Number(b), _) => Value::Number(a / b),
+        (Op::Rem, Value::Number(a), Value::Number(b), _) => Value::Number(a % b),
+        _ => {
+            return Err(format!(
+                "can't apply {} to {} and {}",
+                op.token(),
+                left.describe(),
+                right.describe()
+            ))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn params() -> Params {
+        serde_json::from_value(json!({
+            "n": 1000,
+            "k": 700,
+            "voting_steps": 2,
+            "loss": 0.1,
+            "label": "baseline",
+        }))
+        .unwrap()
+    }
+
+    fn matches(source: &str) -> Result<bool, String> {
+        Filter::parse(source)?.matches(&params())
+    }
+
+    #[test]
+    fn follows_rust_precedence_and_associativity() {
+        for source in [
+            "1 + 2 * 3 == 7",
+            "(1 + 2) * 3 == 9",
+            "10 - 3 - 2 == 5",
+            "12 / 3 / 2 == 2",
+            "7 % 4 * 2 == 6",
+            "-2 * -3 == 6",
+            "n >= 1000 && k > n / 2",
+            "false && true || true",
+            "true || true && false",
+            "!(k > n)",
+            "!false == true",
+        ] {
+            assert_eq!(matches(source), Ok(true), "{}", source);
+        }
+        assert_eq!(matches("false && (true || true)"), Ok(false));
+        assert_eq!(matches("!true || false"), Ok(false));
+    }
+
+    #[test]
+    fn tells_negation_from_not_equal_and_comparisons_from_their_or_equal_forms() {
+        for source in ["n != 5", "!(n == 5)", "k <= 700", "k >= 700", "k < 701", "k > 699"] {
+            assert_eq!(matches(source), Ok(true), "{}", source);
+        }
+        assert_eq!(matches("k<700"), Ok(false));
+        assert_eq!(matches("k>=701"), Ok(false));
+    }
+
+    #[test]
+    fn compares_strings_and_fills_in_defaults() {
+        for source in [
+            "label == \"baseline\"",
+            "label == 'baseline'",
+            "label < 'c' && label > 'a'",
+            "quorum == 'majority'",
+            "partners == 'random'",
+            "mode == 'compact'",
+            "script == ''",
+            "max_rounds == 0 && max_messages_per_round == 0",
+            "loss > 0.05 && loss < .2",
+            "timings == false && !timings",
+        ] {
+            assert_eq!(matches(source), Ok(true), "{}", source);
+        }
+    }
+
+    #[test]
+    fn short_circuits_before_mismatched_types() {
+        assert_eq!(matches("n < 10 && label > 1"), Ok(false));
+        assert_eq!(matches("n > 10 || label > 1"), Ok(true));
+        assert!(matches("n > 10 && label > 1").is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_expressions() {
+        for (source, error) in [
+            ("", "expected a value at the end"),
+            ("n >", "expected a value at the end"),
+            ("n > 1 1", "expected an operator at \"1\""),
+            ("(n > 1", "expected ) at the end"),
+            ("label == 'baseline", "unterminated string"),
+            ("nodes > 1", "unknown parameter \"nodes\""),
+            ("n > 1.2.3", "invalid number"),
+            ("n > #", "expected a value at \"#\""),
+        ] {
+            let e = Filter::parse(source).unwrap_err();
+            assert!(e.contains(error), "{:?} gave {:?}, expected {:?}", source, e, error);
+        }
+    }
+
+    #[test]
+    fn rejects_mismatched_types() {
+        for (source, error) in [
+            ("n + 1", "is a number, not true or false"),
+            ("label", "is a string, not true or false"),
+            ("n > 'a'", "can't apply > to a number and a string"),
+            ("label + 'x' == 'a'", "can't apply + to a string and a string"),
+            ("timings == 0", "can't apply == to true or false and a number"),
+            ("!n", "can't apply ! to a number"),
+            ("-label == 1", "can't negate a string"),
+            ("n && true", "expected a condition, not a number"),
+            ("false || 1", "expected a condition, not a number"),
+        ] {
+            let e = matches(source).unwrap_err();
+            assert!(e.contains(error), "{:?} gave {:?}, expected {:?}", source, e, error);
+        }
+    }
+}
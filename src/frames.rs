@@ -0,0 +1,195 @@
+//! Frames of a single run, one per round, for rendering the spread of the votes as an animation.
+//!
+//! Frame `r` is the state of every node after `r` rounds, starting from frame 0 before the first
+//! round, and is written to `frame-<r>.<extension>` in the output directory, with a row per node
+//! in the results format. With `mobility`, each node's row has its position in the unit square in
+//! that round, from frame 1 on since the first round places the nodes. Otherwise the network has
+//! no layout, as every node can reach every other, so the frames leave placing the nodes to
+//! whatever renders them.
+
+use rand::Rng;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use crate::{load_quorum_rule, load_strategy, write_results, OutputFormat, Params, Simulation};
+
+/// The state of one node in a frame.
+#[derive(Clone, Debug, Serialize)]
+pub struct NodeFrame {
+    pub round: usize,
+    pub node: usize,
+    pub voted: bool,
+    /// Voters that the node knows of.
+    pub voters_known: usize,
+    pub has_quorum: bool,
+    /// Whether the node knows of every node that has voted so far.
+    pub knows_every_voter: bool,
+    /// Where the node is with `mobility`.
+    pub x: Option<f64>,
+    pub y: Option<f64>,
+}
+
+/// The frame of `simulation` as it stands.
+fn frame(simulation: &Simulation) -> Vec<NodeFrame> {
+    let nodes = simulation.nodes();
+    let voters_known: Vec<usize> = nodes
+        .iter()
+        .map(|node| node.votes.get(&0).map_or(0, |vote_info| vote_info.voters().len()))
+        .collect();
+    let num_voted = nodes.iter().filter(|node| node.has_voted_for(0)).count();
+    nodes
+        .iter()
+        .zip(voters_known)
+        .enumerate()
+        .map(|(i, (node, voters_known))| {
+            let position = simulation.mobility().and_then(|mobility| mobility.position(i));
+            NodeFrame {
+                round: simulation.round(),
+                node: i,
+                voted: node.has_voted_for(0),
+                voters_known,
+                has_quorum: node.has_quorum_for(0),
+                knows_every_voter: voters_known == num_voted,
+                x: position.map(|(x, _)| x),
+                y: position.map(|(_, y)| y),
+            }
+        })
+        .collect()
+}
+
+/// Remove the frames of an earlier run from `output_dir`, so that a shorter run doesn't leave
+/// the later frames of a longer one behind.
+fn remove_frames(output_dir: &str) -> Result<(), Box<dyn Error>> {
+    let entries =
+        fs::read_dir(output_dir).map_err(|e| format!("failed to read {}: {}", output_dir, e))?;
+    for entry in entries {
+        let path = entry?.path();
+        let name = path.file_name().and_then(|name| name.to_str());
+        let is_frame = name.is_some_and(|name| name.starts_with("frame-"));
+        if is_frame && path.is_file() {
+            fs::remove_file(&path)
+                .map_err(|e| format!("failed to remove {}: {}", path.display(), e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Run `params` once until every node has a quorum or the deadline passes, writing a frame for
+/// every round to `output_dir` in place of any frames already there, and return the number of
+/// frames written.
+pub fn write_frames<R: Rng>(
+    params: &Params,
+    rng: &mut R,
+    output_dir: &str,
+    format: OutputFormat,
+) -> Result<usize, Box<dyn Error>> {
+    let quorum = load_quorum_rule(params)?;
+    let strategy = load_strategy(params, rng)?;
+    let mut simulation = Simulation::new(params, &quorum)?;
+    fs::create_dir_all(output_dir)
+        .map_err(|e| format!("failed to create {}: {}", output_dir, e))?;
+    remove_frames(output_dir)?;
+
+    loop {
+        let filename = format!("frame-{:05}.{}", simulation.round(), format.extension());
+        let path = Path::new(output_dir).join(filename);
+        write_results(&path.to_string_lossy(), frame(&simulation), format)?;
+        if simulation.is_finished() {
+            return Ok(simulation.round() + 1);
+        }
+        simulation.step(&*strategy, rng)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::seeded_rng;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    /// A fresh directory for the frames of the test `name`.
+    fn output_dir(name: &str) -> String {
+        let name = format!("gossip-frames-{}-{}", name, std::process::id());
+        let dir = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        dir.to_str().unwrap().into()
+    }
+
+    /// The rows of the frame after `round` rounds in `output_dir`, by column.
+    fn read_frame(output_dir: &str, round: usize) -> Vec<HashMap<String, String>> {
+        let path = Path::new(output_dir).join(format!("frame-{:05}.csv", round));
+        let mut reader = csv::Reader::from_path(path).unwrap();
+        reader.deserialize().map(Result::unwrap).collect()
+    }
+
+    fn frame_names(output_dir: &str) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(output_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn params(extra: serde_json::Value) -> Params {
+        let mut params = json!({"n": 20, "k": 14, "voting_steps": 2});
+        params.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(params).unwrap()
+    }
+
+    #[test]
+    fn writes_a_frame_before_every_round_and_after_the_last() {
+        let dir = output_dir("rounds");
+        let params = params(json!({}));
+        let num_frames = write_frames(&params, &mut seeded_rng(4), &dir, OutputFormat::Csv);
+        let num_frames = num_frames.unwrap();
+        let rounds = crate::simulate_runs(&params, 1, &mut seeded_rng(4), &mut |_| {}).unwrap();
+        assert_eq!(num_frames as f64, rounds[0].num_iterations + 1.0);
+        let expected: Vec<_> = (0..num_frames).map(|r| format!("frame-{:05}.csv", r)).collect();
+        assert_eq!(frame_names(&dir), expected);
+
+        let first = read_frame(&dir, 0);
+        assert_eq!(first.len(), 20);
+        assert!(first.iter().all(|node| node["round"] == "0" && node["has_quorum"] == "false"));
+        let last = read_frame(&dir, num_frames - 1);
+        assert!(last.iter().all(|node| node["has_quorum"] == "true"));
+        // Without mobility, the nodes have no positions.
+        assert!(last.iter().all(|node| node["x"].is_empty() && node["y"].is_empty()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn places_moving_nodes_from_the_first_round() {
+        let dir = output_dir("mobility");
+        let params = params(json!({"mobility": "waypoint:0.5:0.05", "max_rounds": 5}));
+        let num_frames = write_frames(&params, &mut seeded_rng(4), &dir, OutputFormat::Csv);
+        let num_frames = num_frames.unwrap();
+        assert!(num_frames > 1);
+        assert!(read_frame(&dir, 0).iter().all(|node| node["x"].is_empty()));
+        for node in read_frame(&dir, 1) {
+            let (x, y): (f64, f64) = (node["x"].parse().unwrap(), node["y"].parse().unwrap());
+            assert!((0.0..=1.0).contains(&x) && (0.0..=1.0).contains(&y));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn replaces_only_earlier_frames() {
+        let dir = output_dir("replace");
+        fs::create_dir_all(Path::new(&dir).join("frame-dir")).unwrap();
+        for name in ["frame-99999.csv", "frame-00000.json", "notes.txt"] {
+            fs::write(Path::new(&dir).join(name), "old").unwrap();
+        }
+        let params = params(json!({}));
+        let num_frames = write_frames(&params, &mut seeded_rng(4), &dir, OutputFormat::Csv);
+        let num_frames = num_frames.unwrap();
+        let names = frame_names(&dir);
+        assert_eq!(names.len(), num_frames + 2);
+        assert!(names.contains(&"frame-dir".to_string()));
+        assert!(names.contains(&"notes.txt".to_string()));
+        assert!(!names.iter().any(|name| name == "frame-99999.csv" || name.ends_with(".json")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
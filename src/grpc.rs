@@ -0,0 +1,290 @@
+//! gRPC service for running simulations on behalf of a remote controller.
+//!
+//! Jobs are run one per blocking thread as soon as they're submitted. Their per-round metrics
+//! and final results are kept in memory for the lifetime of the server.
+
+use rand::weak_rng;
+use std::collections::HashMap;
+use std::error::Error;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, watch};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+
+use crate::{
+    seeded_rng, simulate_params, validate_params, Params, RoundMetrics, SimulationResult,
+    REPETITIONS,
+};
+
+mod pb {
+    tonic::include_proto!("gossip");
+}
+
+use self::pb::simulator_server::{Simulator, SimulatorServer};
+
+/// Progress of a submitted simulation.
+#[derive(Default)]
+struct JobState {
+    metrics: Vec<pb::RoundMetrics>,
+    /// Set once the job finishes, with an error message if it failed.
+    result: Option<Result<pb::SimulationResult, String>>,
+}
+
+struct Job {
+    state: Mutex<JobState>,
+    /// Signalled whenever `state` changes.
+    updated: watch::Sender<()>,
+}
+
+impl Job {
+    fn new() -> Self {
+        Job {
+            state: Mutex::new(JobState::default()),
+            updated: watch::channel(()).0,
+        }
+    }
+
+    fn update<F: FnOnce(&mut JobState)>(&self, f: F) {
+        f(&mut self.state.lock().unwrap());
+        self.updated.send_replace(());
+    }
+}
+
+#[derive(Default)]
+struct SimulatorService {
+    jobs: Mutex<HashMap<u64, Arc<Job>>>,
+    next_id: AtomicU64,
+}
+
+impl SimulatorService {
+    // `Status` is large, but it's what tonic handlers return anyway.
+    #[allow(clippy::result_large_err)]
+    fn job(&self, id: &pb::JobId) -> Result<Arc<Job>, Status> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .get(&id.id)
+            .cloned()
+            .ok_or_else(|| Status::not_found(format!("no job with ID {}", id.id)))
+    }
+}
+
+#[tonic::async_trait]
+impl Simulator for SimulatorService {
+    async fn submit(&self, request: Request<pb::Params>) -> Result<Response<pb::JobId>, Status> {
+        let request = request.into_inner();
+        let partners = match request.partners {
+            Some(ref mode) => Some(mode.parse().map_err(Status::invalid_argument)?),
+            None => None,
+        };
+        let mode = match request.mode {
+            Some(ref mode) => Some(mode.parse().map_err(Status::invalid_argument)?),
+            None => None,
+        };
+        let rate_limit = match request.rate_limit {
+            Some(ref limit) => Some(limit.parse().map_err(Status::invalid_argument)?),
+            None => None,
+        };
+        let rate_limit_policy = match request.rate_limit_policy {
+            Some(ref policy) => Some(policy.parse().map_err(Status::invalid_argument)?),
+            None => None,
+        };
+        let election = match request.election {
+            Some(ref rank) => Some(rank.parse().map_err(Status::invalid_argument)?),
+            None => None,
+        };
+        let params = Params {
+            n: request.n as usize,
+            k: request.k as usize,
+            voting_steps: request.voting_steps as usize,
+            script: request.script,
+            quorum: request.quorum,
+            partners,
+            timings: request.timings,
+            mode,
+            seed: request.seed,
+            label: request.label,
+            loss: request.loss,
+            max_messages_per_round: request.max_messages_per_round.map(|max| max as usize),
+            rate_limit,
+            rate_limit_policy,
+            cost: request.cost,
+            cohorts: request.cohorts,
+            subnets: request.subnets,
+            max_rounds: request.max_rounds.map(|max| max as usize),
+            membership: request.membership,
+            election,
+            mobility: request.mobility,
+        };
+        validate_params(&params).map_err(Status::invalid_argument)?;
+        let repetitions = match request.repetitions {
+            0 => REPETITIONS,
+            r => r as usize,
+        };
+
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let job = Arc::new(Job::new());
+        self.jobs.lock().unwrap().insert(id, job.clone());
+
+        tokio::task::spawn_blocking(move || {
+            let mut rng = params.seed.map_or_else(weak_rng, seeded_rng);
+            let result = simulate_params(&params, repetitions, &mut rng, &mut |metrics| {
+                job.update(|state| state.metrics.push(metrics.into()))
+            });
+            let result = result.map(|r| (&r).into()).map_err(|e| e.to_string());
+            job.update(|state| state.result = Some(result));
+        });
+
+        Ok(Response::new(pb::JobId { id }))
+    }
+
+    type StreamMetricsStream = ReceiverStream<Result<pb::RoundMetrics, Status>>;
+
+    async fn stream_metrics(
+        &self,
+        request: Request<pb::JobId>,
+    ) -> Result<Response<Self::StreamMetricsStream>, Status> {
+        let job = self.job(request.get_ref())?;
+        let (tx, rx) = mpsc::channel(64);
+
+        tokio::spawn(async move {
+            let mut updated = job.updated.subscribe();
+            let mut sent = 0;
+            loop {
+                let (pending, done) = {
+                    let state = job.state.lock().unwrap();
+                    (state.metrics[sent..].to_vec(), state.result.is_some())
+                };
+                sent += pending.len();
+                for metrics in pending {
+                    if tx.send(Ok(metrics)).await.is_err() {
+                        // Client went away.
+                        return;
+                    }
+                }
+                if done || updated.changed().await.is_err() {
+                    return;
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn get_result(
+        &self,
+        request: Request<pb::JobId>,
+    ) -> Result<Response<pb::SimulationResult>, Status> {
+        let job = self.job(request.get_ref())?;
+        let mut updated = job.updated.subscribe();
+        loop {
+            if let Some(ref result) = job.state.lock().unwrap().result {
+                return result.clone().map(Response::new).map_err(Status::internal);
+            }
+            updated
+                .changed()
+                .await
+                .map_err(|_| Status::internal("job was dropped"))?;
+        }
+    }
+}
+
+impl From<&RoundMetrics> for pb::RoundMetrics {
+    fn from(metrics: &RoundMetrics) -> Self {
+        pb::RoundMetrics {
+            repetition: metrics.repetition as u64,
+            round: metrics.round as u64,
+            nodes_with_quorum: metrics.nodes_with_quorum as u64,
+            num_exchanges: metrics.num_exchanges as u64,
+            num_vote_exchanges: metrics.num_vote_exchanges as u64,
+            times: metrics.times.map(|times| pb::PhaseTimes {
+                gossip: times.gossip,
+                merge: times.merge,
+                apply: times.apply,
+                convergence: times.convergence,
+            }),
+        }
+    }
+}
+
+impl From<&SimulationResult> for pb::SimulationResult {
+    fn from(result: &SimulationResult) -> Self {
+        pb::SimulationResult {
+            n: result.n as u64,
+            k: result.k as u64,
+            voting_steps: result.voting_steps as u64,
+            script: result.script.clone(),
+            quorum: result.quorum.clone(),
+            partners: result.partners.name().to_string(),
+            timings: result.timings,
+            mode: result.mode.map(|mode| mode.name().to_string()),
+            seed: result.seed,
+            label: result.label.clone(),
+            loss: result.loss,
+            max_messages_per_round: result.max_messages_per_round.map(|max| max as u64),
+            rate_limit: result.rate_limit.name().to_string(),
+            rate_limit_policy: result.rate_limit_policy.name().to_string(),
+            num_rate_limited: result.num_rate_limited as u64,
+            cost: result.cost.clone(),
+            total_cost: result.total_cost,
+            mean_node_cost: result.mean_node_cost,
+            max_node_cost: result.max_node_cost,
+            cohorts: result.cohorts.clone(),
+            push_pull_quorum_rounds: result.push_pull_quorum_rounds,
+            push_pull_mean_quorum_rounds: result.push_pull_mean_quorum_rounds,
+            push_quorum_rounds: result.push_quorum_rounds,
+            push_mean_quorum_rounds: result.push_mean_quorum_rounds,
+            pull_quorum_rounds: result.pull_quorum_rounds,
+            pull_mean_quorum_rounds: result.pull_mean_quorum_rounds,
+            subnets: result.subnets.clone(),
+            fastest_subnet_rounds: result.fastest_subnet_rounds,
+            slowest_subnet_rounds: result.slowest_subnet_rounds,
+            num_cross_subnet_exchanges: result.num_cross_subnet_exchanges as u64,
+            max_rounds: result.max_rounds.map(|max| max as u64),
+            missed_deadline: result.missed_deadline,
+            membership: result.membership.clone(),
+            detection_rounds: result.detection_rounds,
+            mean_detection_rounds: result.mean_detection_rounds,
+            false_suspicions: result.false_suspicions,
+            false_positives: result.false_positives,
+            num_membership_messages: result.num_membership_messages as u64,
+            election: result.election.map(|rank| rank.name().to_string()),
+            leader_changes: result.leader_changes,
+            max_leader_changes: result.max_leader_changes,
+            mobility: result.mobility.clone(),
+            mean_neighbours: result.mean_neighbours,
+            isolated_fraction: result.isolated_fraction,
+            num_iterations: result.num_iterations,
+            num_exchanges: result.num_exchanges as u64,
+            num_vote_exchanges: result.num_vote_exchanges as u64,
+            average_votes_held: result.average_votes_held,
+            predicted_iterations: result.predicted_iterations,
+            predicted_exchanges: result.predicted_exchanges,
+            iterations_ratio: result.iterations_ratio,
+            exchanges_ratio: result.exchanges_ratio,
+            quorum_rounds_50: result.quorum_rounds_50,
+            quorum_rounds_90: result.quorum_rounds_90,
+            quorum_rounds_99: result.quorum_rounds_99,
+            quorum_rounds_100: result.quorum_rounds_100,
+            all_voters_rounds_50: result.all_voters_rounds_50,
+            all_voters_rounds_90: result.all_voters_rounds_90,
+            all_voters_rounds_99: result.all_voters_rounds_99,
+            all_voters_rounds_100: result.all_voters_rounds_100,
+        }
+    }
+}
+
+/// Serve the gRPC API on `addr` until the process is killed.
+pub fn serve(addr: SocketAddr) -> Result<(), Box<dyn Error>> {
+    let runtime = tokio::runtime::Runtime::new()?;
+    println!("Serving simulations over gRPC on {}", addr);
+    runtime.block_on(
+        Server::builder()
+            .add_service(SimulatorServer::new(SimulatorService::default()))
+            .serve(addr),
+    )?;
+    Ok(())
+}
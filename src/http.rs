@@ -0,0 +1,190 @@
+//! Minimal HTTP/JSON API for launching simulations and collecting their results.
+//!
+//! * `POST /simulations` with a JSON parameter set starts a simulation and returns its ID.
+//! * `GET /simulations/<id>` reports whether it is running, finished or failed.
+//! * `GET /simulations/<id>/result` returns the averaged result once finished.
+//! * `GET /simulations/<id>/metrics` returns the per-round time series recorded so far.
+//!
+//! Each connection and each simulation gets its own thread, which is plenty for a dashboard.
+
+use rand::weak_rng;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tracing::warn;
+
+use crate::{
+    seeded_rng, simulate_params, validate_params, Params, RoundMetrics, SimulationResult,
+    REPETITIONS,
+};
+
+/// Largest request body accepted, which is far more than any parameter set needs.
+const MAX_BODY_BYTES: usize = 1 << 20;
+
+/// Body of a `POST /simulations` request.
+#[derive(Deserialize)]
+struct SubmitRequest {
+    #[serde(flatten)]
+    params: Params,
+    #[serde(default)]
+    repetitions: Option<usize>,
+}
+
+#[derive(Default)]
+struct Job {
+    metrics: Vec<RoundMetrics>,
+    /// Set once the job finishes, with an error message if it failed.
+    result: Option<Result<SimulationResult, String>>,
+}
+
+#[derive(Default)]
+struct Jobs {
+    jobs: Mutex<HashMap<usize, Arc<Mutex<Job>>>>,
+}
+
+struct Response {
+    status: &'static str,
+    body: Value,
+}
+
+impl Response {
+    fn ok(body: Value) -> Self {
+        Response { status: "200 OK", body }
+    }
+
+    fn error(status: &'static str, message: &str) -> Self {
+        Response { status, body: json!({ "error": message }) }
+    }
+}
+
+impl Jobs {
+    fn submit(&self, body: &[u8]) -> Response {
+        let request: SubmitRequest = match serde_json::from_slice(body) {
+            Ok(request) => request,
+            Err(e) => return Response::error("400 Bad Request", &e.to_string()),
+        };
+        if let Err(e) = validate_params(&request.params) {
+            return Response::error("400 Bad Request", &e);
+        }
+        if request.repetitions == Some(0) {
+            return Response::error("400 Bad Request", "repetitions must be at least 1");
+        }
+
+        let job = Arc::new(Mutex::new(Job::default()));
+        let id = {
+            let mut jobs = self.jobs.lock().unwrap();
+            let id = jobs.len();
+            jobs.insert(id, job.clone());
+            id
+        };
+
+        let params = request.params;
+        let repetitions = request.repetitions.unwrap_or(REPETITIONS);
+        thread::spawn(move || {
+            let mut rng = params.seed.map_or_else(weak_rng, seeded_rng);
+            let result = simulate_params(&params, repetitions, &mut rng, &mut |metrics| {
+                job.lock().unwrap().metrics.push(metrics.clone())
+            });
+            job.lock().unwrap().result = Some(result.map_err(|e| e.to_string()));
+        });
+
+        Response { status: "201 Created", body: json!({ "id": id }) }
+    }
+
+    fn get(&self, id: &str, view: Option<&str>) -> Response {
+        let job = match id.parse().ok().and_then(|id: usize| self.jobs.lock().unwrap().get(&id).cloned()) {
+            Some(job) => job,
+            None => return Response::error("404 Not Found", &format!("no simulation with ID {}", id)),
+        };
+        let job = job.lock().unwrap();
+
+        match (view, &job.result) {
+            (None, &None) => Response::ok(json!({
+                "status": "running",
+                "rounds_completed": job.metrics.len(),
+            })),
+            (None, &Some(Ok(_))) => Response::ok(json!({
+                "status": "finished",
+                "rounds_completed": job.metrics.len(),
+            })),
+            (None, &Some(Err(ref e))) => Response::ok(json!({ "status": "failed", "error": e })),
+            (Some("metrics"), _) => Response::ok(json!(job.metrics)),
+            (Some("result"), &Some(Ok(ref result))) => Response::ok(json!(result)),
+            (Some("result"), &Some(Err(ref e))) => Response::error("500 Internal Server Error", e),
+            (Some("result"), &None) => Response::error("409 Conflict", "simulation is still running"),
+            (Some(_), _) => Response::error("404 Not Found", "unknown resource"),
+        }
+    }
+
+    fn route(&self, method: &str, path: &str, body: &[u8]) -> Response {
+        let segments: Vec<_> = path.trim_matches('/').split('/').collect();
+        match (method, segments.as_slice()) {
+            ("POST", ["simulations"]) => self.submit(body),
+            ("GET", ["simulations", id]) => self.get(id, None),
+            ("GET", ["simulations", id, view]) => self.get(id, Some(view)),
+            _ => Response::error("404 Not Found", &format!("no route for {} {}", method, path)),
+        }
+    }
+}
+
+fn handle_connection(mut stream: TcpStream, jobs: &Jobs) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("").to_string();
+    let path = parts.next().unwrap_or("").to_string();
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    let response = if content_length > MAX_BODY_BYTES {
+        let message = format!("request body is over the limit of {} bytes", MAX_BODY_BYTES);
+        Response::error("413 Payload Too Large", &message)
+    } else {
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body)?;
+        jobs.route(&method, &path, &body)
+    };
+    let body = response.body.to_string();
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+/// Serve the HTTP API on `addr` until the process is killed.
+pub fn serve(addr: &str) -> Result<(), Box<dyn Error>> {
+    let listener = TcpListener::bind(addr)?;
+    let jobs = Arc::new(Jobs::default());
+    println!("Serving simulations over HTTP on {}", listener.local_addr()?);
+
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let jobs = jobs.clone();
+        thread::spawn(move || {
+            if let Err(e) = handle_connection(stream, &jobs) {
+                warn!("HTTP connection error: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
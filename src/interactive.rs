@@ -0,0 +1,198 @@
+//! An interactive prompt for stepping through a single run of the simulation.
+//!
+//! The run uses the simulation with a `Node` for every node, whatever the parameters' `mode`, so
+//! that every node can be inspected. The prompt takes these commands:
+//!
+//! ```text
+//! step [rounds]     run the next round, or that many rounds
+//! run               run rounds until every node has a quorum, or the deadline
+//! show node <id>    whether a node has voted or has a quorum, and the voters it knows of
+//! show quorum       how many nodes have a quorum, and how many voters one takes
+//! inject vote [id]  have a node vote now, by default the first one that hasn't
+//! help              list the commands
+//! quit              leave, as does the end of the input
+//! ```
+
+use rand::Rng;
+use std::error::Error;
+use std::io::{BufRead, Write};
+use std::sync::Arc;
+
+use crate::quorum::QuorumRule;
+use crate::{load_quorum_rule, load_strategy, NodeId, Params, Simulation, Strategy};
+
+/// Voters listed by `show node` before the rest are left out.
+const MAX_VOTERS_SHOWN: usize = 32;
+
+const HELP: &str = "\
+step [rounds]     run the next round, or that many rounds
+run               run rounds until every node has a quorum, or the deadline
+show node <id>    whether a node has voted or has a quorum, and the voters it knows of
+show quorum       how many nodes have a quorum, and how many voters one takes
+inject vote [id]  have a node vote now, by default the first one that hasn't
+help              list the commands
+quit              leave, as does the end of the input";
+
+/// A run being stepped through at the prompt.
+struct Session<'a, R: Rng> {
+    params: &'a Params,
+    simulation: Simulation,
+    strategy: Box<dyn Strategy<R>>,
+    quorum: Arc<dyn QuorumRule>,
+    rng: &'a mut R,
+}
+
+impl<'a, R: Rng> Session<'a, R> {
+    /// Run up to `rounds` rounds, stopping early once every node has a quorum or at the deadline.
+    fn step<W: Write>(&mut self, rounds: usize, output: &mut W) -> Result<(), Box<dyn Error>> {
+        for _ in 0..rounds {
+            if self.simulation.is_finished() {
+                break;
+            }
+            let metrics = self.simulation.step(&*self.strategy, self.rng)?;
+            writeln!(
+                output,
+                "round {}: {} exchanges, {} votes sent, {} of {} nodes have a quorum",
+                metrics.round,
+                metrics.num_exchanges,
+                metrics.num_vote_exchanges,
+                metrics.nodes_with_quorum,
+                self.params.n
+            )?;
+        }
+        if self.simulation.missed_deadline() {
+            writeln!(
+                output,
+                "the deadline of {} rounds passed with {} of {} nodes having a quorum",
+                self.simulation.round(),
+                self.simulation.nodes_with_quorum(),
+                self.params.n
+            )?;
+        } else if self.simulation.is_finished() {
+            writeln!(output, "every node has a quorum after {} rounds", self.simulation.round())?;
+        }
+        Ok(())
+    }
+
+    fn show_node<W: Write>(&self, id: NodeId, output: &mut W) -> Result<(), Box<dyn Error>> {
+        let node = self
+            .simulation
+            .nodes()
+            .get(id as usize)
+            .ok_or_else(|| format!("there's no node {}, only 0 to {}", id, self.params.n - 1))?;
+        let voters: Vec<NodeId> = match node.votes.get(&0) {
+            Some(vote_info) => vote_info.voters().iter().collect(),
+            None => vec![],
+        };
+        let mut shown: Vec<String> =
+            voters.iter().take(MAX_VOTERS_SHOWN).map(|voter| voter.to_string()).collect();
+        if voters.len() > MAX_VOTERS_SHOWN {
+            shown.push(format!("and {} more", voters.len() - MAX_VOTERS_SHOWN));
+        }
+        writeln!(
+            output,
+            "node {}: {}, {}, knows of {} voters: [{}]",
+            id,
+            if node.has_voted_for(0) { "voted" } else { "hasn't voted" },
+            if node.has_quorum_for(0) { "has a quorum" } else { "has no quorum" },
+            voters.len(),
+            shown.join(", ")
+        )?;
+        Ok(())
+    }
+
+    fn show_quorum<W: Write>(&self, output: &mut W) -> Result<(), Box<dyn Error>> {
+        let rule = self.params.quorum.as_deref().unwrap_or("majority");
+        let takes = match self.quorum.min_voters(self.params.n) {
+            Some(min_voters) => format!("takes {} voters", min_voters),
+            None => "depends on who voted".to_string(),
+        };
+        writeln!(
+            output,
+            "after {} rounds, {} of {} nodes have a quorum, which under {} {}",
+            self.simulation.round(),
+            self.simulation.nodes_with_quorum(),
+            self.params.n,
+            rule,
+            takes
+        )?;
+        Ok(())
+    }
+
+    /// Have node `id` vote, or the first node that hasn't if `id` is `None`.
+    fn inject_vote<W: Write>(&mut self, id: Option<NodeId>, output: &mut W)
+        -> Result<(), Box<dyn Error>>
+    {
+        let id = match id {
+            Some(id) => id,
+            None => (0..self.params.n as NodeId)
+                .find(|&id| !self.simulation.nodes()[id as usize].has_voted_for(0))
+                .ok_or("every node has voted already")?,
+        };
+        match self.simulation.inject_vote(id)? {
+            true => writeln!(output, "node {} voted", id)?,
+            false => writeln!(output, "node {} had voted already", id)?,
+        }
+        Ok(())
+    }
+
+    /// Carry out one command, returning `false` to leave the prompt.
+    fn command<W: Write>(&mut self, line: &str, output: &mut W) -> Result<bool, Box<dyn Error>> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words[..] {
+            [] => (),
+            ["step"] => self.step(1, output)?,
+            ["step", rounds] => self.step(parse(rounds, "number of rounds")?, output)?,
+            ["run"] => self.step(usize::MAX, output)?,
+            ["show", "node", id] => self.show_node(parse(id, "node ID")?, output)?,
+            ["show", "quorum"] => self.show_quorum(output)?,
+            ["inject", "vote"] => self.inject_vote(None, output)?,
+            ["inject", "vote", id] => self.inject_vote(Some(parse(id, "node ID")?), output)?,
+            ["help"] => writeln!(output, "{}", HELP)?,
+            ["quit"] | ["exit"] => return Ok(false),
+            _ => return Err(From::from(format!("unknown command {:?}, try help", line.trim()))),
+        }
+        Ok(true)
+    }
+}
+
+fn parse<T: std::str::FromStr>(word: &str, what: &str) -> Result<T, String> {
+    word.parse().map_err(|_| format!("invalid {} {:?}", what, word))
+}
+
+/// Step through a run of `params` with commands read from `input`, writing what they show to
+/// `output`. A prompt is written before each command if `prompt` is set.
+pub fn run<R: Rng, I: BufRead, W: Write>(
+    params: &Params,
+    rng: &mut R,
+    input: I,
+    mut output: W,
+    prompt: bool,
+) -> Result<(), Box<dyn Error>> {
+    let quorum = load_quorum_rule(params)?;
+    let mut session = Session {
+        params,
+        simulation: Simulation::new(params, &quorum)?,
+        strategy: load_strategy(params, rng)?,
+        quorum,
+        rng,
+    };
+
+    let mut lines = input.lines();
+    loop {
+        if prompt {
+            write!(output, "> ")?;
+            output.flush()?;
+        }
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => return Ok(()),
+        };
+        match session.command(&line, &mut output) {
+            Ok(true) => (),
+            Ok(false) => return Ok(()),
+            // Mistakes at the prompt only cost the command.
+            Err(e) => writeln!(output, "error: {}", e)?,
+        }
+    }
+}
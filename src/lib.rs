@@ -0,0 +1,1979 @@
+extern crate csv;
+extern crate rand;
+#[cfg(feature = "scripting")]
+extern crate rhai;
+#[macro_use]
+extern crate serde_derive;
+
+pub mod arena;
+pub mod check;
+pub mod cohort;
+pub mod compact;
+pub mod config;
+pub mod cost;
+pub mod daemon;
+pub mod distributed;
+pub mod election;
+pub mod epidemic;
+pub mod filter;
+pub mod frames;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod interactive;
+pub mod membership;
+pub mod mobility;
+#[cfg(feature = "gossipsub")]
+pub mod p2p;
+pub mod preset;
+pub mod progress;
+pub mod quorum;
+pub mod rate_limit;
+#[cfg(feature = "scripting")]
+mod script;
+pub mod stats;
+pub mod subnet;
+pub mod trace;
+pub mod voters;
+
+use rand::{Rng, SeedableRng, XorShiftRng};
+use std::io::{self, Write};
+use std::fs::{self, File};
+use std::path::Path;
+use std::error::Error;
+use std::fmt;
+use std::collections::BTreeMap;
+use serde::Serialize;
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+
+use crate::arena::RoundArena;
+use crate::cohort::{parse_cohorts, Cohorts};
+use crate::cost::{load_cost_model, Costs};
+use crate::election::ElectionRank;
+use crate::membership::{parse_membership, Membership};
+use crate::mobility::{parse_mobility, Mobility};
+use crate::quorum::{parse_quorum_rule, Majority, QuorumRule};
+use crate::rate_limit::{RateLimit, RateLimitPolicy, RateLimiter};
+use crate::subnet::{parse_subnets, SubnetStats, SubnetStrategy};
+use crate::voters::{Difference, VoterSet};
+
+/// Parameters to run the simulation with.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Params {
+    pub n: usize,
+    pub k: usize,
+    pub voting_steps: usize,
+    /// Optional path to a script overriding partner selection and diff filtering.
+    #[serde(default)]
+    pub script: Option<String>,
+    /// Optional quorum rule spec (see the `quorum` module), defaulting to a simple majority.
+    #[serde(default)]
+    pub quorum: Option<String>,
+    /// How gossip partners are assigned each round, defaulting to `PartnerMode::Random`.
+    #[serde(default)]
+    pub partners: Option<PartnerMode>,
+    /// Whether to time each phase of every round, recording them in the `RoundMetrics`.
+    #[serde(default)]
+    pub timings: bool,
+    /// Which simulation to run, defaulting to compact mode wherever it supports the parameters.
+    #[serde(default)]
+    pub mode: Option<SimulationMode>,
+    /// Seed for the generator of these parameters' runs, overriding the one derived from the
+    /// seed of the whole run.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Free-form name for the experiment the parameters belong to, copied into the result.
+    #[serde(default)]
+    pub label: Option<String>,
+    /// Probability that each message is lost on the way, from 0 (the default) up to but not
+    /// including 1. The sender still counts a lost message's exchange and votes as sent.
+    #[serde(default)]
+    pub loss: f64,
+    /// Most messages each node can send or receive in a round, without a limit by default (see
+    /// the `rate_limit` module).
+    #[serde(default)]
+    pub max_messages_per_round: Option<usize>,
+    /// Which messages the limit applies to, defaulting to `RateLimit::Both`.
+    #[serde(default)]
+    pub rate_limit: Option<RateLimit>,
+    /// What happens to exchanges over the limit, defaulting to `RateLimitPolicy::Drop`.
+    #[serde(default)]
+    pub rate_limit_policy: Option<RateLimitPolicy>,
+    /// Optional prices of the messages sent (see the `cost` module), without which nothing is
+    /// charged.
+    #[serde(default)]
+    pub cost: Option<String>,
+    /// Optional cohorts of nodes running other protocols than push-pull (see the `cohort`
+    /// module).
+    #[serde(default)]
+    pub cohorts: Option<String>,
+    /// Optional subnets that nodes other than bridges only gossip within (see the `subnet`
+    /// module).
+    #[serde(default)]
+    pub subnets: Option<String>,
+    /// Rounds after which a run stops whether or not every node has a quorum, like the deadline
+    /// of a slot, without a deadline by default.
+    #[serde(default)]
+    pub max_rounds: Option<usize>,
+    /// Optional crashes and SWIM-style failure detection alongside the votes (see the
+    /// `membership` module).
+    #[serde(default)]
+    pub membership: Option<String>,
+    /// How to rank the candidates of a leader election held instead of the vote (see the
+    /// `election` module), without one by default.
+    #[serde(default)]
+    pub election: Option<ElectionRank>,
+    /// Optional movement of the nodes, which can then only gossip with those in range (see the
+    /// `mobility` module).
+    #[serde(default)]
+    pub mobility: Option<String>,
+}
+
+/// The fields of `Params`, which are the columns a parameters CSV can have.
+pub const PARAM_FIELDS: &[&str] = &[
+    "n", "k", "voting_steps", "script", "quorum", "partners", "timings", "mode", "seed", "label",
+    "loss", "max_messages_per_round", "rate_limit", "rate_limit_policy", "cost",
+    "cohorts", "subnets", "max_rounds", "membership",
+    "election", "mobility",
+];
+
+/// Which simulation runs a set of parameters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SimulationMode {
+    /// The struct-of-arrays simulation in the `compact` module, for the default strategy with a
+    /// quorum rule that only counts voters.
+    Compact,
+    /// The simulation with a `Node` for every node, which supports everything.
+    General,
+}
+
+impl SimulationMode {
+    /// The name of the mode, as parsed by `from_str`.
+    pub fn name(self) -> &'static str {
+        match self {
+            SimulationMode::Compact => "compact",
+            SimulationMode::General => "general",
+        }
+    }
+}
+
+impl std::str::FromStr for SimulationMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "compact" => Ok(SimulationMode::Compact),
+            "general" => Ok(SimulationMode::General),
+            _ => Err(format!("unknown simulation mode {:?}, expected compact or general", s)),
+        }
+    }
+}
+
+/// How gossip partners are assigned each round.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PartnerMode {
+    /// Every node picks a partner uniformly at random, so some nodes are contacted several times
+    /// in a round and others not at all.
+    Random,
+    /// Partners follow a random cyclic permutation, so every node is contacted exactly once.
+    Matched,
+}
+
+impl PartnerMode {
+    /// The name of the mode, as parsed by `from_str`.
+    pub fn name(self) -> &'static str {
+        match self {
+            PartnerMode::Random => "random",
+            PartnerMode::Matched => "matched",
+        }
+    }
+}
+
+impl std::str::FromStr for PartnerMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "random" => Ok(PartnerMode::Random),
+            "matched" => Ok(PartnerMode::Matched),
+            _ => Err(format!("unknown partner mode {:?}, expected random or matched", s)),
+        }
+    }
+}
+
+/// Result to write to the output CSV, starting with the effective value of every parameter.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SimulationResult {
+    pub n: usize,
+    pub k: usize,
+    pub voting_steps: usize,
+    pub script: Option<String>,
+    pub quorum: String,
+    pub partners: PartnerMode,
+    pub timings: bool,
+    /// The simulation that produced the result, unless it didn't come from the simulator.
+    pub mode: Option<SimulationMode>,
+    /// The seed of the generator for the runs, if it was seeded.
+    pub seed: Option<u64>,
+    pub label: Option<String>,
+    #[serde(default)]
+    pub loss: f64,
+    #[serde(default)]
+    pub max_messages_per_round: Option<usize>,
+    #[serde(default)]
+    pub rate_limit: RateLimit,
+    #[serde(default)]
+    pub rate_limit_policy: RateLimitPolicy,
+    #[serde(default)]
+    pub cost: Option<String>,
+    #[serde(default)]
+    pub cohorts: Option<String>,
+    #[serde(default)]
+    pub subnets: Option<String>,
+    #[serde(default)]
+    pub max_rounds: Option<usize>,
+    #[serde(default)]
+    pub membership: Option<String>,
+    #[serde(default)]
+    pub election: Option<ElectionRank>,
+    #[serde(default)]
+    pub mobility: Option<String>,
+    pub num_iterations: f64,
+    pub num_exchanges: usize,
+    pub num_vote_exchanges: usize,
+    pub average_votes_held: f64,
+    /// The fraction of the runs that reached `max_rounds` before every node had a quorum.
+    #[serde(default)]
+    pub missed_deadline: f64,
+    /// Messages stopped by the rate limit, whether refused (which still count as sent) or never
+    /// sent.
+    #[serde(default)]
+    pub num_rate_limited: usize,
+    /// What the messages sent cost under the `cost` prices, in total and for the average and
+    /// most expensive node, with the sender paying for each one.
+    #[serde(default)]
+    pub total_cost: f64,
+    #[serde(default)]
+    pub mean_node_cost: f64,
+    #[serde(default)]
+    pub max_node_cost: f64,
+    /// Rounds until every node of each protocol's cohort had a quorum, and the mean rounds of its
+    /// nodes, if the `cohorts` put any nodes in it.
+    #[serde(default)]
+    pub push_pull_quorum_rounds: Option<f64>,
+    #[serde(default)]
+    pub push_pull_mean_quorum_rounds: Option<f64>,
+    #[serde(default)]
+    pub push_quorum_rounds: Option<f64>,
+    #[serde(default)]
+    pub push_mean_quorum_rounds: Option<f64>,
+    #[serde(default)]
+    pub pull_quorum_rounds: Option<f64>,
+    #[serde(default)]
+    pub pull_mean_quorum_rounds: Option<f64>,
+    /// Rounds until every node of the fastest and of the slowest subnet had a quorum, and the
+    /// exchanges between subnets, if there are `subnets`.
+    #[serde(default)]
+    pub fastest_subnet_rounds: Option<f64>,
+    #[serde(default)]
+    pub slowest_subnet_rounds: Option<f64>,
+    #[serde(default)]
+    pub num_cross_subnet_exchanges: usize,
+    /// Rounds from the crashes until every live node believed all the crashed nodes dead, and
+    /// the mean rounds for each crashed node, if there's a `membership` layer and they were all
+    /// detected.
+    #[serde(default)]
+    pub detection_rounds: Option<f64>,
+    #[serde(default)]
+    pub mean_detection_rounds: Option<f64>,
+    /// Suspicions raised about live nodes, and live nodes that some live node believes dead.
+    #[serde(default)]
+    pub false_suspicions: f64,
+    #[serde(default)]
+    pub false_positives: f64,
+    /// Pings, ping-reqs and acks sent by the membership layer.
+    #[serde(default)]
+    pub num_membership_messages: usize,
+    /// Times that nodes moved from one leader to another in an `election`, in total and for the
+    /// node that moved most.
+    #[serde(default)]
+    pub leader_changes: f64,
+    #[serde(default)]
+    pub max_leader_changes: f64,
+    /// The mean number of nodes in range of each node each round with `mobility`, and the
+    /// fraction of the time that a node had none.
+    #[serde(default)]
+    pub mean_neighbours: f64,
+    #[serde(default)]
+    pub isolated_fraction: f64,
+    /// Rounds until 50%, 90%, 99% and 100% of the nodes had a quorum, which separate the bulk of
+    /// the network converging from the stragglers. Averages are left out unless every run has one.
+    #[serde(default)]
+    pub quorum_rounds_50: Option<f64>,
+    #[serde(default)]
+    pub quorum_rounds_90: Option<f64>,
+    #[serde(default)]
+    pub quorum_rounds_99: Option<f64>,
+    #[serde(default)]
+    pub quorum_rounds_100: Option<f64>,
+    /// Rounds until those fractions of the nodes held every voter, if they did before the run
+    /// ended. Nodes aren't sent votes once they have a quorum, so they only get every voter if
+    /// their last update brings them all.
+    #[serde(default)]
+    pub all_voters_rounds_50: Option<f64>,
+    #[serde(default)]
+    pub all_voters_rounds_90: Option<f64>,
+    #[serde(default)]
+    pub all_voters_rounds_99: Option<f64>,
+    #[serde(default)]
+    pub all_voters_rounds_100: Option<f64>,
+    /// Rounds and exchanges predicted by the epidemic model for `n` nodes, and the ratio of the
+    /// measurements to them, unless it predicted none.
+    #[serde(default)]
+    pub predicted_iterations: f64,
+    #[serde(default)]
+    pub predicted_exchanges: f64,
+    #[serde(default)]
+    pub iterations_ratio: Option<f64>,
+    #[serde(default)]
+    pub exchanges_ratio: Option<f64>,
+    /// Why the simulation failed, in which case the measurements are all zero.
+    pub error: Option<String>,
+}
+
+impl SimulationResult {
+    /// A result for `params` with the effective value of each of them and no measurements yet.
+    pub fn for_params(params: &Params) -> Self {
+        SimulationResult {
+            n: params.n,
+            k: params.k,
+            voting_steps: params.voting_steps,
+            script: params.script.clone(),
+            quorum: params.quorum.clone().unwrap_or_else(|| "majority".to_string()),
+            partners: params.partners.unwrap_or(PartnerMode::Random),
+            timings: params.timings,
+            mode: params.mode,
+            seed: params.seed,
+            label: params.label.clone(),
+            loss: params.loss,
+            max_messages_per_round: params.max_messages_per_round,
+            rate_limit: params.rate_limit.unwrap_or_default(),
+            rate_limit_policy: params.rate_limit_policy.unwrap_or_default(),
+            cost: params.cost.clone(),
+            cohorts: params.cohorts.clone(),
+            subnets: params.subnets.clone(),
+            max_rounds: params.max_rounds,
+            membership: params.membership.clone(),
+            election: params.election,
+            mobility: params.mobility.clone(),
+            num_iterations: 0.0,
+            num_exchanges: 0,
+            num_vote_exchanges: 0,
+            average_votes_held: 0.0,
+            missed_deadline: 0.0,
+            num_rate_limited: 0,
+            total_cost: 0.0,
+            mean_node_cost: 0.0,
+            max_node_cost: 0.0,
+            push_pull_quorum_rounds: None,
+            push_pull_mean_quorum_rounds: None,
+            push_quorum_rounds: None,
+            push_mean_quorum_rounds: None,
+            pull_quorum_rounds: None,
+            pull_mean_quorum_rounds: None,
+            fastest_subnet_rounds: None,
+            slowest_subnet_rounds: None,
+            num_cross_subnet_exchanges: 0,
+            detection_rounds: None,
+            mean_detection_rounds: None,
+            false_suspicions: 0.0,
+            false_positives: 0.0,
+            num_membership_messages: 0,
+            leader_changes: 0.0,
+            max_leader_changes: 0.0,
+            mean_neighbours: 0.0,
+            isolated_fraction: 0.0,
+            quorum_rounds_50: None,
+            quorum_rounds_90: None,
+            quorum_rounds_99: None,
+            quorum_rounds_100: None,
+            all_voters_rounds_50: None,
+            all_voters_rounds_90: None,
+            all_voters_rounds_99: None,
+            all_voters_rounds_100: None,
+            predicted_iterations: 0.0,
+            predicted_exchanges: 0.0,
+            iterations_ratio: None,
+            exchanges_ratio: None,
+            error: None,
+        }
+    }
+
+    /// A result recording that the simulation of `params` failed with `error`.
+    pub fn failed(params: &Params, error: String) -> Self {
+        SimulationResult { error: Some(error), ..SimulationResult::for_params(params) }
+    }
+
+    /// The result with the epidemic model's predictions for `n` nodes filled in.
+    pub fn with_predictions(self) -> Self {
+        let prediction = epidemic::predict(self.n, self.loss);
+        SimulationResult {
+            predicted_iterations: prediction.rounds,
+            predicted_exchanges: prediction.exchanges,
+            iterations_ratio: epidemic::ratio(self.num_iterations, prediction.rounds),
+            exchanges_ratio: epidemic::ratio(self.num_exchanges as f64, prediction.exchanges),
+            ..self
+        }
+    }
+}
+
+/// Percentages of the nodes that a result records the rounds for them to reach, as in
+/// `SimulationResult::quorum_rounds_50`.
+pub const MILESTONES: [usize; 4] = [50, 90, 99, 100];
+
+/// The rounds after which each of the `MILESTONES` of a run were first reached, by the nodes with
+/// a quorum and by the nodes with every voter.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Milestones {
+    quorum: [Option<usize>; 4],
+    all_voters: [Option<usize>; 4],
+}
+
+impl Milestones {
+    /// Record the number of the `n` nodes with a quorum and with every voter after `rounds`
+    /// rounds.
+    pub fn record(&mut self, n: usize, rounds: usize, with_quorum: usize, with_all_voters: usize) {
+        for (i, percent) in MILESTONES.iter().enumerate() {
+            let nodes = (percent * n).div_ceil(100);
+            if with_quorum >= nodes {
+                self.quorum[i].get_or_insert(rounds);
+            }
+            if with_all_voters >= nodes {
+                self.all_voters[i].get_or_insert(rounds);
+            }
+        }
+    }
+
+    /// `result` with the milestones reached so far filled in.
+    pub fn apply_to(&self, result: SimulationResult) -> SimulationResult {
+        let [quorum_50, quorum_90, quorum_99, quorum_100] = self.quorum.map(rounds);
+        let [all_voters_50, all_voters_90, all_voters_99, all_voters_100] =
+            self.all_voters.map(rounds);
+        SimulationResult {
+            quorum_rounds_50: quorum_50,
+            quorum_rounds_90: quorum_90,
+            quorum_rounds_99: quorum_99,
+            quorum_rounds_100: quorum_100,
+            all_voters_rounds_50: all_voters_50,
+            all_voters_rounds_90: all_voters_90,
+            all_voters_rounds_99: all_voters_99,
+            all_voters_rounds_100: all_voters_100,
+            ..result
+        }
+    }
+}
+
+fn rounds(milestone: Option<usize>) -> Option<f64> {
+    milestone.map(|rounds| rounds as f64)
+}
+
+/// Statistics for a single round of one simulation run.
+#[derive(Clone, Debug, Serialize)]
+pub struct RoundMetrics {
+    /// Which of the repeated runs this round belongs to.
+    pub repetition: usize,
+    pub round: usize,
+    /// Number of nodes holding a quorum for the rumour at the end of the round.
+    pub nodes_with_quorum: usize,
+    pub num_exchanges: usize,
+    pub num_vote_exchanges: usize,
+    /// Time spent in each phase of the round, if `Params::timings` is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub times: Option<PhaseTimes>,
+}
+
+/// Wall-clock time spent in each phase of a round, in seconds.
+///
+/// Rounds computed on a single thread merge the updates for each node as they go, so their merge
+/// time is included in `gossip`.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct PhaseTimes {
+    /// Choosing partners and working out what each exchange sends.
+    pub gossip: f64,
+    /// Combining the updates computed by different threads.
+    pub merge: f64,
+    /// Adding the updates to the nodes.
+    pub apply: f64,
+    /// Checking which nodes now have a quorum.
+    pub convergence: f64,
+}
+
+/// Number of runs averaged for each set of parameters.
+pub const REPETITIONS: usize = 20;
+
+/// Map used for vote maps and diffs.
+///
+/// Nothing relies on the iteration order, so by default this is an `FxHashMap`, which benchmarks
+/// faster than a `BTreeMap` for small to medium networks. Build without the `hash-maps` feature
+/// to get ordered maps instead.
+#[cfg(feature = "hash-maps")]
+pub type Map<K, V> = rustc_hash::FxHashMap<K, V>;
+#[cfg(not(feature = "hash-maps"))]
+pub type Map<K, V> = BTreeMap<K, V>;
+
+/// Identifies a node, from `0` to `n - 1`.
+///
+/// Node IDs are `u32`s rather than `usize`s to halve the size of inline voter sets and partner
+/// lists on 64-bit targets, which limits a network to `u32::MAX` nodes.
+pub type NodeId = u32;
+/// Identifies a rumour being voted on.
+pub type VoteId = u32;
+
+/// Convert the number of nodes in a network to a `NodeId` bound, if all of their IDs fit.
+pub fn num_node_ids(n: usize) -> Result<NodeId, String> {
+    NodeId::try_from(n).map_err(|_| format!("n = {} is too large, node IDs must fit in a u32", n))
+}
+
+pub type VoteMap = Map<VoteId, VoteInfo>;
+pub type VoteDiff = Map<VoteId, VoterSet>;
+
+#[derive(Clone, Debug)]
+pub struct Node {
+    /// Our node ID.
+    pub id: NodeId,
+    /// Total number of nodes in our universe (constant for now).
+    pub num_nodes: usize,
+    /// Rule for deciding when we've seen enough votes for a proposal.
+    pub quorum: Arc<dyn QuorumRule>,
+    /// Map from vote ID to number of voters.
+    pub votes: VoteMap,
+}
+
+impl Node {
+    pub fn new(id: NodeId, num_nodes: usize, quorum: Arc<dyn QuorumRule>) -> Self {
+        Node {
+            id,
+            num_nodes,
+            quorum,
+            votes: VoteMap::default(),
+        }
+    }
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct VoteInfo {
+    voters: Arc<VoterSet>,
+    /// Number of voters, kept up to date so that quorum checks don't have to count them.
+    num_voters: usize,
+}
+
+impl VoteInfo {
+    pub fn new(num_nodes: usize) -> Self {
+        VoteInfo::with_voters(Arc::new(VoterSet::new(num_nodes)))
+    }
+
+    pub fn with_voters(voters: Arc<VoterSet>) -> Self {
+        let num_voters = voters.len();
+        VoteInfo { voters, num_voters }
+    }
+
+    /// All nodes that voted for this proposal.
+    ///
+    /// Nodes that received all of their voters from one other node share its set until either
+    /// of them changes it.
+    pub fn voters(&self) -> &Arc<VoterSet> {
+        &self.voters
+    }
+
+    pub fn num_voters(&self) -> usize {
+        self.num_voters
+    }
+
+    fn insert(&mut self, voter: NodeId) {
+        if Arc::make_mut(&mut self.voters).insert(voter) {
+            self.num_voters += 1;
+        }
+    }
+
+    fn union_with(&mut self, voters: &VoterSet) {
+        self.num_voters += Arc::make_mut(&mut self.voters).union_with(voters);
+    }
+}
+
+impl Node {
+    /// Vote for `vote_id`, returning whether our vote gave us a quorum for it.
+    pub fn vote_for(&mut self, vote_id: VoteId) -> bool {
+        let had_quorum = self.has_quorum_for(vote_id);
+        let (our_id, num_nodes) = (self.id, self.num_nodes);
+        self.votes.entry(vote_id).or_insert_with(|| VoteInfo::new(num_nodes)).insert(our_id);
+        !had_quorum && self.has_quorum_for(vote_id)
+    }
+
+    pub fn has_voted_for(&self, vote_id: VoteId) -> bool {
+        self.votes
+            .get(&vote_id)
+            .map(|vote_info| vote_info.voters.contains(self.id))
+            .unwrap_or(false)
+    }
+
+    pub fn has_quorum_for(&self, vote_id: VoteId) -> bool {
+        self.votes
+            .get(&vote_id)
+            .map(|vote_info| match self.quorum.min_voters(self.num_nodes) {
+                // Rules that only count voters can go by the cached count.
+                Some(min_voters) => vote_info.num_voters >= min_voters,
+                None => self.quorum.has_quorum(&vote_info.voters, self.num_nodes),
+            })
+            .unwrap_or(false)
+    }
+
+    /// Add `voters` for `vote_id`, returning whether they gave us a quorum for it.
+    pub fn add_voters(&mut self, vote_id: VoteId, voters: &VoterSet) -> bool {
+        let had_quorum = self.has_quorum_for(vote_id);
+        let num_nodes = self.num_nodes;
+        self.votes.entry(vote_id).or_insert_with(|| VoteInfo::new(num_nodes)).union_with(voters);
+        !had_quorum && self.has_quorum_for(vote_id)
+    }
+
+    /// Add `voters` for `vote_id`, sharing the set rather than copying it if we don't have any
+    /// voters for `vote_id` yet. Unlike `add_voters`, this doesn't check for a quorum.
+    pub fn add_shared_voters(&mut self, vote_id: VoteId, voters: &Arc<VoterSet>) {
+        match self.votes.get_mut(&vote_id) {
+            Some(vote_info) => vote_info.union_with(voters),
+            None => {
+                self.votes.insert(vote_id, VoteInfo::with_voters(voters.clone()));
+            }
+        }
+    }
+
+    /// Add the voters in `diff`, returning the number of votes we've newly reached a quorum for.
+    pub fn apply_diff(&mut self, diff: VoteDiff) -> usize {
+        diff.iter()
+            .filter(|&(&vote_id, voters)| self.add_voters(vote_id, voters))
+            .count()
+    }
+}
+
+/// Load the quorum rule selected by `params`.
+pub fn load_quorum_rule(params: &Params) -> Result<Arc<dyn QuorumRule>, String> {
+    match params.quorum {
+        Some(ref spec) => parse_quorum_rule(spec),
+        None => Ok(Arc::new(Majority)),
+    }
+}
+
+/// Protocol decisions that can be customised without modifying the simulation loop.
+pub trait Strategy<R: Rng> {
+    /// Choose the node that `our_id` gossips with during `round`.
+    fn choose_partner(&self, our_id: NodeId, n: usize, round: usize, rng: &mut R)
+        -> Result<NodeId, Box<dyn Error>>;
+
+    /// Choose every node's partner for `round` at once, replacing the contents of `partners` so
+    /// that node `i` gossips with element `i`. The same buffer is passed in every round.
+    ///
+    /// Override this for strategies that assign partners for the whole network together.
+    fn choose_partners(&self, n: usize, round: usize, rng: &mut R, partners: &mut Vec<NodeId>)
+        -> Result<(), Box<dyn Error>>
+    {
+        partners.clear();
+        for our_id in 0..n as NodeId {
+            partners.push(self.choose_partner(our_id, n, round, rng)?);
+        }
+        Ok(())
+    }
+
+    /// Decide whether `new_voters` for `vote_id` should be sent from `sender` to `receiver`.
+    fn include_in_diff(
+        &self,
+        vote_id: VoteId,
+        sender: &Node,
+        receiver: &Node,
+        new_voters: &Difference,
+    ) -> Result<bool, Box<dyn Error>>;
+
+    /// This strategy as one that can be shared between threads, if it can be, which lets large
+    /// rounds be computed in parallel.
+    fn as_sync(&self) -> Option<&(dyn Strategy<R> + Sync)> {
+        None
+    }
+}
+
+/// The standard protocol: uniformly random partners, and every missing vote gets sent.
+pub struct DefaultStrategy;
+
+impl<R: Rng> Strategy<R> for DefaultStrategy {
+    fn choose_partner(&self, our_id: NodeId, n: usize, _round: usize, rng: &mut R)
+        -> Result<NodeId, Box<dyn Error>>
+    {
+        Ok(choose_partner(our_id, n, rng))
+    }
+
+    fn include_in_diff(&self, _: VoteId, _: &Node, _: &Node, _: &Difference)
+        -> Result<bool, Box<dyn Error>>
+    {
+        Ok(true)
+    }
+
+    fn as_sync(&self) -> Option<&(dyn Strategy<R> + Sync)> {
+        Some(self)
+    }
+}
+
+/// Like the standard protocol, but with partners following a random cyclic permutation each
+/// round, so that every node initiates one exchange and is contacted by exactly one other node.
+pub struct MatchedStrategy;
+
+impl<R: Rng> Strategy<R> for MatchedStrategy {
+    fn choose_partner(&self, _our_id: NodeId, _n: usize, _round: usize, _rng: &mut R)
+        -> Result<NodeId, Box<dyn Error>>
+    {
+        Err(From::from("matched partners can only be chosen for a whole round"))
+    }
+
+    fn choose_partners(&self, n: usize, _round: usize, rng: &mut R, partners: &mut Vec<NodeId>)
+        -> Result<(), Box<dyn Error>>
+    {
+        random_cycle(n, rng, partners);
+        Ok(())
+    }
+
+    fn include_in_diff(&self, _: VoteId, _: &Node, _: &Node, _: &Difference)
+        -> Result<bool, Box<dyn Error>>
+    {
+        Ok(true)
+    }
+
+    fn as_sync(&self) -> Option<&(dyn Strategy<R> + Sync)> {
+        Some(self)
+    }
+}
+
+#[cfg(feature = "scripting")]
+fn load_script<R: Rng>(path: &str, rng: &mut R) -> Result<Box<dyn Strategy<R>>, Box<dyn Error>> {
+    Ok(Box::new(script::ScriptStrategy::load(path, rng)?))
+}
+
+#[cfg(not(feature = "scripting"))]
+fn load_script<R: Rng>(path: &str, _rng: &mut R) -> Result<Box<dyn Strategy<R>>, Box<dyn Error>> {
+    Err(From::from(format!(
+        "cannot load strategy script {}: built without the `scripting` feature",
+        path
+    )))
+}
+
+pub fn load_strategy<R: Rng>(params: &Params, rng: &mut R) -> Result<Box<dyn Strategy<R>>, Box<dyn Error>> {
+    if let Some(spec) = &params.subnets {
+        return Ok(Box::new(SubnetStrategy::new(parse_subnets(spec, params.n)?)));
+    }
+    match (&params.script, params.partners.unwrap_or(PartnerMode::Random)) {
+        (Some(_), PartnerMode::Matched) => {
+            Err(From::from("matched partners can't be combined with a strategy script"))
+        }
+        (Some(path), PartnerMode::Random) => load_script(path, rng),
+        (None, PartnerMode::Random) => Ok(Box::new(DefaultStrategy)),
+        (None, PartnerMode::Matched) => Ok(Box::new(MatchedStrategy)),
+    }
+}
+
+fn choose_partner<R: Rng>(our_id: NodeId, n: usize, rng: &mut R) -> NodeId {
+    // Choose from the other n - 1 nodes, skipping over ourselves.
+    let p = rng.gen_range(0, n - 1) as NodeId;
+    if p >= our_id {
+        p + 1
+    } else {
+        p
+    }
+}
+
+/// Replace `cycle` with a uniformly random permutation of `0..n` consisting of a single cycle
+/// (Sattolo's algorithm), which never maps an element to itself.
+fn random_cycle<R: Rng>(n: usize, rng: &mut R, cycle: &mut Vec<NodeId>) {
+    cycle.clear();
+    cycle.extend(0..n as NodeId);
+    for i in (1..n).rev() {
+        let j = rng.gen_range(0, i);
+        cycle.swap(i, j);
+    }
+}
+
+/// Call `f` with each vote ID and the voters for it that `n1` would send to `n2`, borrowing them
+/// rather than building a `VoteDiff`. If `n2` has no voters for a vote, `f` also gets `n1`'s set,
+/// which `n2` can share instead of copying.
+///
+/// Returns whether anything was sent.
+fn visit_push_gossip<R: Rng>(
+    n1: &Node,
+    n2: &Node,
+    strategy: &dyn Strategy<R>,
+    mut f: impl FnMut(VoteId, &Difference, Option<&Arc<VoterSet>>),
+) -> Result<bool, Box<dyn Error>> {
+    let mut sent = false;
+
+    for (&vote_id, vote_info) in &n1.votes {
+        // If n2 doesn't have a quorum for one of n1's votes, it gets n1's voters sent to it.
+        if n2.has_quorum_for(vote_id) {
+            continue;
+        }
+        let existing_voters = n2.votes.get(&vote_id).map(|info| &*info.voters);
+        let new_voters = vote_info.voters.difference(existing_voters.unwrap_or(VoterSet::empty()));
+        if !new_voters.is_empty() && strategy.include_in_diff(vote_id, n1, n2, &new_voters)? {
+            // If n2 has no voters yet, the new voters are all of n1's, and its set can be shared.
+            f(vote_id, &new_voters, existing_voters.is_none().then_some(&vote_info.voters));
+            sent = true;
+        }
+    }
+
+    Ok(sent)
+}
+
+// Gossip sent n1 => n2.
+pub fn compute_push_gossip<R: Rng>(n1: &Node, n2: &Node, strategy: &dyn Strategy<R>)
+    -> Result<Option<VoteDiff>, Box<dyn Error>>
+{
+    let mut diff = VoteDiff::default();
+
+    visit_push_gossip(n1, n2, strategy, |vote_id, new_voters, _| {
+        diff.insert(vote_id, new_voters.to_set());
+    })?;
+
+    if !diff.is_empty() {
+        Ok(Some(diff))
+    } else {
+        Ok(None)
+    }
+}
+
+pub fn compute_push_pull_gossip<R: Rng>(n1: &Node, n2: &Node, strategy: &dyn Strategy<R>)
+    -> Result<(Option<VoteDiff>, Option<VoteDiff>), Box<dyn Error>>
+{
+    Ok((compute_push_gossip(n2, n1, strategy)?, compute_push_gossip(n1, n2, strategy)?))
+}
+
+/// Merge the votes `sender` would push to `receiver` into `arena`, unless the message is `lost`,
+/// returning the number of exchanges (zero or one) and the number of individual votes sent.
+fn add_push_gossip<R: Rng>(
+    arena: &mut RoundArena,
+    sender: &Node,
+    receiver: &Node,
+    lost: bool,
+    strategy: &dyn Strategy<R>,
+) -> Result<(usize, usize), Box<dyn Error>> {
+    let mut num_votes = 0;
+
+    let sent = visit_push_gossip(sender, receiver, strategy, |vote_id, new_voters, all_voters| {
+        num_votes += new_voters.len();
+        match all_voters {
+            _ if lost => (),
+            Some(voters) => arena.share(receiver.id, vote_id, voters),
+            None => arena.voters_mut(receiver.id, vote_id).union_with_difference(new_voters),
+        }
+    })?;
+
+    if sent {
+        arena.record(sender.id, receiver.id, num_votes);
+    }
+    Ok((sent as usize, num_votes))
+}
+
+/// Count the number of individual votes in a given `VoteDiff`.
+fn num_votes_in_update(diff: &VoteDiff) -> usize {
+    diff.values().map(|voters| voters.len()).sum()
+}
+
+pub fn construct_voting_schedule(k: usize, voting_steps: usize) -> BTreeMap<usize, usize> {
+    let per_step = k / voting_steps;
+
+    (0..voting_steps).map(|i| {
+        let num_voters = if i == voting_steps - 1 {
+            // Take the remaining votes.
+            k - ((voting_steps - 1) * per_step)
+        } else {
+            per_step
+        };
+        (i, num_voters)
+    }).collect()
+}
+
+/// Average the runs of `params` in `results`, keeping the mode and seed that they share.
+pub fn average_results(params: &Params, results: Vec<SimulationResult>) -> SimulationResult {
+    let result_count = results.len();
+    let mode = results.first().map_or(params.mode, |result| result.mode);
+    let seed = results.first().map_or(params.seed, |result| result.seed);
+    let num_iterations = results.iter().map(|r| r.num_iterations).sum::<f64>() / result_count as f64;
+    let num_exchanges = results.iter().map(|r| r.num_exchanges).sum::<usize>() / result_count;
+    let num_vote_exchanges = results.iter().map(|r| r.num_vote_exchanges).sum::<usize>() / result_count;
+    let average_votes_held = results.iter().map(|r| r.average_votes_held).sum::<f64>() / result_count as f64;
+    let num_rate_limited = results.iter().map(|r| r.num_rate_limited).sum::<usize>() / result_count;
+    let num_cross_subnet_exchanges =
+        results.iter().map(|r| r.num_cross_subnet_exchanges).sum::<usize>() / result_count;
+    let num_membership_messages =
+        results.iter().map(|r| r.num_membership_messages).sum::<usize>() / result_count;
+    let average = |field: fn(&SimulationResult) -> f64| {
+        results.iter().map(field).sum::<f64>() / result_count as f64
+    };
+    // A milestone that some run didn't reach has no meaningful average.
+    let milestone = |field: fn(&SimulationResult) -> Option<f64>| {
+        let rounds: Option<Vec<f64>> = results.iter().map(field).collect();
+        let rounds = rounds.filter(|rounds| !rounds.is_empty())?;
+        Some(rounds.iter().sum::<f64>() / result_count as f64)
+    };
+
+    SimulationResult {
+        num_iterations,
+        num_exchanges,
+        num_vote_exchanges,
+        average_votes_held,
+        missed_deadline: average(|r| r.missed_deadline),
+        num_rate_limited,
+        total_cost: average(|r| r.total_cost),
+        mean_node_cost: average(|r| r.mean_node_cost),
+        max_node_cost: average(|r| r.max_node_cost),
+        push_pull_quorum_rounds: milestone(|r| r.push_pull_quorum_rounds),
+        push_pull_mean_quorum_rounds: milestone(|r| r.push_pull_mean_quorum_rounds),
+        push_quorum_rounds: milestone(|r| r.push_quorum_rounds),
+        push_mean_quorum_rounds: milestone(|r| r.push_mean_quorum_rounds),
+        pull_quorum_rounds: milestone(|r| r.pull_quorum_rounds),
+        pull_mean_quorum_rounds: milestone(|r| r.pull_mean_quorum_rounds),
+        fastest_subnet_rounds: milestone(|r| r.fastest_subnet_rounds),
+        slowest_subnet_rounds: milestone(|r| r.slowest_subnet_rounds),
+        num_cross_subnet_exchanges,
+        detection_rounds: milestone(|r| r.detection_rounds),
+        mean_detection_rounds: milestone(|r| r.mean_detection_rounds),
+        false_suspicions: average(|r| r.false_suspicions),
+        false_positives: average(|r| r.false_positives),
+        num_membership_messages,
+        leader_changes: average(|r| r.leader_changes),
+        max_leader_changes: average(|r| r.max_leader_changes),
+        mean_neighbours: average(|r| r.mean_neighbours),
+        isolated_fraction: average(|r| r.isolated_fraction),
+        quorum_rounds_50: milestone(|r| r.quorum_rounds_50),
+        quorum_rounds_90: milestone(|r| r.quorum_rounds_90),
+        quorum_rounds_99: milestone(|r| r.quorum_rounds_99),
+        quorum_rounds_100: milestone(|r| r.quorum_rounds_100),
+        all_voters_rounds_50: milestone(|r| r.all_voters_rounds_50),
+        all_voters_rounds_90: milestone(|r| r.all_voters_rounds_90),
+        all_voters_rounds_99: milestone(|r| r.all_voters_rounds_99),
+        all_voters_rounds_100: milestone(|r| r.all_voters_rounds_100),
+        mode,
+        seed,
+        ..SimulationResult::for_params(params)
+    }
+    .with_predictions()
+}
+
+/// Get nodes to vote according to the schedule.
+///
+/// Returns the number of nodes whose own vote gave them a quorum.
+pub fn cast_scheduled_votes(nodes: &mut [Node], voting_schedule: &BTreeMap<usize, usize>, round: usize)
+    -> usize
+{
+    let mut new_quorums = 0;
+    if let Some(&num_voters) = voting_schedule.get(&round) {
+        for node in nodes.iter_mut().filter(|node| !node.has_voted_for(0)).take(num_voters) {
+            if node.vote_for(0) {
+                new_quorums += 1;
+            }
+        }
+    }
+    new_quorums
+}
+
+/// Networks smaller than this compute each round on a single thread, as spawning the threads
+/// costs more than it saves.
+pub const PARALLEL_MIN_NODES: usize = 10_000;
+
+/// Threads set by `set_round_threads`, or 0 for one per core.
+static ROUND_THREADS: AtomicUsize = AtomicUsize::new(0);
+
+/// Compute the rounds of large simulations on `threads` threads rather than one per core.
+pub fn set_round_threads(threads: usize) {
+    ROUND_THREADS.store(threads, Ordering::Relaxed);
+}
+
+/// Number of threads to compute the rounds of an `n` node simulation with.
+pub fn round_threads(n: usize) -> usize {
+    if n < PARALLEL_MIN_NODES {
+        return 1;
+    }
+    match ROUND_THREADS.load(Ordering::Relaxed) {
+        0 => thread::available_parallelism().map(|threads| threads.get()).unwrap_or(1),
+        threads => threads,
+    }
+}
+
+/// Collect the updates from the exchanges initiated by `node_ids` in `arena`.
+///
+/// Returns the number of exchanges and the number of individual votes sent.
+fn compute_updates<R: Rng>(
+    nodes: &[Node],
+    partners: &[NodeId],
+    lost: &[bool],
+    unsent: &[bool],
+    node_ids: Range<usize>,
+    strategy: &dyn Strategy<R>,
+    arena: &mut RoundArena,
+) -> Result<(usize, usize), Box<dyn Error>> {
+    arena.reset();
+    let mut num_exchanges = 0;
+    let mut num_vote_exchanges = 0;
+
+    for node_id in node_ids {
+        let node = &nodes[node_id];
+        let partner = &nodes[partners[node_id] as usize];
+
+        // Push-pull: we receive the partner's votes, and they receive ours.
+        let messages = [(partner, node), (node, partner)];
+        for (direction, (sender, receiver)) in messages.into_iter().enumerate() {
+            if is_lost(unsent, node_id, direction) {
+                continue;
+            }
+            let lost = is_lost(lost, node_id, direction);
+            let (exchanges, votes) = add_push_gossip(arena, sender, receiver, lost, strategy)?;
+            num_exchanges += exchanges;
+            num_vote_exchanges += votes;
+        }
+    }
+
+    Ok((num_exchanges, num_vote_exchanges))
+}
+
+/// Merge every arena into the first one, merging pairs of arenas in parallel.
+fn merge_arenas(arenas: &mut [RoundArena]) {
+    let mut len = arenas.len();
+    while len > 1 {
+        let half = len.div_ceil(2);
+        let (into, from) = arenas[..len].split_at_mut(half);
+        thread::scope(|scope| {
+            for (into, from) in into.iter_mut().zip(&*from) {
+                scope.spawn(move || into.merge(from));
+            }
+        });
+        len = half;
+    }
+}
+
+/// Decide which of the round's messages are lost, in the order that `is_lost` looks them up,
+/// leaving `lost` empty if there's no loss so that lossless runs draw nothing from `rng`.
+pub fn choose_losses<R: Rng>(loss: f64, n: usize, rng: &mut R, lost: &mut Vec<bool>) {
+    lost.clear();
+    if loss > 0.0 {
+        lost.extend((0..2 * n).map(|_| rng.next_f64() < loss));
+    }
+}
+
+/// Whether the message of the exchange started by `node_id` in `direction` is lost: 0 for the
+/// partner's votes to the node, 1 for the node's to the partner.
+pub fn is_lost(lost: &[bool], node_id: usize, direction: usize) -> bool {
+    lost.get(2 * node_id + direction).copied().unwrap_or(false)
+}
+
+/// Run one round of push-pull gossip in which each node `i` contacts `partners[i]`.
+///
+/// All updates for the round are collected in `arenas` and applied atomically at the end of the
+/// round (which also removes the need to index mutably into `nodes` twice). Given more than one
+/// arena and a strategy that can be shared between threads, the exchanges are split between a
+/// thread per arena. The merged updates are the same either way, so the result doesn't depend
+/// on the number of threads.
+///
+/// The messages that `lost` marks, as chosen by `choose_losses`, aren't delivered, and those that
+/// `unsent` marks, as chosen by a `RateLimiter`, aren't sent either.
+///
+/// Returns the number of exchanges, the number of individual votes sent, and the number of
+/// votes that nodes newly reached a quorum for. The time spent in each phase is added to `times`.
+pub fn gossip_round<R: Rng>(
+    nodes: &mut [Node],
+    partners: &[NodeId],
+    lost: &[bool],
+    unsent: &[bool],
+    strategy: &dyn Strategy<R>,
+    arenas: &mut [RoundArena],
+    times: &mut PhaseTimes,
+) -> Result<(usize, usize, usize), Box<dyn Error>> {
+    let n = nodes.len();
+    let start = Instant::now();
+
+    let (num_exchanges, num_vote_exchanges) = match strategy.as_sync() {
+        Some(strategy) if arenas.len() > 1 => {
+            let nodes: &[Node] = nodes;
+            let chunk_size = n.div_ceil(arenas.len());
+            let results = thread::scope(|scope| {
+                let threads: Vec<_> = arenas
+                    .iter_mut()
+                    .enumerate()
+                    .map(|(i, arena)| {
+                        let node_ids = (i * chunk_size).min(n)..((i + 1) * chunk_size).min(n);
+                        scope.spawn(move || {
+                            compute_updates(
+                                nodes, partners, lost, unsent, node_ids, strategy, arena,
+                            )
+                            .map_err(|e| e.to_string())
+                        })
+                    })
+                    .collect();
+                threads.into_iter().map(|thread| thread.join()).collect::<Vec<_>>()
+            });
+
+            let mut totals = (0, 0);
+            for result in results {
+                let (exchanges, votes) = result.map_err(|_| "gossip thread panicked")??;
+                totals.0 += exchanges;
+                totals.1 += votes;
+            }
+            times.gossip += start.elapsed().as_secs_f64();
+
+            let start = Instant::now();
+            merge_arenas(arenas);
+            times.merge += start.elapsed().as_secs_f64();
+            totals
+        }
+        _ => {
+            let arena = &mut arenas[0];
+            let totals = compute_updates(nodes, partners, lost, unsent, 0..n, strategy, arena)?;
+            times.gossip += start.elapsed().as_secs_f64();
+            totals
+        }
+    };
+
+    // Apply all those updates.
+    let start = Instant::now();
+    for (node_id, vote_id, voters) in arenas[0].updates() {
+        nodes[node_id as usize].add_shared_voters(vote_id, voters);
+    }
+    times.apply += start.elapsed().as_secs_f64();
+
+    // Nodes are only sent voters for votes they don't have a quorum for, so any quorum they have
+    // for those votes now is new.
+    let start = Instant::now();
+    let new_quorums = arenas[0]
+        .updates()
+        .filter(|&(node_id, vote_id, _)| nodes[node_id as usize].has_quorum_for(vote_id))
+        .count();
+    times.convergence += start.elapsed().as_secs_f64();
+
+    Ok((num_exchanges, num_vote_exchanges, new_quorums))
+}
+
+/// Check that a simulation with `params` can run to completion.
+pub fn validate_params(params: &Params) -> Result<(), String> {
+    if params.n < 2 {
+        return Err(format!("n = {} is too small, need at least 2 nodes", params.n));
+    }
+    num_node_ids(params.n)?;
+    if params.k > params.n {
+        return Err(format!("k = {} exceeds n = {}", params.k, params.n));
+    }
+    if params.election.is_some() {
+        election::validate(params)?;
+    } else {
+        let quorum = load_quorum_rule(params)?;
+        quorum.validate(params.n)?;
+        // The first `k` nodes are the ones that vote.
+        if !quorum.has_quorum(&(0..params.k as NodeId).collect(), params.n) {
+            return Err(format!(
+                "k = {} voters can never form a quorum of n = {}",
+                params.k, params.n
+            ));
+        }
+    }
+    if params.voting_steps == 0 {
+        return Err("voting_steps must be at least 1".to_string());
+    }
+    if !(0.0..1.0).contains(&params.loss) {
+        return Err(format!("loss = {} must be at least 0 and less than 1", params.loss));
+    }
+    if params.max_messages_per_round == Some(0) {
+        return Err("max_messages_per_round must be at least 1".to_string());
+    }
+    if params.max_rounds == Some(0) {
+        return Err("max_rounds must be at least 1".to_string());
+    }
+    if let Some(model) = load_cost_model(params)? {
+        model.validate(params.n)?;
+    }
+    if let Some(spec) = &params.cohorts {
+        parse_cohorts(spec, params.n)?;
+    }
+    if let Some(spec) = &params.subnets {
+        parse_subnets(spec, params.n)?;
+        if params.script.is_some() || params.partners == Some(PartnerMode::Matched) {
+            return Err(
+                "subnets choose the partners, so can't be combined with a strategy script or \
+                 matched partners"
+                    .to_string(),
+            );
+        }
+    }
+    if let Some(spec) = &params.membership {
+        parse_membership(spec, params.n, params.k)?;
+        if params.script.is_some()
+            || params.partners == Some(PartnerMode::Matched)
+            || params.subnets.is_some()
+        {
+            return Err(
+                "membership replaces partners believed dead with random ones, so can't be \
+                 combined with a strategy script, matched partners or subnets"
+                    .to_string(),
+            );
+        }
+    }
+    if let Some(spec) = &params.mobility {
+        let config = parse_mobility(spec)?;
+        if params.script.is_some()
+            || params.partners == Some(PartnerMode::Matched)
+            || params.subnets.is_some()
+            || params.membership.is_some()
+        {
+            return Err(
+                "mobility chooses the partners in range, so can't be combined with a strategy \
+                 script, matched partners, subnets or membership"
+                    .to_string(),
+            );
+        }
+        if params.rate_limit_policy == Some(RateLimitPolicy::Defer) {
+            return Err("nodes that move can't defer exchanges, as their partners move on".into());
+        }
+        if config.speed == 0.0 && params.max_rounds.is_none() {
+            return Err(
+                "nodes that never move may never all be connected, so a mobility speed of 0 \
+                 needs max_rounds"
+                    .to_string(),
+            );
+        }
+    }
+    if params.voting_steps > params.k {
+        return Err(format!(
+            "voting_steps = {} exceeds k = {}, so some steps would have no voters",
+            params.voting_steps, params.k
+        ));
+    }
+    if params.script.is_some() && params.partners == Some(PartnerMode::Matched) {
+        return Err("matched partners can't be combined with a strategy script".to_string());
+    }
+    if params.mode == Some(SimulationMode::Compact) && !compact::supports(params) {
+        return Err(
+            "compact mode doesn't support scripts, membership, elections or quorum rules that look \
+             at who voted"
+                .into()
+        );
+    }
+    Ok(())
+}
+
+/// The simulation that `simulate_params` runs `params` with.
+pub fn simulation_mode(params: &Params) -> SimulationMode {
+    // Only scripts, membership and quorum rules that look at who voted need a `Node` for every
+    // node, and elections don't gather votes.
+    match params.mode {
+        Some(mode) => mode,
+        None if compact::supports(params) => SimulationMode::Compact,
+        None => SimulationMode::General,
+    }
+}
+
+/// Run `params` for `repetitions` runs and average the results.
+///
+/// Runs are done in compact mode where it supports `params`, and by `run_simulation` otherwise,
+/// unless `params.mode` chooses. `on_round` is called with the metrics for every round of every
+/// run.
+pub fn simulate_params<R: Rng>(
+    params: &Params,
+    repetitions: usize,
+    rng: &mut R,
+    on_round: &mut dyn FnMut(&RoundMetrics),
+) -> Result<SimulationResult, Box<dyn Error>> {
+    let sim_results = simulate_runs(params, repetitions, rng, on_round)?;
+    Ok(average_results(params, sim_results))
+}
+
+/// Like `simulate_params`, but return the result of every run rather than their average.
+pub fn simulate_runs<R: Rng>(
+    params: &Params,
+    repetitions: usize,
+    rng: &mut R,
+    on_round: &mut dyn FnMut(&RoundMetrics),
+) -> Result<Vec<SimulationResult>, Box<dyn Error>> {
+    if simulation_mode(params) == SimulationMode::Compact {
+        return compact::simulate_runs(params, repetitions, rng, on_round);
+    }
+    let strategy = load_strategy(params, rng)?;
+    if let Some(rank) = params.election {
+        return election::simulate_runs(params, rank, &*strategy, repetitions, rng, on_round);
+    }
+    let quorum = load_quorum_rule(params)?;
+    (0..repetitions)
+        .map(|repetition| {
+            let result =
+                run_simulation(params, &*strategy, &quorum, rng, &mut |metrics: &RoundMetrics| {
+                    on_round(&RoundMetrics { repetition, ..metrics.clone() })
+                })?;
+            let mode = Some(SimulationMode::General);
+            Ok(SimulationResult { mode, ..result }.with_predictions())
+        })
+        .collect()
+}
+
+/// The seed for the runs of row `row` of a run seeded with `seed`.
+pub fn row_seed(seed: u64, row: usize) -> u64 {
+    seed ^ (row as u64).wrapping_mul(0xd1b5_4a32_d192_ed03)
+}
+
+/// A generator seeded with `seed`.
+pub fn seeded_rng(seed: u64) -> XorShiftRng {
+    // Mixed with SplitMix64, so that nearby seeds get unrelated generators.
+    let mut state = seed;
+    let (a, b) = (splitmix64(&mut state), splitmix64(&mut state));
+    let mut words = [a as u32, (a >> 32) as u32, b as u32, (b >> 32) as u32];
+    // An all-zero state would only ever generate zeros.
+    if words == [0; 4] {
+        words[0] = 1;
+    }
+    XorShiftRng::from_seed(words)
+}
+
+/// The next output of the SplitMix64 generator with the given state.
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// A run of the simulation with a `Node` for every node, advanced a round at a time.
+pub struct Simulation {
+    params: Params,
+    nodes: Vec<Node>,
+    voting_schedule: BTreeMap<usize, usize>,
+    /// The next round to run.
+    round: usize,
+    num_exchanges: usize,
+    num_vote_exchanges: usize,
+    // Only rumour 0 is ever voted on, so this counts the nodes with a quorum for it.
+    nodes_with_quorum: usize,
+    milestones: Milestones,
+    // Buffers reused every round, so that a long simulation stops allocating.
+    arenas: Vec<RoundArena>,
+    partners: Vec<NodeId>,
+    lost: Vec<bool>,
+    unsent: Vec<bool>,
+    rate_limiter: Option<RateLimiter>,
+    costs: Option<Costs>,
+    cohorts: Option<Cohorts>,
+    subnets: Option<SubnetStats>,
+    membership: Option<Membership>,
+    mobility: Option<Mobility>,
+}
+
+impl Simulation {
+    /// Start a run of `params`, before any votes have been cast.
+    pub fn new(params: &Params, quorum: &Arc<dyn QuorumRule>) -> Result<Self, Box<dyn Error>> {
+        if params.election.is_some() {
+            return Err(From::from("elections are only run whole, not a round at a time"));
+        }
+        let n = params.n;
+        let costs = Costs::new(params)?;
+        let mut arenas: Vec<_> = (0..round_threads(n)).map(|_| RoundArena::new(n)).collect();
+        for arena in &mut arenas {
+            arena.record_sent = costs.is_some();
+        }
+        Ok(Simulation {
+            params: params.clone(),
+            nodes: (0..num_node_ids(n)?).map(|i| Node::new(i, n, quorum.clone())).collect(),
+            // At each voting step, have roughly an even portion of k vote.
+            voting_schedule: construct_voting_schedule(params.k, params.voting_steps),
+            round: 0,
+            num_exchanges: 0,
+            num_vote_exchanges: 0,
+            nodes_with_quorum: 0,
+            milestones: Milestones::default(),
+            arenas,
+            partners: Vec::with_capacity(n),
+            lost: vec![],
+            unsent: vec![],
+            rate_limiter: RateLimiter::new(params),
+            costs,
+            cohorts: Cohorts::new(params)?,
+            subnets: SubnetStats::new(params)?,
+            membership: Membership::new(params)?,
+            mobility: Mobility::new(params)?,
+        })
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// The movement of the nodes, if they move.
+    pub fn mobility(&self) -> Option<&Mobility> {
+        self.mobility.as_ref()
+    }
+
+    /// Number of rounds run so far.
+    pub fn round(&self) -> usize {
+        self.round
+    }
+
+    pub fn nodes_with_quorum(&self) -> usize {
+        self.nodes_with_quorum
+    }
+
+    /// Whether the run is over, because every node has a quorum or it has reached `max_rounds`.
+    pub fn is_finished(&self) -> bool {
+        self.has_converged() || self.missed_deadline()
+    }
+
+    /// Whether the run has reached `max_rounds` without every node having a quorum.
+    pub fn missed_deadline(&self) -> bool {
+        !self.has_converged() && Some(self.round) == self.params.max_rounds
+    }
+
+    /// Whether every node that can get a quorum has one, and with a `membership` layer, every
+    /// crash has been detected.
+    fn has_converged(&self) -> bool {
+        match &self.membership {
+            Some(membership) => {
+                self.nodes_with_quorum + membership.num_stranded() == self.nodes.len()
+                    && membership.is_settled()
+            }
+            None => self.nodes_with_quorum == self.nodes.len(),
+        }
+    }
+
+    /// Have node `id` vote for the rumour now, on top of the votes in the schedule, returning
+    /// whether it hadn't voted already.
+    pub fn inject_vote(&mut self, id: NodeId) -> Result<bool, String> {
+        let node = self
+            .nodes
+            .get_mut(id as usize)
+            .ok_or_else(|| format!("there's no node {}, only 0 to {}", id, self.params.n - 1))?;
+        if node.has_voted_for(0) {
+            return Ok(false);
+        }
+        if node.vote_for(0) {
+            self.nodes_with_quorum += 1;
+        }
+        Ok(true)
+    }
+
+    /// Run the next round: cast the votes scheduled for it, then have every node gossip with a
+    /// partner chosen by `strategy`.
+    pub fn step<R: Rng>(&mut self, strategy: &dyn Strategy<R>, rng: &mut R)
+        -> Result<RoundMetrics, Box<dyn Error>>
+    {
+        let n = self.params.n;
+        self.nodes_with_quorum +=
+            cast_scheduled_votes(&mut self.nodes, &self.voting_schedule, self.round);
+
+        // Each node chooses a random gossip partner.
+        // Push-pull, so everyone contacts someone and solicits updates.
+        let mut times = PhaseTimes::default();
+        let start = Instant::now();
+        strategy.choose_partners(n, self.round, rng, &mut self.partners)?;
+        if let Some(mobility) = &mut self.mobility {
+            mobility.choose_partners(&mut self.partners, rng);
+        }
+        if let Some(limiter) = &self.rate_limiter {
+            limiter.retry_deferred(&mut self.partners);
+        }
+        if let Some(membership) = &mut self.membership {
+            let nodes = &self.nodes;
+            membership.crash(self.round, |node| nodes[node].has_quorum_for(0));
+            membership.avoid_dead(&mut self.partners, rng);
+        }
+        times.gossip += start.elapsed().as_secs_f64();
+        if self.partners.len() != n {
+            return Err(From::from(format!(
+                "chose {} gossip partners for {} nodes",
+                self.partners.len(),
+                n
+            )));
+        }
+        for (node_id, &partner_id) in self.partners.iter().enumerate() {
+            if partner_id as usize >= n || partner_id as usize == node_id {
+                return Err(From::from(format!(
+                    "node {} chose invalid gossip partner {}", node_id, partner_id
+                )));
+            }
+        }
+
+        if let Some(subnets) = &mut self.subnets {
+            subnets.record_partners(&self.partners);
+        }
+
+        choose_losses(self.params.loss, n, rng, &mut self.lost);
+        self.unsent.clear();
+        if let Some(cohorts) = &self.cohorts {
+            cohorts.mark_unsent(&mut self.unsent);
+        }
+        if let Some(membership) = &self.membership {
+            membership.mark_unsent(&self.partners, &mut self.unsent);
+        }
+        if let Some(mobility) = &self.mobility {
+            mobility.mark_unsent(&mut self.unsent);
+        }
+        if let Some(limiter) = &mut self.rate_limiter {
+            limiter.limit(&self.partners, rng, &mut self.lost, &mut self.unsent);
+        }
+        let (round_exchanges, round_vote_exchanges, new_quorums) = gossip_round(
+            &mut self.nodes,
+            &self.partners,
+            &self.lost,
+            &self.unsent,
+            strategy,
+            &mut self.arenas,
+            &mut times,
+        )?;
+
+        self.num_exchanges += round_exchanges;
+        self.num_vote_exchanges += round_vote_exchanges;
+        self.nodes_with_quorum += new_quorums;
+        if let Some(costs) = &mut self.costs {
+            for &(sender, receiver, voters) in self.arenas.iter().flat_map(RoundArena::sent) {
+                costs.add(sender as usize, receiver as usize, voters);
+            }
+        }
+        let k = self.params.k;
+        let with_all_voters = self
+            .nodes
+            .iter()
+            .filter(|node| node.votes.get(&0).is_some_and(|vote_info| vote_info.num_voters == k))
+            .count();
+        if let Some(membership) = &mut self.membership {
+            let params = &self.params;
+            membership.step(self.round, &self.partners, &self.lost, &self.unsent, params.loss, rng);
+        }
+        // Crashed nodes without a quorum never get one, so the milestones leave them out.
+        let can_get_quorum = n - self.membership.as_ref().map_or(0, Membership::num_stranded);
+        self.milestones.record(
+            can_get_quorum,
+            self.round + 1,
+            self.nodes_with_quorum,
+            with_all_voters,
+        );
+        if let Some(cohorts) = &mut self.cohorts {
+            let nodes = &self.nodes;
+            cohorts.record(self.round + 1, |node| nodes[node].has_quorum_for(0));
+        }
+        if let Some(subnets) = &mut self.subnets {
+            let nodes = &self.nodes;
+            subnets.record(self.round + 1, |node| nodes[node].has_quorum_for(0));
+        }
+
+        let metrics = RoundMetrics {
+            repetition: 0,
+            round: self.round,
+            nodes_with_quorum: self.nodes_with_quorum,
+            num_exchanges: round_exchanges,
+            num_vote_exchanges: round_vote_exchanges,
+            times: self.params.timings.then_some(times),
+        };
+        self.round += 1;
+        Ok(metrics)
+    }
+
+    /// The result of the rounds run so far.
+    pub fn result(&self) -> SimulationResult {
+        let total_votes_collected: usize = self
+            .nodes
+            .iter()
+            .map(|node| node.votes.get(&0).map_or(0, VoteInfo::num_voters))
+            .sum();
+        let result = self.milestones.apply_to(SimulationResult {
+            num_iterations: self.round as f64,
+            num_exchanges: self.num_exchanges,
+            num_vote_exchanges: self.num_vote_exchanges,
+            average_votes_held: total_votes_collected as f64 / self.params.n as f64,
+            missed_deadline: if self.missed_deadline() { 1.0 } else { 0.0 },
+            num_rate_limited: self.rate_limiter.as_ref().map_or(0, |limiter| limiter.num_limited),
+            ..SimulationResult::for_params(&self.params)
+        });
+        let result = match &self.costs {
+            Some(costs) => costs.apply_to(result),
+            None => result,
+        };
+        let result = match &self.cohorts {
+            Some(cohorts) => cohorts.apply_to(result),
+            None => result,
+        };
+        let result = match &self.subnets {
+            Some(subnets) => subnets.apply_to(result),
+            None => result,
+        };
+        let result = match &self.membership {
+            Some(membership) => membership.apply_to(result),
+            None => result,
+        };
+        match &self.mobility {
+            Some(mobility) => mobility.apply_to(result),
+            None => result,
+        }
+    }
+}
+
+pub fn run_simulation<R: Rng>(
+    params: &Params,
+    strategy: &dyn Strategy<R>,
+    quorum: &Arc<dyn QuorumRule>,
+    rng: &mut R,
+    on_round: &mut dyn FnMut(&RoundMetrics),
+) -> Result<SimulationResult, Box<dyn Error>> {
+    let mut simulation = Simulation::new(params, quorum)?;
+
+    // Keep running while any node lacks a quorum, up to the deadline.
+    while !simulation.is_finished() {
+        on_round(&simulation.step(strategy, rng)?);
+    }
+
+    Ok(simulation.result())
+}
+
+/// The file name that stands for stdin when reading, or stdout when writing.
+pub const STDIO: &str = "-";
+
+/// Open `filename` for reading, or stdin if it's `STDIO`.
+pub fn open_input(filename: &str) -> io::Result<Box<dyn io::Read>> {
+    if filename == STDIO {
+        Ok(Box::new(io::stdin()))
+    } else {
+        Ok(Box::new(File::open(filename)?))
+    }
+}
+
+/// A row of a parameters CSV that couldn't be read.
+#[derive(Debug)]
+pub struct RowError {
+    pub filename: String,
+    /// The line of the file that the row starts on, counting from 1.
+    pub line: u64,
+    pub message: String,
+}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} line {}: {}", self.filename, self.line, self.message)
+    }
+}
+
+impl Error for RowError {}
+
+/// Describe what's wrong with `record`, naming the field at fault and the type it should have.
+fn describe_row_error(error: &csv::Error, headers: &csv::StringRecord, record: &csv::StringRecord)
+    -> String
+{
+    match error.kind() {
+        csv::ErrorKind::Deserialize { err, .. } => {
+            let expected = match err.kind() {
+                csv::DeserializeErrorKind::ParseInt(_) => " (expected a whole number)",
+                csv::DeserializeErrorKind::ParseFloat(_) => " (expected a number)",
+                csv::DeserializeErrorKind::ParseBool(_) => " (expected true or false)",
+                _ => "",
+            };
+            match err.field().map(|field| field as usize) {
+                Some(field) => format!(
+                    "invalid {} {:?}: {}{}",
+                    headers.get(field).unwrap_or("field"),
+                    record.get(field).unwrap_or(""),
+                    err.kind(),
+                    expected
+                ),
+                None => err.kind().to_string(),
+            }
+        }
+        csv::ErrorKind::UnequalLengths { expected_len, len, .. } => {
+            format!("has {} fields, but the header has {}", len, expected_len)
+        }
+        _ => error.to_string(),
+    }
+}
+
+/// Read every row of a parameters CSV, going on past rows that can't be read rather than stopping
+/// at the first.
+pub fn read_param_rows(filename: &str) -> Result<Vec<Result<Params, RowError>>, Box<dyn Error>> {
+    let input = open_input(filename).map_err(|e| format!("failed to read {}: {}", filename, e))?;
+    let mut csv_reader = csv::Reader::from_reader(input);
+    let headers = csv_reader
+        .headers()
+        .map_err(|e| format!("failed to read the header of {}: {}", filename, e))?
+        .clone();
+
+    let mut rows = vec![];
+    let mut record = csv::StringRecord::new();
+    loop {
+        let row_error = |error: &csv::Error, record: &csv::StringRecord| RowError {
+            filename: filename.to_string(),
+            line: error.position().or(record.position()).map_or(0, |pos| pos.line()),
+            message: describe_row_error(error, &headers, record),
+        };
+        match csv_reader.read_record(&mut record) {
+            Ok(false) => break,
+            Ok(true) => rows.push(
+                record.deserialize(Some(&headers)).map_err(|e| row_error(&e, &record)),
+            ),
+            // Rows with the wrong number of fields are skipped by the reader, unlike I/O errors.
+            Err(e) if matches!(e.kind(), csv::ErrorKind::UnequalLengths { .. }) => {
+                rows.push(Err(row_error(&e, &record)));
+            }
+            Err(e) => return Err(From::from(format!("failed to read {}: {}", filename, e))),
+        }
+    }
+    Ok(rows)
+}
+
+pub fn read_params(filename: &str) -> Result<Vec<Params>, Box<dyn Error>> {
+    let mut all_params = vec![];
+    for row in read_param_rows(filename)? {
+        all_params.push(row?);
+    }
+    Ok(all_params)
+}
+
+/// File formats that results can be written in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// A CSV row per result, with a header.
+    #[default]
+    Csv,
+    /// A JSON array with an object per result.
+    Json,
+}
+
+impl OutputFormat {
+    /// The usual file extension for the format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Csv => "csv",
+            OutputFormat::Json => "json",
+        }
+    }
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "csv" => Ok(OutputFormat::Csv),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!("unknown output format {:?}, expected csv or json", s)),
+        }
+    }
+}
+
+/// Write `results` to `output_file`, or stdout if it's `STDIO`. The file's directory is created
+/// if it doesn't exist.
+pub fn write_results<T: Serialize>(output_file: &str, results: Vec<T>, format: OutputFormat)
+    -> io::Result<()>
+{
+    if output_file == STDIO {
+        return write_results_to(io::stdout().lock(), results, format);
+    }
+    if let Some(dir) = Path::new(output_file).parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    write_results_to(File::create(output_file)?, results, format)
+}
+
+/// Add `results` to the end of `output_file`, or write it as `write_results` does if it doesn't
+/// exist yet. A CSV's header must match the columns of the results.
+pub fn append_results<T: Serialize>(output_file: &str, results: Vec<T>, format: OutputFormat)
+    -> Result<(), Box<dyn Error>>
+{
+    let existing = match fs::read_to_string(output_file) {
+        Ok(existing) if !existing.trim().is_empty() && output_file != STDIO => existing,
+        Ok(_) => return Ok(write_results(output_file, results, format)?),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Ok(write_results(output_file, results, format)?);
+        }
+        Err(e) => return Err(From::from(format!("failed to read {}: {}", output_file, e))),
+    };
+
+    match format {
+        OutputFormat::Csv => {
+            let mut new = vec![];
+            write_results_to(&mut new, results, format)?;
+            let mut new_rows = csv::Reader::from_reader(&new[..]);
+            let mut old_rows = csv::Reader::from_reader(existing.as_bytes());
+            let (new_header, old_header) = (new_rows.headers()?, old_rows.headers()?);
+            if !new.is_empty() && new_header != old_header {
+                return Err(From::from(format!(
+                    "can't append to {}: its columns are {:?}, but the results' are {:?}",
+                    output_file,
+                    old_header.iter().collect::<Vec<_>>(),
+                    new_header.iter().collect::<Vec<_>>()
+                )));
+            }
+            // Everything after the header line.
+            let header_end = new.iter().position(|&byte| byte == b'\n');
+            let rows = header_end.map_or(&[][..], |end| &new[end + 1..]);
+            let mut file = fs::OpenOptions::new().append(true).open(output_file)?;
+            if !existing.ends_with('\n') {
+                writeln!(file)?;
+            }
+            file.write_all(rows)?;
+        }
+        OutputFormat::Json => {
+            let mut all_results: Vec<serde_json::Value> = serde_json::from_str(&existing)
+                .map_err(|e| format!("can't append to {}: {}", output_file, e))?;
+            for result in results {
+                all_results.push(serde_json::to_value(result)?);
+            }
+            write_results(output_file, all_results, format)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn write_results_to<W: Write, T: Serialize>(writer: W, results: Vec<T>, format: OutputFormat)
+    -> io::Result<()>
+{
+    match format {
+        OutputFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(writer);
+            for result in results {
+                writer.serialize(result)?;
+            }
+            writer.flush()
+        }
+        OutputFormat::Json => {
+            let mut writer = io::BufWriter::new(writer);
+            serde_json::to_writer_pretty(&mut writer, &results)?;
+            writeln!(writer)?;
+            writer.flush()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// 100 nodes with 70 voters, and the parameters in `extra`.
+    fn params(extra: serde_json::Value) -> Params {
+        let mut params = json!({"n": 100, "k": 70, "voting_steps": 3});
+        params.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(params).unwrap()
+    }
+
+    /// The quorum milestones of `result`, in order.
+    fn quorum_milestones(result: &SimulationResult) -> [Option<f64>; 4] {
+        [
+            result.quorum_rounds_50,
+            result.quorum_rounds_90,
+            result.quorum_rounds_99,
+            result.quorum_rounds_100,
+        ]
+    }
+
+    #[test]
+    fn records_each_milestone_once() {
+        let mut milestones = Milestones::default();
+        for (rounds, with_quorum, with_all_voters) in [(1, 3, 0), (2, 5, 1), (3, 9, 5), (4, 10, 5)]
+        {
+            milestones.record(10, rounds, with_quorum, with_all_voters);
+        }
+        let result = milestones.apply_to(SimulationResult::for_params(&params(json!({}))));
+        assert_eq!(quorum_milestones(&result), [Some(2.0), Some(3.0), Some(4.0), Some(4.0)]);
+        assert_eq!(result.all_voters_rounds_50, Some(3.0));
+        assert_eq!(result.all_voters_rounds_90, None);
+        assert_eq!(result.all_voters_rounds_100, None);
+    }
+
+    #[test]
+    fn milestones_never_come_before_earlier_ones() {
+        for extra in [json!({}), json!({"loss": 0.3}), json!({"mode": "general"})] {
+            let params = params(extra.clone());
+            for run in simulate_runs(&params, 10, &mut seeded_rng(2), &mut |_| {}).unwrap() {
+                let milestones = quorum_milestones(&run);
+                assert!(milestones.iter().all(Option::is_some), "{} gave {:?}", extra, milestones);
+                assert!(milestones.windows(2).all(|pair| pair[0] <= pair[1]), "{:?}", milestones);
+                assert_eq!(run.quorum_rounds_100, Some(run.num_iterations));
+            }
+        }
+    }
+
+    #[test]
+    fn leaves_out_averages_of_milestones_some_runs_missed() {
+        let params = params(json!({}));
+        let run = |quorum: [Option<usize>; 4]| {
+            let milestones = Milestones { quorum, all_voters: [None; 4] };
+            milestones.apply_to(SimulationResult::for_params(&params))
+        };
+        let runs = vec![
+            run([Some(3), Some(5), Some(6), Some(8)]),
+            run([Some(4), Some(6), Some(7), None]),
+            run([Some(5), Some(7), None, None]),
+        ];
+        let average = average_results(&params, runs);
+        assert_eq!(quorum_milestones(&average), [Some(4.0), Some(6.0), None, None]);
+        assert_eq!(average.all_voters_rounds_50, None);
+
+        // Runs cut short by a deadline leave out the milestones they didn't get to.
+        let params = Params { max_rounds: Some(4), ..params };
+        let runs = simulate_runs(&params, 5, &mut seeded_rng(2), &mut |_| {}).unwrap();
+        assert!(runs.iter().all(|run| run.missed_deadline == 1.0));
+        let average = average_results(&params, runs);
+        assert_eq!(average.quorum_rounds_100, None);
+    }
+
+    #[test]
+    fn random_cycles_visit_every_node_once() {
+        let mut rng = seeded_rng(5);
+        let mut cycle = vec![];
+        for n in [2, 3, 4, 10, 101] {
+            for _ in 0..20 {
+                random_cycle(n, &mut rng, &mut cycle);
+                assert_eq!(cycle.len(), n);
+                // Every node is contacted exactly once, and never by itself.
+                let mut contacted = vec![false; n];
+                for (node, &partner) in cycle.iter().enumerate() {
+                    assert_ne!(node, partner as usize, "{:?}", cycle);
+                    assert!(!std::mem::replace(&mut contacted[partner as usize], true));
+                }
+                // Following the partners from node 0 visits every node before coming back.
+                let mut node = 0;
+                for step in 1..=n {
+                    node = cycle[node] as usize;
+                    assert_eq!(node == 0, step == n, "{:?}", cycle);
+                }
+            }
+        }
+        random_cycle(2, &mut rng, &mut cycle);
+        assert_eq!(cycle, vec![1, 0]);
+    }
+
+    #[test]
+    fn threads_dont_change_the_results() {
+        // Loss and rate limits draw from the rng each round, so they'd catch a thread drawing
+        // out of turn.
+        let params = json!({
+            "n": PARALLEL_MIN_NODES,
+            "k": 10,
+            "voting_steps": 2,
+            "mode": "general",
+            "loss": 0.1,
+            "max_messages_per_round": 3,
+            "max_rounds": 60,
+        });
+        let params: Params = serde_json::from_value(params).unwrap();
+        let results: Vec<_> = [1, 4]
+            .into_iter()
+            .map(|threads| {
+                set_round_threads(threads);
+                assert_eq!(round_threads(params.n), threads);
+                let runs = simulate_runs(&params, 1, &mut seeded_rng(3), &mut |_| {});
+                set_round_threads(0);
+                serde_json::to_value(runs.unwrap()).unwrap()
+            })
+            .collect();
+        assert_eq!(results[0], results[1]);
+    }
+}
@@ -1,313 +1,2296 @@
-extern crate csv;
-extern crate rand;
+extern crate gossip;
 #[macro_use]
 extern crate serde_derive;
 
-use rand::{weak_rng, Rng};
-use std::io;
-use std::fs::File;
-use std::env;
+use clap::{ArgAction, Args, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use gossip::check::CheckResult;
+use gossip::filter::Filter;
+use gossip::preset::Preset;
+use gossip::progress::{Event, Progress, ProgressFormat};
+use gossip::quorum::parse_quorum_rule;
+use gossip::trace::{trace_params, Trace};
+use gossip::{
+    append_results, average_results, check, compact, config, daemon, distributed, load_strategy,
+    open_input, row_seed, seeded_rng, set_round_threads, simulate_params, simulate_runs, stats,
+    trace, validate_params, write_results, write_results_to, NodeId, OutputFormat, Params, PartnerMode,
+    RoundMetrics, SimulationMode, SimulationResult, PARAM_FIELDS, REPETITIONS, STDIO,
+};
+#[cfg(feature = "gossipsub")]
+use gossip::p2p;
+use rand::{weak_rng, Rng, XorShiftRng};
+use std::collections::{HashMap, VecDeque};
 use std::error::Error;
-use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::process;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, error, info, warn};
+use tracing_subscriber::EnvFilter;
 
-/// Parameters to run the simulation with.
-#[derive(Debug, Deserialize, Serialize)]
-struct Params {
-    n: usize,
-    k: usize,
-    voting_steps: usize,
+/// Directory that results are written to if no results file is given.
+const DEFAULT_RESULTS_DIR: &str = "results";
+
+/// How often `--watch` checks whether the input has changed.
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Address the `serve` subcommand listens on if none is given.
+const DEFAULT_SERVE_ADDR: &str = "127.0.0.1:50051";
+
+/// Address the `serve-http` subcommand listens on if none is given.
+const DEFAULT_SERVE_HTTP_ADDR: &str = "127.0.0.1:8080";
+
+/// Address the `worker` subcommand listens on if none is given.
+const DEFAULT_WORKER_ADDR: &str = "0.0.0.0:7878";
+
+/// Simulate push-pull gossip of votes until every node has a quorum.
+///
+/// Parameter sets are read from a CSV with a row per set and `n`, `k` and `voting_steps` columns
+/// (see the README for the optional ones), or from a TOML or YAML config file. `gossip <INPUT>
+/// <OUTPUT>` is short for `gossip run <INPUT> <OUTPUT>`. Any input or output file can be `-` to
+/// read a CSV from stdin or write to stdout.
+#[derive(Parser)]
+#[command(version, arg_required_else_help = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Parameters CSV or config file, for the `run` shorthand.
+    input: Option<String>,
+    /// Results file, for the `run` shorthand.
+    output: Option<String>,
+    #[command(flatten)]
+    options: Options,
 }
 
-/// Result to write to the output CSV.
-#[derive(Debug, Deserialize, Serialize)]
-struct SimulationResult {
-    n: usize,
-    k: usize,
-    voting_steps: usize,
-    num_iterations: f64,
-    num_exchanges: usize,
-    num_vote_exchanges: usize,
-    average_votes_held: f64,
+/// Flags shared by every subcommand.
+///
+/// Those that a config file can also set can be given in `GOSSIP_*` environment variables too,
+/// which override the config file but not the command line.
+#[derive(Args)]
+struct Options {
+    /// Seed for the random number generator, so that a run can be repeated exactly.
+    #[arg(long, global = true, env = "GOSSIP_SEED")]
+    seed: Option<u64>,
+    /// Format to write results in: csv or json [default: csv, unless the config sets it].
+    #[arg(long, global = true, env = "GOSSIP_FORMAT")]
+    format: Option<OutputFormat>,
+    /// Number of threads to split the rounds of networks of 10,000 or more nodes between in the
+    /// general simulation, which rows only use without compact mode [default: one per core].
+    #[arg(long, global = true, env = "GOSSIP_THREADS")]
+    threads: Option<usize>,
+    /// Log the start and end of each row on stderr. Repeat to also log every round, or again to
+    /// log everything. Without it, the level is taken from `--log` or defaults to warnings.
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
+    /// What to log, as a level like `info` or a filter like `warn,gossip=debug`, when `-v` isn't
+    /// given [default: `RUST_LOG`, or warnings].
+    #[arg(long, global = true, env = "GOSSIP_LOG", value_name = "FILTER")]
+    log: Option<String>,
+    /// Check every row of the input and print the runs it expands to, without simulating them
+    /// (for run, sweep, compact, check and coordinate).
+    #[arg(long, global = true)]
+    dry_run: bool,
+    /// Skip rows of the input that can't be read or can't run, reporting them on stderr, rather
+    /// than stopping. Rows that can't be read don't count towards the row numbers for `replay`.
+    #[arg(long, global = true)]
+    skip_invalid: bool,
+    /// Overwrite the results file if it already exists, or replace the frames in the directory
+    /// of `frames`.
+    #[arg(long, global = true, conflicts_with = "append")]
+    force: bool,
+    /// Add the results to the end of the results file if it already exists, as long as it has
+    /// the same columns.
+    #[arg(long, global = true)]
+    append: bool,
+    /// Report progress as machine-readable events, in `ndjson` (a JSON object per line), for
+    /// run, sweep and compact.
+    #[arg(long, global = true, value_name = "FORMAT")]
+    progress: Option<ProgressFormat>,
+    /// File or named pipe to report progress to [default: stderr].
+    #[arg(long, global = true, value_name = "PATH", requires = "progress")]
+    progress_to: Option<String>,
+    /// Where progress is reported, once opened.
+    #[arg(skip)]
+    progress_sink: Option<Progress>,
+    /// Only use these rows of the input, counting from 1, like `3,10..20` (which includes 20),
+    /// `10..` or `..20`.
+    #[arg(long, global = true, value_delimiter = ',', value_name = "ROWS")]
+    rows: Vec<RowRange>,
+    /// Only use the rows whose parameters match an expression like `"n >= 1000 && k > n / 2"`.
+    #[arg(long, global = true, value_name = "EXPR", value_parser = Filter::parse)]
+    filter: Option<Filter>,
+    /// Write a row for every run rather than one with their average, for `stats compare` (for
+    /// run, sweep and compact).
+    #[arg(long, global = true)]
+    per_run: bool,
+    /// Fill in the parameters that rows leave out from a named scenario: `eth-attestation`, for
+    /// an approximation of the attestations of a slot of Ethereum (see the `preset` module).
+    #[arg(long, global = true, value_name = "NAME")]
+    preset: Option<Preset>,
 }
 
-type VoteMap = BTreeMap<usize, VoteInfo>;
-type VoteDiff = BTreeMap<usize, BTreeSet<usize>>;
+#[derive(Subcommand)]
+enum StatsCommand {
+    /// Test whether the rounds and exchanges of the runs of two results files differ, pairing
+    /// up the runs with the same parameters.
+    ///
+    /// The results need a row for every run, as `--per-run` writes. Writes a Mann–Whitney U test
+    /// of each measurement for each set of parameters to stdout.
+    Compare {
+        /// Results to compare against.
+        a: String,
+        /// Results to compare.
+        b: String,
+    },
+    /// Fit normal, log-normal and Gumbel distributions to the rounds of the runs of each set of
+    /// parameters in a results file, and pick the best.
+    ///
+    /// The results need a row for every run, as `--per-run` writes, and at least 3 runs of a set
+    /// of parameters to fit. Writes the moments of the rounds, each fit's Kolmogorov–Smirnov
+    /// statistic, and a percentile of the best fit to stdout. Rounds are whole numbers, so the
+    /// statistic is for choosing between the fits rather than testing them.
+    Fit {
+        /// Results to fit.
+        input: String,
+        /// Percentile of the best fit to report, for setting timeouts.
+        #[arg(long, default_value_t = 99.0)]
+        percentile: f64,
+    },
+}
 
-#[derive(Clone, Debug)]
-struct Node {
-    /// Our node ID.
-    id: usize,
-    /// Total number of nodes in our universe (constant for now).
-    num_nodes: usize,
-    /// Map from vote ID to number of voters.
-    votes: VoteMap,
+/// How `search` looks for the smallest value meeting its target.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SearchMethod {
+    Binary,
+    Grid,
 }
 
-impl Node {
-    fn new(id: usize, num_nodes: usize) -> Self {
-        Node {
-            id,
-            num_nodes,
-            votes: VoteMap::new(),
+impl std::str::FromStr for SearchMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "binary" => Ok(SearchMethod::Binary),
+            "grid" => Ok(SearchMethod::Grid),
+            _ => Err(format!("unknown search method {:?}, expected binary or grid", s)),
         }
     }
 }
 
-#[derive(Clone, Default, Debug)]
-struct VoteInfo {
-    /// All nodes that voted for this proposal.
-    voters: BTreeSet<usize>,
+/// A range of rows given to `--rows`, including both ends.
+#[derive(Clone, Copy, Debug)]
+struct RowRange {
+    first: usize,
+    last: usize,
 }
 
-impl Node {
-    fn vote_for(&mut self, vote_id: usize) {
-        let our_id = self.id;
-        self.votes
-            .entry(vote_id)
-            .or_insert_with(VoteInfo::default)
-            .voters
-            .insert(our_id);
+impl RowRange {
+    fn contains(self, row: usize) -> bool {
+        (self.first..=self.last).contains(&row)
     }
+}
 
-    fn has_voted_for(&self, vote_id: usize) -> bool {
-        self.votes
-            .get(&vote_id)
-            .map(|vote_info| vote_info.voters.contains(&self.id))
-            .unwrap_or(false)
-    }
+impl std::str::FromStr for RowRange {
+    type Err = String;
 
-    fn has_quorum_for(&self, vote_id: usize) -> bool {
-        self.votes
-            .get(&vote_id)
-            .map(|vote_info| has_quorum(vote_info.voters.len(), self.num_nodes))
-            .unwrap_or(false)
+    fn from_str(s: &str) -> Result<Self, String> {
+        let parse = |row: &str, default| match row.trim() {
+            "" => Ok(default),
+            row => row.parse().map_err(|_| format!("invalid row {:?}", row)),
+        };
+        let range = match s.split_once("..") {
+            Some((first, last)) => {
+                RowRange { first: parse(first, 1)?, last: parse(last, usize::MAX)? }
+            }
+            None => {
+                let row = parse(s, 0)?;
+                RowRange { first: row, last: row }
+            }
+        };
+        if range.first == 0 {
+            return Err(format!("invalid rows {:?}, rows are counted from 1", s));
+        }
+        Ok(range)
     }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Simulate every row of a parameters CSV, writing the average of several runs of each.
+    Run(RunArgs),
+    /// Simulate every combination of the given parameter values.
+    ///
+    /// Combinations that can't run, such as those with too few voters for a quorum, are skipped.
+    Sweep(SweepArgs),
+    /// Compare two results files, pairing up rows with the same parameters.
+    ///
+    /// Writes the change in every measurement from the first file to the second to stdout.
+    Compare {
+        /// Results to compare against.
+        a: String,
+        /// Results to compare.
+        b: String,
+    },
+    /// Repeat the runs of one row of an earlier `run --seed`, writing the metrics of every round.
+    Replay {
+        /// Parameters CSV or config file of the earlier run.
+        input: String,
+        /// Results file for the per-round metrics.
+        output: String,
+        /// Row to replay, counting the first row after the header (or the first run) as 1.
+        #[arg(long)]
+        row: usize,
+        /// Number of runs, which must match the earlier run's [default: 20, unless the config
+        /// sets it].
+        #[arg(long)]
+        repetitions: Option<usize>,
+    },
+    /// Step through a single run of one row at a prompt, inspecting the nodes as it goes.
+    ///
+    /// Type `help` at the prompt for the commands. Commands are also read from a pipe, which
+    /// runs them without a prompt.
+    Interactive {
+        /// Parameters CSV or config file.
+        input: String,
+        /// Row to step through, counting the first row after the header (or the first run) as 1.
+        #[arg(long, default_value_t = 1)]
+        row: usize,
+    },
+    /// Write a frame of every node's votes and quorum for each round of a single run of one row,
+    /// for rendering an animation of the spread.
+    Frames {
+        /// Parameters CSV or config file.
+        input: String,
+        /// Directory for the frames, one file per round in the results format. It must be empty,
+        /// unless `--force` is given to replace the frames in it.
+        output: String,
+        /// Row to run, counting the first row after the header (or the first run) as 1.
+        #[arg(long, default_value_t = 1)]
+        row: usize,
+    },
+    /// Perturb each of `n`, `k`, `voting_steps`, and `max_messages_per_round` and `max_rounds` if
+    /// the row sets them, of one row in turn, writing the change in the rounds and bytes sent from
+    /// the row as it is to stdout, the parameters with the largest effect on rounds first.
+    ///
+    /// Every run uses the row's seed, so that the changes come from the parameters rather than
+    /// the randomness. Bytes are those of the votes sent, as the UDP daemon encodes them.
+    Sensitivity {
+        /// Parameters CSV or config file.
+        input: String,
+        /// Row to perturb, counting the first row after the header (or the first run) as 1.
+        #[arg(long, default_value_t = 1)]
+        row: usize,
+        /// Percentages to perturb each parameter by, both up and down, separated by commas.
+        #[arg(long, default_value = "10,25", value_delimiter = ',')]
+        changes: Vec<f64>,
+        /// Number of runs to average for each perturbation [default: 20, unless the config sets
+        /// it].
+        #[arg(long)]
+        repetitions: Option<usize>,
+    },
+    /// Find the smallest value of `n`, `k`, `voting_steps`, `max_messages_per_round` or
+    /// `max_rounds` for one row at which enough of its runs converge within a number of rounds.
+    ///
+    /// Writes the values tried, with the fraction of their runs that converged in time, to stdout,
+    /// and the smallest value meeting the target to stderr.
+    Search(SearchArgs),
+    /// Run one row with message loss rates from 0 up to a maximum, writing how likely its runs
+    /// are to converge in time and how long they take at each rate to stdout.
+    ///
+    /// Every rate uses the row's seed, and comes with 95% confidence intervals: the Wilson
+    /// interval for the probability of converging, and the normal one for the mean rounds.
+    LossCurve(LossCurveArgs),
+    /// Fit a loss rate and a length of round to a trace of the deliveries of a message in a real
+    /// network, writing how well each rate fits to stdout and the best to stderr.
+    ///
+    /// Each rate simulates one vote spreading from a single voter, and comes with the round
+    /// length that best lines up the simulated spread with the trace. See the `trace` module for
+    /// the format of the trace.
+    Import(ImportArgs),
+    /// Print the count, mean, standard deviation and range of every numeric column of a CSV.
+    Summarize {
+        /// Results file (or any other CSV) to summarize.
+        input: String,
+    },
+    /// Statistical tests on results files.
+    Stats {
+        #[command(subcommand)]
+        command: StatsCommand,
+    },
+    /// Like `run`, but insist on compact mode, failing on rows that it doesn't support.
+    Compact(RunArgs),
+    /// Explore every choice of partners in every round for tiny networks (n <= 6).
+    Check {
+        /// Parameters CSV or config file.
+        input: String,
+        /// Results file.
+        output: String,
+        /// Number of rounds to explore.
+        max_rounds: usize,
+    },
+    /// Run a gRPC server that runs simulations for a remote controller.
+    Serve {
+        #[arg(default_value = DEFAULT_SERVE_ADDR)]
+        address: String,
+    },
+    /// Run an HTTP server with a JSON API for running simulations.
+    ServeHttp {
+        #[arg(default_value = DEFAULT_SERVE_HTTP_ADDR)]
+        address: String,
+    },
+    /// Run simulations handed out by `coordinate`.
+    Worker {
+        #[arg(default_value = DEFAULT_WORKER_ADDR)]
+        address: String,
+    },
+    /// Spread the rows of a parameters CSV over several workers.
+    Coordinate {
+        /// Parameters CSV or config file.
+        input: String,
+        /// Results file, written in input order.
+        output: String,
+        /// File listing a worker `host:port` per line.
+        workers: String,
+        /// Number of runs to average for each parameter set [default: 20, unless the config sets
+        /// it].
+        #[arg(long)]
+        repetitions: Option<usize>,
+    },
+    /// Run a real node speaking the gossip protocol over UDP.
+    Daemon {
+        /// File listing a `host:port` per line, with node `i` on line `i`.
+        peers: String,
+        /// Our node ID.
+        id: NodeId,
+        /// Vote for rumour 0 on startup.
+        #[arg(long)]
+        vote: bool,
+    },
+    /// Run every row of a parameters CSV over a libp2p gossipsub swarm instead of simulating.
+    Gossipsub {
+        /// Parameters CSV or config file.
+        input: String,
+        /// Results file.
+        output: String,
+        /// Number of runs to average for each parameter set [default: 20, unless the config sets
+        /// it].
+        #[arg(long)]
+        repetitions: Option<usize>,
+    },
+    /// Print a completion script for the subcommands and flags to stdout.
+    ///
+    /// For example, `gossip completions bash > /etc/bash_completion.d/gossip`.
+    Completions {
+        /// Shell to complete in: bash, zsh, fish, elvish or powershell.
+        shell: Shell,
+    },
+}
+
+#[derive(Args)]
+struct RunArgs {
+    /// Parameters CSV or config file.
+    input: String,
+    /// Results file [default: the config's `output.path`, or else a new file in `results/`].
+    output: Option<String>,
+    /// Number of runs to average for each row [default: 20, unless the config sets it].
+    #[arg(long)]
+    repetitions: Option<usize>,
+    /// Keep watching the input, and run it again whenever it changes, overwriting the results.
+    #[arg(long)]
+    watch: bool,
+}
+
+#[derive(Args)]
+struct SearchArgs {
+    /// Parameters CSV or config file.
+    input: String,
+    /// Row to search from, counting the first row after the header (or the first run) as 1.
+    #[arg(long, default_value_t = 1)]
+    row: usize,
+    /// Parameter to search over: n, k, voting_steps, max_messages_per_round or max_rounds.
+    #[arg(long)]
+    param: String,
+    /// Values to search, as a range like `100..1000 step 10` (or for `k`, `50%..90%`).
+    #[arg(long)]
+    values: String,
+    /// Rounds within which a run must converge.
+    #[arg(long)]
+    within: usize,
+    /// Fraction of the runs that must converge in time.
+    #[arg(long, default_value_t = 0.99)]
+    probability: f64,
+    /// How to search: `binary`, which assumes that the outcome is monotone, with every value
+    /// after one meeting the target meeting it too, or `grid`, which tries every value. More
+    /// voters, messages per round or rounds before the deadline only help, but more nodes or
+    /// voting steps only slow a run down, so search those with `grid`.
+    #[arg(long, default_value = "binary")]
+    method: SearchMethod,
+    /// Number of runs of each value [default: 20, unless the config sets it].
+    #[arg(long)]
+    repetitions: Option<usize>,
+}
 
-    fn apply_diff(&mut self, diff: VoteDiff) {
-        for (vote_id, voters) in diff {
-            self.votes
-                .entry(vote_id)
-                .or_insert_with(VoteInfo::default)
-                .voters
-                .extend(voters);
+#[derive(Args)]
+struct LossCurveArgs {
+    /// Parameters CSV or config file.
+    input: String,
+    /// Row to run, counting the first row after the header (or the first run) as 1.
+    #[arg(long, default_value_t = 1)]
+    row: usize,
+    /// Highest loss rate, which must be less than 1.
+    #[arg(long, default_value_t = 0.5)]
+    max_loss: f64,
+    /// Number of rates above 0, evenly spaced up to `--max-loss`.
+    #[arg(long, default_value_t = 10)]
+    steps: usize,
+    /// Rounds within which a run counts as converging.
+    #[arg(long)]
+    within: usize,
+    /// Number of runs at each rate [default: 20, unless the config sets it].
+    #[arg(long)]
+    repetitions: Option<usize>,
+}
+
+#[derive(Args)]
+struct ImportArgs {
+    /// Trace CSV, with a `node,time` row for every time a node received the message.
+    trace: String,
+    /// Number of nodes in the network, counting any that never received the message [default:
+    /// the nodes in the trace].
+    #[arg(long)]
+    nodes: Option<usize>,
+    /// Highest loss rate to try, which must be less than 1.
+    #[arg(long, default_value_t = 0.5)]
+    max_loss: f64,
+    /// Number of rates above 0 to try, evenly spaced up to `--max-loss`.
+    #[arg(long, default_value_t = 10)]
+    steps: usize,
+    /// Number of runs at each rate [default: 20].
+    #[arg(long)]
+    repetitions: Option<usize>,
+    /// Parameters CSV to write the best fitting row to, for running it like any other.
+    #[arg(long)]
+    calibrated: Option<String>,
+}
+
+#[derive(Args)]
+struct SweepArgs {
+    /// Results file [default: a new file in `results/`].
+    output: Option<String>,
+    /// Numbers of nodes, separated by commas. Each can be a range like `100..1000 step 100`.
+    #[arg(short, long, required = true, value_delimiter = ',')]
+    n: Vec<String>,
+    /// Numbers of voters, separated by commas. Each can be a percentage of `n` like `70%`, or a
+    /// range like `50%..90% step 10%`.
+    #[arg(short, long, required = true, value_delimiter = ',')]
+    k: Vec<String>,
+    /// Numbers of rounds to spread the votes over, separated by commas. Each can be a range.
+    #[arg(long, default_value = "1", value_delimiter = ',')]
+    voting_steps: Vec<String>,
+    /// Quorum rules, separated by commas [default: majority].
+    #[arg(long, value_delimiter = ',')]
+    quorum: Vec<String>,
+    /// Partner modes (random or matched), separated by commas [default: random].
+    #[arg(long, value_delimiter = ',')]
+    partners: Vec<PartnerMode>,
+    /// Label for every combination, copied into its result.
+    #[arg(long)]
+    label: Option<String>,
+    /// Number of runs to average for each combination.
+    #[arg(long, default_value_t = REPETITIONS)]
+    repetitions: usize,
+}
+
+impl SweepArgs {
+    /// Every combination of the values that can run, with the later flags varying fastest.
+    fn params(&self) -> Result<Vec<Params>, Box<dyn Error>> {
+        for spec in &self.quorum {
+            parse_quorum_rule(spec)?;
+        }
+
+        // The same fields as a run in a config file, leaving out empty lists for the defaults.
+        let mut fields = serde_json::Map::new();
+        fields.insert("n".into(), serde_json::to_value(&self.n)?);
+        fields.insert("k".into(), serde_json::to_value(&self.k)?);
+        fields.insert("voting_steps".into(), serde_json::to_value(&self.voting_steps)?);
+        if !self.quorum.is_empty() {
+            fields.insert("quorum".into(), serde_json::to_value(&self.quorum)?);
+        }
+        if !self.partners.is_empty() {
+            fields.insert("partners".into(), serde_json::to_value(&self.partners)?);
         }
+        if let Some(ref label) = self.label {
+            fields.insert("label".into(), label.clone().into());
+        }
+        Ok(config::expand_run(&fields)?)
     }
 }
 
-fn has_quorum(k: usize, n: usize) -> bool {
-    2 * k > n
+/// A round's metrics, with the phase times flattened into columns so they can be written as CSV.
+#[derive(Clone, Serialize)]
+struct RoundRow {
+    repetition: usize,
+    round: usize,
+    nodes_with_quorum: usize,
+    num_exchanges: usize,
+    num_vote_exchanges: usize,
+    gossip_time: Option<f64>,
+    merge_time: Option<f64>,
+    apply_time: Option<f64>,
+    convergence_time: Option<f64>,
 }
 
-fn choose_partner<R: Rng>(our_id: usize, n: usize, rng: &mut R) -> usize {
-    loop {
-        let p = rng.gen_range(0, n);
-        if p != our_id {
-            return p;
+impl From<&RoundMetrics> for RoundRow {
+    fn from(metrics: &RoundMetrics) -> Self {
+        RoundRow {
+            repetition: metrics.repetition,
+            round: metrics.round,
+            nodes_with_quorum: metrics.nodes_with_quorum,
+            num_exchanges: metrics.num_exchanges,
+            num_vote_exchanges: metrics.num_vote_exchanges,
+            gossip_time: metrics.times.map(|times| times.gossip),
+            merge_time: metrics.times.map(|times| times.merge),
+            apply_time: metrics.times.map(|times| times.apply),
+            convergence_time: metrics.times.map(|times| times.convergence),
         }
     }
 }
 
-// Gossip sent n1 => n2.
-fn compute_push_gossip(n1: &Node, n2: &Node) -> Option<VoteDiff> {
-    let diff: VoteDiff = n1.votes
-        .iter()
-        .filter_map(|(&vote_id, vote_info)| {
-            // If n2 doesn't have a quorum for one of n1's votes, it gets n1's voters sent to it.
-            if !n2.has_quorum_for(vote_id) {
-                let new_voters = if let Some(existing_info) = n2.votes.get(&vote_id) {
-                    &vote_info.voters - &existing_info.voters
-                } else {
-                    vote_info.voters.clone()
-                };
-                if !new_voters.is_empty() {
-                    return Some((vote_id, new_voters));
+/// The change in one measurement between two results files.
+#[derive(Serialize)]
+struct Change {
+    /// The parameters both rows share.
+    params: String,
+    metric: String,
+    a: f64,
+    b: f64,
+    difference: f64,
+    /// The difference as a fraction of `a`, unless `a` is 0.
+    relative_difference: Option<f64>,
+}
+
+/// Whether one measurement differs between the runs of two results files with some parameters.
+#[derive(Serialize)]
+struct Significance {
+    /// The parameters the runs share.
+    params: String,
+    metric: String,
+    runs_a: usize,
+    runs_b: usize,
+    mean_a: f64,
+    mean_b: f64,
+    /// The Mann–Whitney U statistic, the number of pairs of runs in which `b`'s is larger.
+    u: f64,
+    p_value: f64,
+    /// The rank-biserial correlation, from -1 if every run of `b` is below every run of `a` to
+    /// 1 if every one is above.
+    effect_size: f64,
+}
+
+/// A row of the table written by `sensitivity`.
+#[derive(Serialize)]
+struct Perturbation {
+    /// The parameter perturbed, or `none` for the row as it is.
+    parameter: &'static str,
+    /// The change to the parameter, as a percentage of its value in the row.
+    change: f64,
+    /// The parameter's value after rounding.
+    value: Option<usize>,
+    num_iterations: f64,
+    bytes: usize,
+    /// The changes in rounds and bytes from the row as it is, as fractions of it.
+    iterations_change: Option<f64>,
+    bytes_change: Option<f64>,
+    error: Option<String>,
+}
+
+/// A value tried by `search`.
+#[derive(Serialize)]
+struct SearchTrial {
+    value: usize,
+    runs: usize,
+    /// The fraction of the runs that converged within the rounds of the target.
+    converged: f64,
+    num_iterations: f64,
+    meets_target: bool,
+}
+
+/// A point on the curves written by `loss-curve`.
+#[derive(Serialize)]
+struct LossPoint {
+    loss: f64,
+    runs: usize,
+    /// The fraction of the runs that converged within the rounds asked for, and its interval.
+    converged: f64,
+    converged_low: f64,
+    converged_high: f64,
+    /// The mean rounds of the runs, and its interval.
+    num_iterations: f64,
+    iterations_low: f64,
+    iterations_high: f64,
+}
+
+/// How well a loss rate fits a trace, as written by `import`.
+#[derive(Serialize)]
+struct TraceFit {
+    loss: f64,
+    runs: usize,
+    /// The length of a round that best fits the trace, in the trace's unit of time, and the root
+    /// mean square difference in the fraction of nodes with the message that it leaves.
+    round_duration: f64,
+    fit_error: f64,
+    /// The mean rounds until half, 90% and all of the nodes had the message in the simulation,
+    /// and the round lengths until they did in the trace.
+    rounds_50: Option<f64>,
+    trace_rounds_50: Option<f64>,
+    rounds_90: Option<f64>,
+    trace_rounds_90: Option<f64>,
+    num_iterations: f64,
+    trace_rounds: Option<f64>,
+    /// Exchanges that carried the vote per node in the simulation, which only sends it to nodes
+    /// that lack it, to compare with the trace's deliveries per node.
+    vote_messages_per_node: f64,
+}
+
+/// The distributions fitted to the rounds of the runs of a set of parameters by `stats fit`.
+#[derive(Serialize)]
+struct RoundsFit {
+    /// The parameters the runs share.
+    params: String,
+    runs: usize,
+    mean: f64,
+    variance: f64,
+    skewness: f64,
+    /// The distribution with the smallest Kolmogorov–Smirnov statistic, and its parameters.
+    best_fit: &'static str,
+    location: f64,
+    scale: f64,
+    /// The rounds that the percentile asked for of the runs take at most under the best fit.
+    percentile_rounds: f64,
+    normal_ks: Option<f64>,
+    lognormal_ks: Option<f64>,
+    gumbel_ks: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct ColumnSummary {
+    column: String,
+    count: usize,
+    mean: f64,
+    std_dev: f64,
+    min: f64,
+    max: f64,
+}
+
+/// A row of the plan printed by `--dry-run`.
+#[derive(Serialize)]
+struct PlannedRow {
+    row: usize,
+    n: usize,
+    k: usize,
+    voting_steps: usize,
+    script: Option<String>,
+    quorum: Option<String>,
+    partners: Option<PartnerMode>,
+    timings: bool,
+    seed: Option<u64>,
+    label: Option<String>,
+    /// How the row would run: `compact`, `general` or `check`.
+    mode: &'static str,
+}
+
+/// How each row would run, or why it can't.
+type Mode = fn(&Params) -> Result<&'static str, String>;
+
+/// The mode of a row run by `simulate_params`.
+fn simulation_mode(params: &Params) -> Result<&'static str, String> {
+    Ok(gossip::simulation_mode(params).name())
+}
+
+fn compact_mode(params: &Params) -> Result<&'static str, String> {
+    match params.mode {
+        Some(SimulationMode::General) => Err("the row asks for general mode".into()),
+        _ if compact::supports(params) => Ok("compact"),
+        _ => Err("compact mode doesn't support scripts, membership, elections or quorum rules \
+                  that look at who voted"
+            .into()),
+    }
+}
+
+fn check_mode(params: &Params) -> Result<&'static str, String> {
+    check::validate(params).map(|()| "check")
+}
+
+/// Signature shared by `simulate_runs` and `compact::simulate_runs`.
+type Simulate = fn(
+    &Params,
+    usize,
+    &mut XorShiftRng,
+    &mut dyn FnMut(&RoundMetrics),
+) -> Result<Vec<SimulationResult>, Box<dyn Error>>;
+
+impl Options {
+    /// Load a config file or parameters CSV, skipping invalid rows if asked to.
+    fn load_config(&self, filename: &str) -> Result<config::Config, Box<dyn Error>> {
+        let mut config = match self.skip_invalid {
+            true => config::load_skipping_invalid(filename)?,
+            false => config::load(filename)?,
+        };
+        config.runs = self.apply_preset(config.runs);
+        Ok(config)
+    }
+
+    /// `runs` with the parameters they leave out filled in from `--preset`, if it was given.
+    fn apply_preset(&self, mut runs: Vec<Params>) -> Vec<Params> {
+        if let Some(preset) = self.preset {
+            runs.iter_mut().for_each(|params| preset.apply(params));
+        }
+        runs
+    }
+
+    /// Number the parameter sets in `runs` from 1, keeping the rows chosen by `--rows` and
+    /// `--filter`.
+    fn select(&self, runs: Vec<Params>) -> Result<Vec<(usize, Params)>, Box<dyn Error>> {
+        let mut selected = vec![];
+        for (i, params) in runs.into_iter().enumerate() {
+            let row = i + 1;
+            if !self.rows.is_empty() && !self.rows.iter().any(|range| range.contains(row)) {
+                continue;
+            }
+            if let Some(ref filter) = self.filter {
+                if !filter.matches(&params).map_err(|e| format!("row {}: {}", row, e))? {
+                    continue;
                 }
             }
-            None
+            selected.push((row, params));
+        }
+        if selected.is_empty() && (!self.rows.is_empty() || self.filter.is_some()) {
+            warn!("no rows match --rows or --filter");
+        }
+        Ok(selected)
+    }
+
+    /// The seed to use, falling back to the config's and then choosing one at random.
+    fn seed(&self, config_seed: Option<u64>) -> u64 {
+        let seed = self.seed.or(config_seed).unwrap_or_else(|| weak_rng().gen());
+        info!(seed, "seeded the random number generator");
+        seed
+    }
+
+    /// Check that results can be written to `output` without losing what's already there.
+    fn check_output(&self, output: &str) -> Result<(), Box<dyn Error>> {
+        if output != STDIO && !self.force && !self.append && Path::new(output).exists() {
+            return Err(From::from(format!(
+                "{} already exists, pass --force to overwrite it or --append to add to it",
+                output
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check that frames can be written to the directory `output_dir`, which must be empty
+    /// unless `--force` is given to replace the frames in it.
+    fn check_output_dir(&self, output_dir: &str) -> Result<(), Box<dyn Error>> {
+        if self.append {
+            return Err(From::from("frames can't be added to with --append"));
+        }
+        let is_empty = match fs::read_dir(output_dir) {
+            Ok(mut entries) => entries.next().is_none(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => true,
+            Err(e) => return Err(From::from(format!("failed to read {}: {}", output_dir, e))),
+        };
+        if !self.force && !is_empty {
+            return Err(From::from(format!(
+                "{} isn't empty, pass --force to replace the frames in it",
+                output_dir
+            )));
+        }
+        Ok(())
+    }
+
+    /// Write `results` to `output`, or add them to the end of it with `--append`. Results that
+    /// can't be appended are written to a new file instead, so they aren't lost.
+    fn write_results<T: serde::Serialize + Clone>(
+        &self,
+        output: &str,
+        results: Vec<T>,
+        format: OutputFormat,
+    ) -> Result<(), Box<dyn Error>> {
+        if !self.append {
+            return Ok(write_results(output, results, format)?);
+        }
+        append_results(output, results.clone(), format).or_else(|e| {
+            let fallback = default_output(output, format)?;
+            write_results(&fallback, results, format)?;
+            Err(From::from(format!("{}, so wrote the results to {} instead", e, fallback)))
         })
-        .collect();
+    }
+
+    /// The format to write results in, falling back to the config's and then CSV.
+    fn format(&self, config_format: Option<OutputFormat>) -> OutputFormat {
+        self.format.or(config_format).unwrap_or_default()
+    }
 
-    if !diff.is_empty() {
-        Some(diff)
-    } else {
-        None
+    /// Simulate the numbered `rows` in order, with each row's runs drawing from a generator of
+    /// their own so that `replay` can repeat any one of them. Rows can set their own seed, and
+    /// otherwise get one derived from `seed` and their number.
+    ///
+    /// A row that fails, even by panicking, gets a result recording the error rather than
+    /// stopping the rest, and its number is returned alongside the results.
+    fn simulate_rows(
+        &self,
+        rows: &[(usize, Params)],
+        repetitions: usize,
+        seed: u64,
+        simulate: Simulate,
+    ) -> (Vec<SimulationResult>, Vec<usize>) {
+        let mut results = vec![];
+        let mut failed_rows = vec![];
+        let progress = self.progress_sink.as_ref();
+        for &(row, ref params) in rows {
+            let valid = validate_params(params);
+            if let (Err(e), true) = (&valid, self.skip_invalid) {
+                warn!("skipping row {}: {}", row, e);
+                continue;
+            }
+            if let Some(progress) = progress {
+                progress.row_started(row, params, repetitions);
+            }
+            let simulated = valid
+                .and_then(|()| simulate_row(params, row, repetitions, seed, simulate, progress));
+            let (result, runs) = match simulated {
+                Ok(runs) => (average_results(params, runs.clone()), runs),
+                Err(e) => {
+                    error!("row {} failed: {}", row, e);
+                    failed_rows.push(row);
+                    let failed = SimulationResult::failed(params, e);
+                    (failed.clone(), vec![failed])
+                }
+            };
+            info!(
+                row,
+                rounds = result.num_iterations,
+                exchanges = result.num_exchanges,
+                votes_sent = result.num_vote_exchanges,
+                "finished"
+            );
+            if let Some(progress) = progress {
+                progress.row_finished(row, &result);
+            }
+            match self.per_run {
+                true => results.extend(runs),
+                false => results.push(result),
+            }
+        }
+        if let Some(progress) = progress {
+            progress.emit(&Event::Finished { rows: results.len(), failed: failed_rows.len() });
+        }
+        (results, failed_rows)
+    }
+
+    /// Model check the numbered `rows` for `max_rounds` rounds in order, like `simulate_rows`
+    /// does, recording the error of a row that fails in its result rather than stopping.
+    fn check_rows(&self, rows: &[(usize, Params)], max_rounds: usize)
+        -> (Vec<CheckResult>, Vec<usize>)
+    {
+        let mut results = vec![];
+        let mut failed_rows = vec![];
+        for &(row, ref params) in rows {
+            let valid = validate_params(params).and_then(|()| check::validate(params));
+            if let (Err(e), true) = (&valid, self.skip_invalid) {
+                warn!("skipping row {}: {}", row, e);
+                continue;
+            }
+            info!(row, n = params.n, k = params.k, voting_steps = params.voting_steps, "checking");
+            let checked = valid.and_then(|()| {
+                catch_panic(|| check::check(params, max_rounds).map_err(|e| e.to_string()))
+            });
+            match checked {
+                Ok(result) => results.push(result),
+                Err(e) => {
+                    error!("row {} failed: {}", row, e);
+                    failed_rows.push(row);
+                    results.push(CheckResult::failed(params, max_rounds, e));
+                }
+            }
+        }
+        (results, failed_rows)
+    }
+
+    /// Write the results of a batch of rows, then fail if any of them did, naming them.
+    fn finish_rows<T: serde::Serialize + Clone>(
+        &self,
+        output: &str,
+        generated: bool,
+        results: Vec<T>,
+        failed_rows: &[usize],
+        format: OutputFormat,
+    ) -> Result<(), Box<dyn Error>> {
+        let num_rows = results.len();
+        self.write_results(output, results, format)?;
+        if generated {
+            eprintln!("wrote results to {}", output);
+        }
+        if failed_rows.is_empty() {
+            return Ok(());
+        }
+        let rows = failed_rows.iter().map(|row| row.to_string()).collect::<Vec<_>>();
+        Err(From::from(format!(
+            "{} of the {} rows failed ({} {}), see the error column of {}",
+            failed_rows.len(),
+            num_rows,
+            if failed_rows.len() == 1 { "row" } else { "rows" },
+            rows.join(", "),
+            output
+        )))
     }
 }
 
-fn compute_push_pull_gossip(n1: &Node, n2: &Node) -> (Option<VoteDiff>, Option<VoteDiff>) {
-    (compute_push_gossip(n2, n1), compute_push_gossip(n1, n2))
+/// Simulate the runs of one row, catching any panic so that it only fails that row.
+fn simulate_row(
+    params: &Params,
+    row: usize,
+    repetitions: usize,
+    seed: u64,
+    simulate: Simulate,
+    progress: Option<&Progress>,
+) -> Result<Vec<SimulationResult>, String> {
+    info!(
+        row,
+        n = params.n,
+        k = params.k,
+        voting_steps = params.voting_steps,
+        repetitions,
+        "simulating"
+    );
+    let rng_seed = params.seed.unwrap_or_else(|| row_seed(seed, row));
+    let mut rng = seeded_rng(rng_seed);
+    let mut log = log_round(row);
+    let mut milestones = progress.map(|progress| progress.milestones(row, params.n));
+    let mut on_round = |metrics: &RoundMetrics| {
+        log(metrics);
+        if let Some(milestones) = &mut milestones {
+            milestones(metrics);
+        }
+    };
+    let runs = catch_panic(|| {
+        simulate(params, repetitions, &mut rng, &mut on_round).map_err(|e| e.to_string())
+    })?;
+    if runs.is_empty() {
+        return Err("no runs to average, as --repetitions is 0".to_string());
+    }
+    Ok(runs.into_iter().map(|run| SimulationResult { seed: Some(rng_seed), ..run }).collect())
 }
 
-fn add_updates(updates: &mut BTreeMap<usize, VoteDiff>, node: usize, diff: VoteDiff) {
-    let existing_diff = updates.entry(node)
-        .or_insert_with(VoteDiff::new);
+/// Call `f`, turning a panic into an error so that it only fails the row it was running.
+fn catch_panic<T>(f: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|panic| {
+        let message = match panic.downcast_ref::<&str>() {
+            Some(message) => message.to_string(),
+            None => panic.downcast_ref::<String>().cloned().unwrap_or_default(),
+        };
+        Err(format!("panicked: {}", message))
+    })
+}
 
-    for (vote_id, voters) in diff {
-        let existing_voters = existing_diff.entry(vote_id).or_insert_with(BTreeSet::new);
-        existing_voters.extend(voters);
+/// A callback logging the metrics of every round of row `row` at debug level.
+fn log_round(row: usize) -> impl FnMut(&RoundMetrics) {
+    move |metrics| {
+        debug!(
+            row,
+            run = metrics.repetition,
+            round = metrics.round,
+            nodes_with_quorum = metrics.nodes_with_quorum,
+            exchanges = metrics.num_exchanges,
+            votes_sent = metrics.num_vote_exchanges,
+            "round"
+        )
     }
 }
 
-/// Count the number of individual votes in a given `VoteDiff`.
-fn num_votes_in_update(diff: &VoteDiff) -> usize {
-    diff.values().map(|voters| voters.len()).sum()
+/// Log at the level chosen by `-v`, or by `log` or `RUST_LOG` if it isn't given. Only this crate's
+/// logs are raised by `-v`, so that the network libraries don't drown them out.
+fn init_logging(verbose: u8, log: Option<&str>) {
+    let mut invalid_log = None;
+    let filter = match (verbose, log) {
+        (0, Some(log)) => EnvFilter::try_new(log).unwrap_or_else(|e| {
+            invalid_log = Some(e);
+            EnvFilter::new("warn")
+        }),
+        (0, None) => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn")),
+        (1, _) => EnvFilter::new("warn,gossip=info"),
+        (2, _) => EnvFilter::new("warn,gossip=debug"),
+        _ => EnvFilter::new("trace"),
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .init();
+    // Only reported now that there's somewhere to report it.
+    if let Some(e) = invalid_log {
+        warn!("ignoring invalid log filter {:?}: {}", log.unwrap_or_default(), e);
+    }
 }
 
-fn construct_voting_schedule(k: usize, voting_steps: usize) -> BTreeMap<usize, usize> {
-    let per_step = k / voting_steps;
+/// Read a CSV's header and rows, leaving out the rows of results files whose simulation failed.
+fn read_csv(filename: &str) -> Result<(csv::StringRecord, Vec<csv::StringRecord>), Box<dyn Error>> {
+    let input = open_input(filename).map_err(|e| format!("failed to read {}: {}", filename, e))?;
+    let mut reader = csv::Reader::from_reader(input);
+    let headers = reader.headers()?.clone();
+    let mut rows: Vec<csv::StringRecord> = reader.records().collect::<Result<_, _>>()?;
+    if let Some(error) = headers.iter().position(|header| header == "error") {
+        rows.retain(|row| row.get(error).is_none_or(str::is_empty));
+    }
+    Ok((headers, rows))
+}
+
+fn compare(a_file: &str, b_file: &str) -> Result<Vec<Change>, Box<dyn Error>> {
+    if a_file == STDIO && b_file == STDIO {
+        return Err(From::from("can't read both files to compare from stdin"));
+    }
+    let (a_headers, a_rows) = read_csv(a_file)?;
+    let (b_headers, b_rows) = read_csv(b_file)?;
+    let (key_columns, metric_columns) = shared_columns(a_file, &a_headers, b_file, &b_headers)?;
+
+    // Rows of `b` with the same parameters, in order, to pair with those of `a` in order.
+    let mut b_by_key: HashMap<String, VecDeque<&csv::StringRecord>> = HashMap::new();
+    for row in &b_rows {
+        b_by_key.entry(row_key(&key_columns, row, |c| c.2)).or_default().push_back(row);
+    }
 
-    (0..voting_steps).map(|i| {
-        let num_voters = if i == voting_steps - 1 {
-            // Take the remaining votes.
-            k - ((voting_steps - 1) * per_step)
-        } else {
-            per_step
+    let mut changes = vec![];
+    let mut unmatched = 0;
+    for a_row in &a_rows {
+        let params = row_key(&key_columns, a_row, |c| c.1);
+        let b_row = match b_by_key.get_mut(&params).and_then(VecDeque::pop_front) {
+            Some(b_row) => b_row,
+            None => {
+                unmatched += 1;
+                continue;
+            }
         };
-        (i, num_voters)
-    }).collect()
+        for &(metric, a_column, b_column) in &metric_columns {
+            let a = a_row.get(a_column).and_then(|value| value.parse::<f64>().ok());
+            let b = b_row.get(b_column).and_then(|value| value.parse::<f64>().ok());
+            if let (Some(a), Some(b)) = (a, b) {
+                changes.push(Change {
+                    params: params.clone(),
+                    metric: metric.to_string(),
+                    a,
+                    b,
+                    difference: b - a,
+                    relative_difference: (a != 0.0).then(|| (b - a) / a),
+                });
+            }
+        }
+    }
+    if unmatched > 0 {
+        warn!(
+            "{} of the {} rows of {} have no match in {}",
+            unmatched, a_rows.len(), a_file, b_file
+        );
+    }
+    Ok(changes)
 }
 
-fn average_results(params: &Params, results: Vec<SimulationResult>) -> SimulationResult {
-    let result_count = results.len();
-    let num_iterations = results.iter().map(|r| r.num_iterations).sum::<f64>() / result_count as f64;
-    let num_exchanges = results.iter().map(|r| r.num_exchanges).sum::<usize>() / result_count;
-    let num_vote_exchanges = results.iter().map(|r| r.num_vote_exchanges).sum::<usize>() / result_count;
-    let average_votes_held = results.iter().map(|r| r.average_votes_held).sum::<f64>() / result_count as f64;
+/// A column of both of two results files, with its position in each.
+type SharedColumn<'a> = (&'a str, usize, usize);
 
-    SimulationResult {
-        n: params.n,
-        k: params.k,
-        voting_steps: params.voting_steps,
-        num_iterations,
-        num_exchanges,
-        num_vote_exchanges,
-        average_votes_held,
+/// The parameter columns of two results files to pair their rows by, and their other columns in
+/// common.
+fn shared_columns<'a>(
+    a_file: &str,
+    a_headers: &'a csv::StringRecord,
+    b_file: &str,
+    b_headers: &csv::StringRecord,
+) -> Result<(Vec<SharedColumn<'a>>, Vec<SharedColumn<'a>>), Box<dyn Error>> {
+    let b_column = |name: &str| b_headers.iter().position(|header| header == name);
+    let mut key_columns = vec![];
+    let mut metric_columns = vec![];
+    for (a_column, name) in a_headers.iter().enumerate() {
+        match (PARAM_FIELDS.contains(&name), b_column(name)) {
+            // Seeds differ between runs, and files from before a parameter existed lack its
+            // column, so rows are paired by the other parameters they both have.
+            (true, _) if name == "seed" => (),
+            (true, Some(b_column)) => key_columns.push((name, a_column, b_column)),
+            (true, None) => (),
+            (false, Some(b_column)) => metric_columns.push((name, a_column, b_column)),
+            (false, None) => (),
+        }
+    }
+    if key_columns.is_empty() {
+        return Err(From::from(format!(
+            "{} and {} have no parameter columns in common to pair rows by",
+            a_file, b_file
+        )));
     }
+    Ok((key_columns, metric_columns))
 }
 
-fn run_simulation<R: Rng>(params: &Params, rng: &mut R) -> SimulationResult {
-    let n = params.n;
-    let k = params.k;
+/// The parameters of `row` to pair it by, from the columns of its file that `column` picks.
+fn row_key(
+    key_columns: &[SharedColumn],
+    row: &csv::StringRecord,
+    column: fn(&SharedColumn) -> usize,
+) -> String {
+    key_columns
+        .iter()
+        .map(|c| format!("{}={}", c.0, row.get(column(c)).unwrap_or("")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
 
-    let mut nodes: Vec<Node> = (0..n).map(|i| Node::new(i, n)).collect();
+/// Measurements that `stats compare` tests.
+const TESTED_METRICS: &[&str] = &["num_iterations", "num_exchanges"];
 
-    // At each voting step, have roughly an even portion of k vote.
-    let voting_schedule = construct_voting_schedule(k, params.voting_steps);
+fn stats_compare(a_file: &str, b_file: &str) -> Result<Vec<Significance>, Box<dyn Error>> {
+    if a_file == STDIO && b_file == STDIO {
+        return Err(From::from("can't read both files to compare from stdin"));
+    }
+    let (a_headers, a_rows) = read_csv(a_file)?;
+    let (b_headers, b_rows) = read_csv(b_file)?;
+    let (key_columns, metric_columns) = shared_columns(a_file, &a_headers, b_file, &b_headers)?;
+    let metric_columns: Vec<_> =
+        metric_columns.into_iter().filter(|c| TESTED_METRICS.contains(&c.0)).collect();
+    if metric_columns.is_empty() {
+        return Err(From::from(format!(
+            "{} and {} don't both have rounds and exchanges to compare",
+            a_file, b_file
+        )));
+    }
 
-    // Statistics.
-    let mut num_iterations = 0;
-    let mut num_exchanges = 0;
-    let mut num_vote_exchanges = 0;
+    // The runs of each set of parameters, in the order the sets first appear in `a`.
+    let mut a_runs: Vec<(String, Vec<&csv::StringRecord>)> = vec![];
+    for row in &a_rows {
+        let params = row_key(&key_columns, row, |c| c.1);
+        match a_runs.iter_mut().find(|(key, _)| *key == params) {
+            Some((_, runs)) => runs.push(row),
+            None => a_runs.push((params, vec![row])),
+        }
+    }
+    let mut b_runs: HashMap<String, Vec<&csv::StringRecord>> = HashMap::new();
+    for row in &b_rows {
+        b_runs.entry(row_key(&key_columns, row, |c| c.2)).or_default().push(row);
+    }
 
-    // Keep running while any node lacks a quorum.
-    while !nodes.iter().all(|node| node.has_quorum_for(0)) {
-        // Get nodes to vote according to the schedule.
-        if let Some(&num_voters) = voting_schedule.get(&num_iterations) {
-            for node in nodes.iter_mut().filter(|node| !node.has_voted_for(0)).take(num_voters) {
-                node.vote_for(0);
+    let mut tests = vec![];
+    let mut unmatched = 0;
+    let mut single_runs = 0;
+    for (params, a_runs) in &a_runs {
+        let Some(b_runs) = b_runs.get(params) else {
+            unmatched += 1;
+            continue;
+        };
+        if a_runs.len() < 2 || b_runs.len() < 2 {
+            single_runs += 1;
+            continue;
+        }
+        for &(metric, a_column, b_column) in &metric_columns {
+            let values = |runs: &[&csv::StringRecord], column: usize| -> Vec<f64> {
+                runs.iter().filter_map(|row| row.get(column)?.parse().ok()).collect()
+            };
+            let (a, b) = (values(a_runs, a_column), values(b_runs, b_column));
+            if a.is_empty() || b.is_empty() {
+                continue;
             }
+            let test = stats::mann_whitney(&a, &b);
+            tests.push(Significance {
+                params: params.clone(),
+                metric: metric.to_string(),
+                runs_a: a.len(),
+                runs_b: b.len(),
+                mean_a: a.iter().sum::<f64>() / a.len() as f64,
+                mean_b: b.iter().sum::<f64>() / b.len() as f64,
+                u: test.u,
+                p_value: test.p_value,
+                effect_size: test.effect_size,
+            });
         }
+    }
+    if unmatched > 0 {
+        warn!(
+            "{} of the {} sets of parameters of {} have no match in {}",
+            unmatched, a_runs.len(), a_file, b_file
+        );
+    }
+    if single_runs > 0 {
+        let message = format!(
+            "{} of the sets of parameters have a single run in {} or {}, which can't be tested; \
+             write a row for every run with --per-run",
+            single_runs, a_file, b_file
+        );
+        match tests.is_empty() {
+            true => return Err(From::from(message)),
+            false => warn!("{}", message),
+        }
+    }
+    Ok(tests)
+}
 
-        // Each node chooses a random gossip partner.
-        // Push-pull, so everyone contacts someone and solicits updates.
-
-        // Map from node ID to vote ID to voter set.
-        // All updates for this iteration go into this container and get applied atomically
-        // at the end of the iteration (removes the need to index mutably into the vec twice).
-        let mut updates = BTreeMap::new();
+fn stats_fit(filename: &str, percentile: f64) -> Result<Vec<RoundsFit>, Box<dyn Error>> {
+    if !(percentile > 0.0 && percentile < 100.0) {
+        return Err(From::from(format!("--percentile {} must be between 0 and 100", percentile)));
+    }
+    let (headers, rows) = read_csv(filename)?;
+    let column = |name: &str| headers.iter().position(|header| header == name);
+    let rounds_column = column("num_iterations")
+        .ok_or_else(|| format!("{} has no num_iterations column to fit", filename))?;
+    // Every run of a set of parameters has the same seed, so it's one of the parameters here.
+    let key_columns: Vec<SharedColumn> = PARAM_FIELDS
+        .iter()
+        .filter_map(|&name| Some((name, column(name)?, 0)))
+        .collect();
 
-        for (node_id, node) in nodes.iter().enumerate() {
-            let partner_id = choose_partner(node_id, n, rng);
-            let partner = &nodes[partner_id];
+    let mut groups: Vec<(String, Vec<f64>)> = vec![];
+    for row in &rows {
+        let params = row_key(&key_columns, row, |c| c.1);
+        let Some(rounds) = row.get(rounds_column).and_then(|value| value.parse().ok()) else {
+            continue;
+        };
+        match groups.iter_mut().find(|(key, _)| *key == params) {
+            Some((_, values)) => values.push(rounds),
+            None => groups.push((params, vec![rounds])),
+        }
+    }
 
-            let (our_updates, their_updates) = compute_push_pull_gossip(node, partner);
+    let mut fits = vec![];
+    let mut too_few = 0;
+    for (params, rounds) in groups {
+        if rounds.len() < 3 {
+            too_few += 1;
+            continue;
+        }
+        let moments = stats::moments(&rounds);
+        let all_fits = stats::fit_all(&rounds);
+        let ks = |distribution| {
+            let fit = all_fits.iter().find(|fit| fit.distribution == distribution)?;
+            Some(fit.ks_statistic)
+        };
+        let Some(best) = stats::best_fit(&all_fits) else {
+            warn!("can't fit the rounds of {}, which are all {}", params, moments.mean);
+            continue;
+        };
+        fits.push(RoundsFit {
+            params,
+            runs: rounds.len(),
+            mean: moments.mean,
+            variance: moments.variance,
+            skewness: moments.skewness,
+            best_fit: best.distribution.name(),
+            location: best.location,
+            scale: best.scale,
+            percentile_rounds: best.quantile(percentile / 100.0),
+            normal_ks: ks(stats::Distribution::Normal),
+            lognormal_ks: ks(stats::Distribution::LogNormal),
+            gumbel_ks: ks(stats::Distribution::Gumbel),
+        });
+    }
+    if too_few > 0 {
+        let message = format!(
+            "{} of the sets of parameters in {} have fewer than 3 runs to fit; write a row for \
+             every run with --per-run",
+            too_few, filename
+        );
+        match fits.is_empty() {
+            true => return Err(From::from(message)),
+            false => warn!("{}", message),
+        }
+    }
+    Ok(fits)
+}
 
-            if let Some(our_updates) = our_updates {
-                num_exchanges += 1;
-                num_vote_exchanges += num_votes_in_update(&our_updates);
-                add_updates(&mut updates, node_id, our_updates);
+fn summarize(filename: &str) -> Result<Vec<ColumnSummary>, Box<dyn Error>> {
+    let (headers, rows) = read_csv(filename)?;
+    let summaries = headers
+        .iter()
+        .enumerate()
+        .filter_map(|(column, name)| {
+            let values: Vec<f64> =
+                rows.iter().filter_map(|row| row.get(column)?.parse().ok()).collect();
+            if values.is_empty() {
+                return None;
             }
+            let count = values.len();
+            let mean = values.iter().sum::<f64>() / count as f64;
+            let variance = match count {
+                1 => 0.0,
+                _ => values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (count - 1) as f64,
+            };
+            Some(ColumnSummary {
+                column: name.to_string(),
+                count,
+                mean,
+                std_dev: variance.sqrt(),
+                min: values.iter().cloned().fold(f64::INFINITY, f64::min),
+                max: values.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+            })
+        })
+        .collect();
+    Ok(summaries)
+}
 
-            if let Some(their_updates) = their_updates {
-                num_exchanges += 1;
-                num_vote_exchanges += num_votes_in_update(&their_updates);
-                add_updates(&mut updates, partner_id, their_updates);
+/// Check every row of `rows` as if about to run it in the given `mode`, printing the ones that
+/// would run to stdout and why the others can't to stderr.
+fn dry_run(rows: &[(usize, Params)], mode: Mode, plan: &str, format: OutputFormat)
+    -> Result<(), Box<dyn Error>>
+{
+    let mut planned = vec![];
+    let mut num_invalid = 0;
+    for &(row, ref params) in rows {
+        // Loading the strategy checks that its script exists and compiles.
+        let checked = validate_params(params)
+            .and_then(|()| load_strategy(params, &mut weak_rng()).map_err(|e| e.to_string()))
+            .and_then(|_| mode(params));
+        match checked {
+            Ok(mode) => planned.push(PlannedRow {
+                row,
+                n: params.n,
+                k: params.k,
+                voting_steps: params.voting_steps,
+                script: params.script.clone(),
+                quorum: params.quorum.clone(),
+                partners: params.partners,
+                timings: params.timings,
+                seed: params.seed,
+                label: params.label.clone(),
+                mode,
+            }),
+            Err(e) => {
+                error!("row {}: {}", row, e);
+                num_invalid += 1;
             }
         }
+    }
+    write_results_to(io::stdout(), planned, format)?;
+    eprintln!("{} rows, {}", rows.len(), plan);
+    match num_invalid {
+        0 => Ok(()),
+        _ => Err(From::from(format!("{} of the {} rows can't run", num_invalid, rows.len()))),
+    }
+}
 
-        // Apply all those updates.
-        for (node_id, diff) in updates {
-            nodes[node_id].apply_diff(diff);
+/// A new file in `DEFAULT_RESULTS_DIR` for the results of `input`, named after it and the current
+/// time in UTC, like `results/2024-05-01T12-30-00_params.csv`.
+fn default_output(input: &str, format: OutputFormat) -> Result<String, Box<dyn Error>> {
+    let name = match input {
+        STDIO => "stdin",
+        _ => Path::new(input).file_stem().and_then(|stem| stem.to_str()).unwrap_or("results"),
+    };
+    let timestamp = file_timestamp(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs());
+    let mut output =
+        format!("{}/{}_{}.{}", DEFAULT_RESULTS_DIR, timestamp, name, format.extension());
+    // Runs started in the same second are numbered rather than writing over each other.
+    let mut copy = 1;
+    while Path::new(&output).exists() {
+        copy += 1;
+        output = format!(
+            "{}/{}_{}_{}.{}",
+            DEFAULT_RESULTS_DIR, timestamp, name, copy, format.extension()
+        );
+    }
+    Ok(output)
+}
+
+/// Format `secs` since the Unix epoch as a UTC date and time that can go in a file name.
+fn file_timestamp(secs: u64) -> String {
+    let (days, secs) = ((secs / 86_400) as i64, secs % 86_400);
+    // Howard Hinnant's `civil_from_days`, counting from 0000-03-01 so leap days come last.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = era * 400 + year_of_era + (month <= 2) as i64;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}-{:02}-{:02}",
+        year, month, day, secs / 3600, secs / 60 % 60, secs % 60
+    )
+}
+
+/// A description of a run for `dry_run`.
+fn describe_run(repetitions: usize, seed: Option<u64>, output: &str) -> String {
+    let seed = seed.map_or("a random seed".to_string(), |seed| format!("seed {}", seed));
+    format!("{} runs each with {}, writing to {}", repetitions, seed, output)
+}
+
+/// Simulate every row of `args.input`, writing the results to the output from the arguments or
+/// the config.
+fn run(args: RunArgs, options: &Options, simulate: Simulate, mode: Mode)
+    -> Result<(), Box<dyn Error>>
+{
+    if !args.watch {
+        return run_once(&args, options, simulate, mode, &mut None);
+    }
+    if args.input == STDIO {
+        return Err(From::from("can't watch stdin for changes"));
+    }
+    if options.append {
+        return Err(From::from("--watch can't be combined with --append"));
+    }
+
+    let mut output = None;
+    loop {
+        let modified = modified_time(&args.input);
+        // Mistakes in the input are reported, and fixing them runs it again.
+        if let Err(e) = run_once(&args, options, simulate, mode, &mut output) {
+            error!("{}", e);
+        }
+        eprintln!("watching {} for changes", args.input);
+        while modified_time(&args.input) == modified {
+            thread::sleep(WATCH_INTERVAL);
         }
+        // Give whatever is writing the input time to finish.
+        thread::sleep(WATCH_INTERVAL);
+    }
+}
 
-        num_iterations += 1;
+/// When `filename` was last modified, if it can be read.
+fn modified_time(filename: &str) -> Option<SystemTime> {
+    fs::metadata(filename).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Run `args` once. The results file is stored in `output` once chosen, and is reused without
+/// checking it if it's already set, so that runs in watch mode overwrite their own results.
+fn run_once(
+    args: &RunArgs,
+    options: &Options,
+    simulate: Simulate,
+    mode: Mode,
+    output: &mut Option<String>,
+) -> Result<(), Box<dyn Error>> {
+    let config = options.load_config(&args.input)?;
+    let repetitions = args.repetitions.or(config.repetitions).unwrap_or(REPETITIONS);
+    let rows = options.select(config.runs)?;
+    let format = options.format(config.output.format);
+    let (output, generated) = match output {
+        Some(output) => (output.clone(), false),
+        None => {
+            let (chosen, generated) = match args.output.clone().or(config.output.path) {
+                Some(chosen) => (chosen, false),
+                None => (default_output(&args.input, format)?, true),
+            };
+            options.check_output(&chosen)?;
+            (output.insert(chosen).clone(), generated)
+        }
+    };
+    if options.dry_run {
+        let plan = describe_run(repetitions, options.seed.or(config.seed), &output);
+        return dry_run(&rows, mode, &plan, format);
     }
+    let seed = options.seed(config.seed);
+    let (results, failed_rows) = options.simulate_rows(&rows, repetitions, seed, simulate);
+    options.finish_rows(&output, generated, results, &failed_rows, format)
+}
 
-    // Compute stats.
-    let total_votes_collected: usize = nodes.iter().map(|node| node.votes[&0].voters.len()).sum();
-    let average_votes_held = total_votes_collected as f64 / n as f64;
+fn replay(input_file: &str, row: usize, repetitions: Option<usize>, options: &Options)
+    -> Result<Vec<RoundRow>, Box<dyn Error>>
+{
+    let config = options.load_config(input_file)?;
+    let repetitions = repetitions.or(config.repetitions).unwrap_or(REPETITIONS);
+    let params = row
+        .checked_sub(1)
+        .and_then(|i| config.runs.get(i))
+        .ok_or_else(|| format!("{} has no row {}", input_file, row))?;
+    let seed = match params.seed {
+        Some(seed) => seed,
+        None => {
+            let seed = options.seed.or(config.seed).ok_or(
+                "replay needs the --seed of the run to replay, unless the row has a seed",
+            )?;
+            row_seed(seed, row)
+        }
+    };
+    validate_params(params)?;
 
-    SimulationResult {
-        n,
-        k,
-        voting_steps: params.voting_steps,
-        num_iterations: num_iterations as f64,
-        num_exchanges,
-        num_vote_exchanges,
-        average_votes_held,
+    let mut rounds = vec![];
+    let mut log = log_round(row);
+    info!(
+        row,
+        n = params.n,
+        k = params.k,
+        voting_steps = params.voting_steps,
+        repetitions,
+        "replaying"
+    );
+    let result = simulate_params(params, repetitions, &mut seeded_rng(seed), &mut |metrics| {
+        log(metrics);
+        rounds.push(RoundRow::from(metrics))
+    })?;
+    info!(row, rounds = result.num_iterations, exchanges = result.num_exchanges, "finished");
+    Ok(rounds)
+}
+
+/// The numeric parameters, which `sensitivity` perturbs and `search` searches over.
+const NUMERIC_PARAMS: &[&str] = config::NUMERIC_FIELDS;
+
+/// The numeric parameter `name` of `params`, if they set it.
+fn numeric_param<'a>(params: &'a mut Params, name: &str) -> Option<&'a mut usize> {
+    match name {
+        "n" => Some(&mut params.n),
+        "k" => Some(&mut params.k),
+        "voting_steps" => Some(&mut params.voting_steps),
+        "max_messages_per_round" => params.max_messages_per_round.as_mut(),
+        "max_rounds" => params.max_rounds.as_mut(),
+        _ => None,
     }
 }
 
-fn read_params(filename: &str) -> io::Result<Vec<Params>> {
-    let mut all_params = vec![];
-    let f = File::open(filename)?;
-    let mut csv_reader = csv::Reader::from_reader(f);
+fn sensitivity(
+    input_file: &str,
+    row: usize,
+    changes: &[f64],
+    repetitions: Option<usize>,
+    options: &Options,
+) -> Result<Vec<Perturbation>, Box<dyn Error>> {
+    let config = options.load_config(input_file)?;
+    let base = row
+        .checked_sub(1)
+        .and_then(|i| config.runs.get(i))
+        .ok_or_else(|| format!("{} has no row {}", input_file, row))?;
+    validate_params(base)?;
+    let repetitions = repetitions.or(config.repetitions).unwrap_or(REPETITIONS);
+    let seed = match base.seed {
+        Some(seed) => seed,
+        None => row_seed(options.seed(config.seed), row),
+    };
 
-    for row in csv_reader.deserialize() {
-        let params: Params = row?;
-        all_params.push(params);
+    // Rounds and bytes for `params`, running them with the same generator every time.
+    let measure = |params: &Params| -> Result<(f64, usize), Box<dyn Error>> {
+        validate_params(params)?;
+        info!(n = params.n, k = params.k, voting_steps = params.voting_steps, "simulating");
+        let result = simulate_params(params, repetitions, &mut seeded_rng(seed), &mut |_| ())?;
+        let bytes = daemon::encoded_size(result.num_exchanges, result.num_vote_exchanges);
+        Ok((result.num_iterations, bytes))
+    };
+    let (base_iterations, base_bytes) = measure(base)?;
+    let relative = |x: f64, base: f64| (base != 0.0).then(|| (x - base) / base);
+
+    let mut by_parameter = vec![];
+    for &parameter in NUMERIC_PARAMS {
+        let mut perturbations = vec![];
+        let mut steps: Vec<f64> = changes.iter().flat_map(|&change| [-change, change]).collect();
+        steps.sort_by(f64::total_cmp);
+        if numeric_param(&mut base.clone(), parameter).is_none() {
+            info!("skipping {}, which the row doesn't set", parameter);
+            continue;
+        }
+        for change in steps {
+            let mut params = base.clone();
+            let field = numeric_param(&mut params, parameter).expect("the row sets it");
+            let value = (*field as f64 * (1.0 + change / 100.0)).round().max(1.0) as usize;
+            if value == *field {
+                info!("skipping {} {:+}%, which rounds to the same {}", parameter, change, value);
+                continue;
+            }
+            *field = value;
+            let perturbation = match measure(&params) {
+                Ok((num_iterations, bytes)) => Perturbation {
+                    parameter,
+                    change,
+                    value: Some(value),
+                    num_iterations,
+                    bytes,
+                    iterations_change: relative(num_iterations, base_iterations),
+                    bytes_change: relative(bytes as f64, base_bytes as f64),
+                    error: None,
+                },
+                Err(e) => {
+                    warn!("{} {:+}% failed: {}", parameter, change, e);
+                    Perturbation {
+                        parameter,
+                        change,
+                        value: Some(value),
+                        num_iterations: 0.0,
+                        bytes: 0,
+                        iterations_change: None,
+                        bytes_change: None,
+                        error: Some(e.to_string()),
+                    }
+                }
+            };
+            perturbations.push(perturbation);
+        }
+        by_parameter.push(perturbations);
     }
 
-    Ok(all_params)
+    // Largest swing in rounds first, as in a tornado chart.
+    let swing = |perturbations: &Vec<Perturbation>| {
+        perturbations.iter().filter_map(|p| p.iterations_change).map(f64::abs).fold(0.0, f64::max)
+    };
+    by_parameter.sort_by(|a, b| swing(b).total_cmp(&swing(a)));
+
+    let unperturbed = Perturbation {
+        parameter: "none",
+        change: 0.0,
+        value: None,
+        num_iterations: base_iterations,
+        bytes: base_bytes,
+        iterations_change: Some(0.0),
+        bytes_change: Some(0.0),
+        error: None,
+    };
+    Ok(std::iter::once(unperturbed).chain(by_parameter.into_iter().flatten()).collect())
 }
 
-fn write_results(output_file: &str, results: Vec<SimulationResult>) -> io::Result<()> {
-    let mut writer = csv::Writer::from_path(output_file)?;
+fn search(args: &SearchArgs, options: &Options)
+    -> Result<(Vec<SearchTrial>, Option<usize>), Box<dyn Error>>
+{
+    if !(args.probability > 0.0 && args.probability <= 1.0) {
+        return Err(From::from(format!(
+            "--probability {} must be above 0 and at most 1",
+            args.probability
+        )));
+    }
+    let param = args.param.as_str();
+    if !NUMERIC_PARAMS.contains(&param) {
+        return Err(From::from(format!(
+            "can't search over {:?}, only {}",
+            param,
+            NUMERIC_PARAMS.join(", ")
+        )));
+    }
+    let config = options.load_config(&args.input)?;
+    let base = args
+        .row
+        .checked_sub(1)
+        .and_then(|i| config.runs.get(i))
+        .ok_or_else(|| format!("{} has no row {}", args.input, args.row))?;
+    let repetitions = args.repetitions.or(config.repetitions).unwrap_or(REPETITIONS);
+    let seed = match base.seed {
+        Some(seed) => seed,
+        None => row_seed(options.seed(config.seed), args.row),
+    };
 
-    for result in results {
-        writer.serialize(result)?;
+    // The row with each of the values, as a config file's run with a range would expand to,
+    // leaving out values that can't run.
+    let mut fields = match serde_json::to_value(base)? {
+        serde_json::Value::Object(fields) => fields,
+        _ => unreachable!("parameters serialize to an object"),
+    };
+    fields.insert(param.into(), args.values.clone().into());
+    let candidates = config::expand_run(&fields)?;
+    if candidates.is_empty() {
+        return Err(From::from(format!("none of {} = {} can run", param, args.values)));
     }
+    let value_of = |params: &Params| {
+        numeric_param(&mut params.clone(), param).copied().expect("every candidate sets it")
+    };
 
-    Ok(())
+    let mut trials: Vec<SearchTrial> = vec![];
+    let mut trial = |params: &Params| -> Result<bool, Box<dyn Error>> {
+        validate_params(params)?;
+        let value = value_of(params);
+        let runs = simulate_runs(params, repetitions, &mut seeded_rng(seed), &mut |_| ())?;
+        // Runs that hit a `max_rounds` deadline stop there without converging.
+        let converged = runs
+            .iter()
+            .filter(|run| run.missed_deadline == 0.0 && run.num_iterations <= args.within as f64);
+        let converged = converged.count() as f64 / runs.len().max(1) as f64;
+        let meets_target = converged >= args.probability;
+        info!(param, value, converged, meets_target, "tried");
+        trials.push(SearchTrial {
+            value,
+            runs: runs.len(),
+            converged,
+            num_iterations: average_results(params, runs).num_iterations,
+            meets_target,
+        });
+        Ok(meets_target)
+    };
+
+    let smallest = match args.method {
+        SearchMethod::Grid => {
+            let mut smallest = None;
+            for params in &candidates {
+                if trial(params)? && smallest.is_none() {
+                    smallest = Some(value_of(params));
+                }
+            }
+            smallest
+        }
+        SearchMethod::Binary => {
+            // The first candidate meeting the target is in `low..=high`, or there isn't one if
+            // `low` passes the end.
+            let (mut low, mut high) = (0, candidates.len());
+            while low < high {
+                let middle = low + (high - low) / 2;
+                match trial(&candidates[middle])? {
+                    true => high = middle,
+                    false => low = middle + 1,
+                }
+            }
+            candidates.get(low).map(value_of)
+        }
+    };
+    trials.sort_by_key(|trial| trial.value);
+    Ok((trials, smallest))
 }
 
-fn main_with_result() -> Result<(), Box<Error>> {
-    let args: Vec<_> = env::args().collect();
+fn loss_curve(args: &LossCurveArgs, options: &Options) -> Result<Vec<LossPoint>, Box<dyn Error>> {
+    if !(0.0..1.0).contains(&args.max_loss) {
+        return Err(From::from(format!(
+            "--max-loss {} must be at least 0 and less than 1",
+            args.max_loss
+        )));
+    }
+    let config = options.load_config(&args.input)?;
+    let base = args
+        .row
+        .checked_sub(1)
+        .and_then(|i| config.runs.get(i))
+        .ok_or_else(|| format!("{} has no row {}", args.input, args.row))?;
+    validate_params(base)?;
+    let repetitions = args.repetitions.or(config.repetitions).unwrap_or(REPETITIONS);
+    let seed = match base.seed {
+        Some(seed) => seed,
+        None => row_seed(options.seed(config.seed), args.row),
+    };
 
-    if args.len() != 3 {
-        println!("Usage: ./gossip <input csv> <output csv>");
-        return Err(From::from(format!("incorrect CLI args: {:?}", args)));
+    let mut points = vec![];
+    for step in 0..=args.steps {
+        let loss = args.max_loss * step as f64 / args.steps.max(1) as f64;
+        let params = Params { loss, ..base.clone() };
+        info!(loss, "simulating");
+        let runs = simulate_runs(&params, repetitions, &mut seeded_rng(seed), &mut |_| ())?;
+        let rounds: Vec<f64> = runs.iter().map(|run| run.num_iterations).collect();
+        // Runs that hit a `max_rounds` deadline stop there without converging, as in `search`.
+        let converged = runs
+            .iter()
+            .filter(|run| run.missed_deadline == 0.0 && run.num_iterations <= args.within as f64)
+            .count();
+        let (converged_low, converged_high) =
+            stats::wilson_interval(converged, runs.len(), stats::Z_95);
+        let (num_iterations, iterations_low, iterations_high) =
+            stats::mean_interval(&rounds, stats::Z_95);
+        points.push(LossPoint {
+            loss,
+            runs: runs.len(),
+            converged: converged as f64 / runs.len().max(1) as f64,
+            converged_low,
+            converged_high,
+            num_iterations,
+            iterations_low,
+            iterations_high,
+        });
     }
+    Ok(points)
+}
 
-    let input_file = &args[1];
-    let output_file = &args[2];
-    let repetitions = 20;
+fn import(args: &ImportArgs, options: &Options) -> Result<Vec<TraceFit>, Box<dyn Error>> {
+    if !(0.0..1.0).contains(&args.max_loss) {
+        return Err(From::from(format!(
+            "--max-loss {} must be at least 0 and less than 1",
+            args.max_loss
+        )));
+    }
+    let trace = Trace::read(&args.trace, args.nodes)?;
+    let base = trace_params(&trace);
+    validate_params(&base)?;
+    let n = trace.num_nodes();
+    let repetitions = args.repetitions.unwrap_or(REPETITIONS);
+    let seed = options.seed(None);
+    let [trace_50, trace_90, _, trace_100] = trace.milestones();
 
-    let all_params = read_params(input_file)?;
-    let mut rng = weak_rng();
+    let mut fits: Vec<(TraceFit, Params)> = vec![];
+    for step in 0..=args.steps {
+        let loss = args.max_loss * step as f64 / args.steps.max(1) as f64;
+        let params = Params { loss, ..base.clone() };
+        info!(loss, "simulating");
+        let mut runs: Vec<Vec<usize>> = vec![vec![]; repetitions];
+        let result = simulate_params(&params, repetitions, &mut seeded_rng(seed), &mut |metrics| {
+            runs[metrics.repetition].push(metrics.nodes_with_quorum)
+        })?;
+        let curves: Vec<Vec<f64>> = runs.iter().map(|run| trace::spread_curve(run, n)).collect();
+        let fit = trace.fit(&curves);
+        let in_rounds = |time: Option<f64>| time.map(|time| time / fit.round_duration);
+        let fit = TraceFit {
+            loss,
+            runs: repetitions,
+            round_duration: fit.round_duration,
+            fit_error: fit.error,
+            rounds_50: result.quorum_rounds_50,
+            trace_rounds_50: in_rounds(trace_50),
+            rounds_90: result.quorum_rounds_90,
+            trace_rounds_90: in_rounds(trace_90),
+            num_iterations: result.num_iterations,
+            trace_rounds: in_rounds(trace_100),
+            vote_messages_per_node: result.num_vote_exchanges as f64 / n as f64,
+        };
+        fits.push((fit, params));
+    }
 
-    let results: Vec<_> = all_params.iter()
-        .map(|params| {
-            let sim_results = (0..repetitions).map(|_| run_simulation(params, &mut rng)).collect();
-            average_results(params, sim_results)
-        })
-        .collect();
+    let (best, params) = fits
+        .iter()
+        .min_by(|a, b| a.0.fit_error.total_cmp(&b.0.fit_error))
+        .expect("at least one loss rate is tried");
+    eprintln!(
+        "best fit: loss {}, with rounds of {:.4} (fit error {:.4}); the trace has {:.2} deliveries \
+         per node",
+        best.loss,
+        best.round_duration,
+        best.fit_error,
+        trace.deliveries_per_node()
+    );
+    if let Some(calibrated) = &args.calibrated {
+        options.check_output(calibrated)?;
+        write_results(calibrated, vec![params.clone()], OutputFormat::Csv)?;
+    }
+    Ok(fits.into_iter().map(|(fit, _)| fit).collect())
+}
+
+fn interactive(input_file: &str, row: usize, options: &Options) -> Result<(), Box<dyn Error>> {
+    let config = options.load_config(input_file)?;
+    let params = row
+        .checked_sub(1)
+        .and_then(|i| config.runs.get(i))
+        .ok_or_else(|| format!("{} has no row {}", input_file, row))?;
+    validate_params(params)?;
+    // The same generator as the row's runs get with this seed, so that stepping through a row
+    // run in general mode repeats its first run.
+    let seed = match params.seed {
+        Some(seed) => seed,
+        None => row_seed(options.seed(config.seed), row),
+    };
+    println!(
+        "n = {}, k = {}, voting_steps = {}, seed = {}; type help for the commands",
+        params.n, params.k, params.voting_steps, seed
+    );
+    let stdin = io::stdin();
+    let prompt = stdin.is_terminal();
+    gossip::interactive::run(params, &mut seeded_rng(seed), stdin.lock(), io::stdout(), prompt)
+}
+
+fn frames(
+    input_file: &str,
+    output_dir: &str,
+    row: usize,
+    options: &Options,
+    format: OutputFormat,
+) -> Result<usize, Box<dyn Error>> {
+    let config = options.load_config(input_file)?;
+    let params = row
+        .checked_sub(1)
+        .and_then(|i| config.runs.get(i))
+        .ok_or_else(|| format!("{} has no row {}", input_file, row))?;
+    validate_params(params)?;
+    // Seeded as `interactive` is, so that the frames show the row's first run in general mode.
+    let seed = match params.seed {
+        Some(seed) => seed,
+        None => row_seed(options.seed(config.seed), row),
+    };
+    gossip::frames::write_frames(params, &mut seeded_rng(seed), output_dir, format)
+}
+
+#[cfg(feature = "grpc")]
+fn serve(addr: &str) -> Result<(), Box<dyn Error>> {
+    gossip::grpc::serve(addr.parse()?)
+}
+
+#[cfg(not(feature = "grpc"))]
+fn serve(_addr: &str) -> Result<(), Box<dyn Error>> {
+    Err(From::from("cannot serve: built without the `grpc` feature"))
+}
+
+#[cfg(feature = "http")]
+fn serve_http(addr: &str) -> Result<(), Box<dyn Error>> {
+    gossip::http::serve(addr)
+}
+
+#[cfg(not(feature = "http"))]
+fn serve_http(_addr: &str) -> Result<(), Box<dyn Error>> {
+    Err(From::from("cannot serve HTTP: built without the `http` feature"))
+}
+
+/// Run the runs of `params` over a libp2p gossipsub swarm instead of simulating, as a `Simulate`
+/// for `simulate_rows`. The swarm chooses its own peers, so `rng` goes unused.
+#[cfg(feature = "gossipsub")]
+fn run_gossipsub(
+    params: &Params,
+    repetitions: usize,
+    _rng: &mut XorShiftRng,
+    _on_round: &mut dyn FnMut(&RoundMetrics),
+) -> Result<Vec<SimulationResult>, Box<dyn Error>> {
+    p2p::validate(params)?;
+    (0..repetitions).map(|_| p2p::run(params)).collect()
+}
+
+#[cfg(not(feature = "gossipsub"))]
+fn run_gossipsub(
+    _params: &Params,
+    _repetitions: usize,
+    _rng: &mut XorShiftRng,
+    _on_round: &mut dyn FnMut(&RoundMetrics),
+) -> Result<Vec<SimulationResult>, Box<dyn Error>> {
+    Err(From::from("cannot run libp2p comparison: built without the `gossipsub` feature"))
+}
+
+/// The subcommand to run, with a bare input file short for `run`.
+///
+/// Clap prints the help when there are no arguments at all, but global flags on their own, as in
+/// `gossip --seed 1`, get here without a subcommand or an input.
+fn subcommand(
+    command: Option<Command>,
+    input: Option<String>,
+    output: Option<String>,
+) -> Result<Command, clap::Error> {
+    match (command, input) {
+        (Some(command), _) => Ok(command),
+        (None, Some(input)) => {
+            Ok(Command::Run(RunArgs { input, output, repetitions: None, watch: false }))
+        }
+        (None, None) => Err(Cli::command().error(
+            clap::error::ErrorKind::MissingSubcommand,
+            "a subcommand or a parameters file is required",
+        )),
+    }
+}
 
-    write_results(output_file, results)?;
+fn main_with_result() -> Result<(), Box<dyn Error>> {
+    let mut cli = Cli::parse();
+    init_logging(cli.options.verbose, cli.options.log.as_deref());
+    if cli.options.progress.is_some() {
+        let path = cli.options.progress_to.as_deref().unwrap_or("stderr");
+        let progress = Progress::open(cli.options.progress_to.as_deref())
+            .map_err(|e| format!("failed to open {} for progress: {}", path, e))?;
+        cli.options.progress_sink = Some(progress);
+    }
+    let options = &cli.options;
+    let format = options.format(None);
+    if let Some(threads) = options.threads {
+        set_round_threads(threads);
+    }
+
+    let command = subcommand(cli.command, cli.input, cli.output).unwrap_or_else(|e| e.exit());
+
+    match command {
+        Command::Run(args) => run(args, options, simulate_runs, simulation_mode)?,
+        Command::Sweep(args) => {
+            let rows = options.select(options.apply_preset(args.params()?))?;
+            let (output, generated) = match args.output {
+                Some(output) => (output, false),
+                None => (default_output("sweep", format)?, true),
+            };
+            options.check_output(&output)?;
+            if options.dry_run {
+                let plan = describe_run(args.repetitions, options.seed, &output);
+                return dry_run(&rows, simulation_mode, &plan, format);
+            }
+            let seed = options.seed(None);
+            let (results, failed_rows) =
+                options.simulate_rows(&rows, args.repetitions, seed, simulate_runs);
+            options.finish_rows(&output, generated, results, &failed_rows, format)?;
+        }
+        Command::Compare { a, b } => write_results_to(io::stdout(), compare(&a, &b)?, format)?,
+        Command::Replay { input, output, row, repetitions } => {
+            options.check_output(&output)?;
+            options.write_results(&output, replay(&input, row, repetitions, options)?, format)?;
+        }
+        Command::Interactive { input, row } => interactive(&input, row, options)?,
+        Command::Frames { input, output, row } => {
+            options.check_output_dir(&output)?;
+            let num_frames = frames(&input, &output, row, options, format)?;
+            eprintln!("wrote {} frames to {}", num_frames, output);
+        }
+        Command::Sensitivity { input, row, changes, repetitions } => {
+            let table = sensitivity(&input, row, &changes, repetitions, options)?;
+            write_results_to(io::stdout(), table, format)?
+        }
+        Command::Search(args) => {
+            let (trials, smallest) = search(&args, options)?;
+            write_results_to(io::stdout(), trials, format)?;
+            match smallest {
+                Some(value) => eprintln!("smallest {} meeting the target: {}", args.param, value),
+                None => {
+                    return Err(From::from(format!(
+                        "no {} in {} has {}% of its runs converge within {} rounds",
+                        args.param,
+                        args.values,
+                        args.probability * 100.0,
+                        args.within
+                    )))
+                }
+            }
+        }
+        Command::LossCurve(args) => {
+            write_results_to(io::stdout(), loss_curve(&args, options)?, format)?
+        }
+        Command::Import(args) => write_results_to(io::stdout(), import(&args, options)?, format)?,
+        Command::Summarize { input } => write_results_to(io::stdout(), summarize(&input)?, format)?,
+        Command::Stats { command: StatsCommand::Compare { a, b } } => {
+            write_results_to(io::stdout(), stats_compare(&a, &b)?, format)?
+        }
+        Command::Stats { command: StatsCommand::Fit { input, percentile } } => {
+            write_results_to(io::stdout(), stats_fit(&input, percentile)?, format)?
+        }
+        Command::Compact(args) => run(args, options, compact::simulate_runs, compact_mode)?,
+        Command::Check { input, output, max_rounds } => {
+            let rows = options.select(options.load_config(&input)?.runs)?;
+            options.check_output(&output)?;
+            if options.dry_run {
+                let plan = format!("checking {} rounds, writing to {}", max_rounds, output);
+                return dry_run(&rows, check_mode, &plan, format);
+            }
+            let (results, failed_rows) = options.check_rows(&rows, max_rounds);
+            options.finish_rows(&output, false, results, &failed_rows, format)?;
+        }
+        Command::Serve { address } => serve(&address)?,
+        Command::ServeHttp { address } => serve_http(&address)?,
+        Command::Worker { address } => distributed::serve_worker(&address)?,
+        Command::Coordinate { input, output, workers, repetitions } => {
+            if options.per_run {
+                return Err(From::from("coordinate can't write a row per run with --per-run"));
+            }
+            let config = options.load_config(&input)?;
+            let repetitions = repetitions.or(config.repetitions).unwrap_or(REPETITIONS);
+            let rows = options.select(config.runs)?;
+            options.check_output(&output)?;
+            if options.dry_run {
+                let plan = format!(
+                    "{}, spread over the workers in {}",
+                    describe_run(repetitions, options.seed.or(config.seed), &output),
+                    workers
+                );
+                return dry_run(&rows, simulation_mode, &plan, format);
+            }
+            let seed = options.seed(config.seed);
+            let row_numbers: Vec<_> = rows.iter().map(|&(row, _)| row).collect();
+            let results = distributed::coordinate(rows, &workers, repetitions, seed)?;
+            let failed_rows: Vec<_> = row_numbers
+                .into_iter()
+                .zip(&results)
+                .filter(|(_, result)| result.error.is_some())
+                .map(|(row, _)| row)
+                .collect();
+            options.finish_rows(&output, false, results, &failed_rows, format)?;
+        }
+        Command::Daemon { peers, id, vote } => daemon::run(&peers, id, vote)?,
+        Command::Gossipsub { input, output, repetitions } => {
+            let config = options.load_config(&input)?;
+            let repetitions = repetitions.or(config.repetitions).unwrap_or(REPETITIONS);
+            let rows = options.select(config.runs)?;
+            options.check_output(&output)?;
+            // The swarm isn't seeded, so no seed would repeat its runs.
+            let (mut results, failed_rows) =
+                options.simulate_rows(&rows, repetitions, 0, run_gossipsub);
+            results.iter_mut().for_each(|result| result.seed = None);
+            options.finish_rows(&output, false, results, &failed_rows, format)?;
+        }
+        Command::Completions { shell } => {
+            clap_complete::generate(shell, &mut Cli::command(), "gossip", &mut io::stdout())
+        }
+    }
 
     Ok(())
 }
 
 fn main() {
-    main_with_result().unwrap()
+    if let Err(e) = main_with_result() {
+        // Printed directly, so that even `--log off` doesn't hide why the run failed.
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(spec: &str) -> Result<(usize, usize), String> {
+        spec.parse().map(|range: RowRange| (range.first, range.last))
+    }
+
+    #[test]
+    fn parses_row_ranges() {
+        assert_eq!(rows("3"), Ok((3, 3)));
+        assert_eq!(rows("10..20"), Ok((10, 20)));
+        assert_eq!(rows(" 10 .. 20 "), Ok((10, 20)));
+        assert_eq!(rows("10.."), Ok((10, usize::MAX)));
+        assert_eq!(rows("..20"), Ok((1, 20)));
+        assert_eq!(rows(".."), Ok((1, usize::MAX)));
+    }
+
+    #[test]
+    fn row_ranges_include_both_ends() {
+        let range: RowRange = "10..20".parse().unwrap();
+        assert!(!range.contains(9));
+        assert!(range.contains(10));
+        assert!(range.contains(20));
+        assert!(!range.contains(21));
+        // A backwards range is empty, rather than an error.
+        let range: RowRange = "5..3".parse().unwrap();
+        assert!((1..10).all(|row| !range.contains(row)));
+    }
+
+    #[test]
+    fn rejects_bad_row_ranges() {
+        for (spec, error) in [
+            ("0", "rows are counted from 1"),
+            ("0..5", "rows are counted from 1"),
+            ("", "rows are counted from 1"),
+            ("x", "invalid row \"x\""),
+            ("1..x", "invalid row \"x\""),
+            ("-1", "invalid row \"-1\""),
+            ("1...3", "invalid row \".3\""),
+        ] {
+            let e = rows(spec).unwrap_err();
+            assert!(e.contains(error), "{:?} gave {:?}, expected {:?}", spec, e, error);
+        }
+    }
+
+    #[test]
+    fn takes_global_flags_before_the_subcommand_or_the_shorthand() {
+        for args in [
+            &["gossip", "--seed", "1", "run", "p.csv", "out.csv"][..],
+            &["gossip", "-v", "run", "p.csv"],
+            &["gossip", "run", "--seed", "1", "p.csv"],
+        ] {
+            let cli = Cli::try_parse_from(args).unwrap();
+            assert!(matches!(cli.command, Some(Command::Run(_))), "{:?}", args);
+        }
+        let cli = Cli::try_parse_from(["gossip", "--seed", "1", "p.csv", "out.csv"]).unwrap();
+        assert!(cli.command.is_none());
+        assert_eq!(cli.input.as_deref(), Some("p.csv"));
+        assert_eq!(cli.options.seed, Some(1));
+    }
+
+    /// `csv` written to a file of the test's own, named after `name`.
+    fn input(name: &str, csv: &str) -> String {
+        let path = std::env::temp_dir().join(format!("gossip-{}-{}.csv", name, process::id()));
+        fs::write(&path, csv).unwrap();
+        path.to_str().unwrap().into()
+    }
+
+    /// The command line `args`, after `gossip`.
+    fn parse(args: &[&str]) -> Cli {
+        Cli::try_parse_from(["gossip"].iter().chain(args)).unwrap()
+    }
+
+    #[test]
+    fn loss_curve_counts_missed_deadlines_as_not_converging() {
+        let path = input("loss-curve", "n,k,voting_steps,max_rounds\n50,40,1,3\n");
+        let cli = parse(&["loss-curve", &path, "--steps", "2", "--within", "10"]);
+        let Some(Command::LossCurve(args)) = &cli.command else { panic!("not loss-curve") };
+        let points = loss_curve(args, &cli.options).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(points.len(), 3);
+        for point in points {
+            // Every run stops at the deadline, within the 10 rounds, without converging.
+            assert!(point.num_iterations <= 3.0);
+            assert_eq!(point.converged, 0.0, "at loss {}", point.loss);
+        }
+    }
+
+    #[test]
+    fn binary_and_grid_search_agree_on_a_monotone_range() {
+        // With a fixed quorum, more voters only get every node there sooner.
+        let path = input("search", "n,k,voting_steps,quorum\n500,10,1,count:10\n");
+        let search_by = |method| {
+            let args = ["search", &path, "--param", "k", "--values", "10..100 step 10"];
+            let options = ["--within", "6", "--seed", "1", "--method", method];
+            let cli = parse(&[&args[..], &options].concat());
+            let Some(Command::Search(args)) = &cli.command else { panic!("not search") };
+            search(args, &cli.options).unwrap()
+        };
+        let (grid, smallest) = search_by("grid");
+        let (binary, binary_smallest) = search_by("binary");
+        fs::remove_file(&path).unwrap();
+        assert_eq!(grid.len(), 10);
+        assert!(grid.windows(2).all(|pair| pair[0].meets_target <= pair[1].meets_target));
+        assert!(smallest.is_some_and(|k| k > 10 && k < 100), "{:?}", smallest);
+        assert_eq!(binary_smallest, smallest);
+        assert!(binary.len() < grid.len());
+        for trial in binary {
+            let same = grid.iter().find(|grid| grid.value == trial.value).unwrap();
+            assert_eq!((trial.converged, trial.meets_target), (same.converged, same.meets_target));
+        }
+    }
+
+    #[test]
+    fn search_never_meets_a_target_past_the_deadline() {
+        let path = input("deadline", "n,k,voting_steps,max_rounds\n100,10,1,4\n");
+        for method in ["grid", "binary"] {
+            let args = ["search", &path, "--param", "k", "--values", "10..100 step 30"];
+            let cli = parse(&[&args[..], &["--within", "20", "--method", method]].concat());
+            let Some(Command::Search(args)) = &cli.command else { panic!("not search") };
+            let (trials, smallest) = search(args, &cli.options).unwrap();
+            assert_eq!(smallest, None, "{}", method);
+            assert!(trials.iter().all(|trial| trial.converged == 0.0 && !trial.meets_target));
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn sensitivity_sorts_the_parameters_like_a_tornado_chart() {
+        let path = input("sensitivity", "n,k,voting_steps,quorum\n100,4,2,count:4\n");
+        let cli = parse(&["--seed", "1", &path]);
+        let table = sensitivity(&path, 1, &[10.0, 25.0], Some(5), &cli.options).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(table[0].parameter, "none");
+        assert_eq!(table[0].iterations_change, Some(0.0));
+        let changes: Vec<_> = table.iter().map(|p| (p.parameter, p.change, p.value)).collect();
+        // k and voting_steps ±10% round back to 4 and 2, as does voting_steps -25%.
+        for (parameter, change, value) in [
+            ("n", -25.0, 75),
+            ("n", -10.0, 90),
+            ("n", 10.0, 110),
+            ("n", 25.0, 125),
+            ("k", -25.0, 3),
+            ("k", 25.0, 5),
+            ("voting_steps", 25.0, 3),
+        ] {
+            let expected = (parameter, change, Some(value));
+            assert!(changes.contains(&expected), "{:?} missing from {:?}", expected, changes);
+        }
+        assert_eq!(table.len(), 8);
+
+        // 3 voters can never form a quorum of 4, which fails that row but not the table.
+        let failed: Vec<_> = table.iter().filter(|p| p.error.is_some()).collect();
+        assert_eq!(failed.len(), 1);
+        assert_eq!((failed[0].parameter, failed[0].value), ("k", Some(3)));
+        assert!(failed[0].error.as_ref().unwrap().contains("can never form a quorum"));
+        assert_eq!(failed[0].iterations_change, None);
+
+        // Each parameter's rows are together, the one with the largest swing in rounds first.
+        let mut swings: Vec<(&str, f64)> = vec![];
+        for perturbation in &table[1..] {
+            let swing = perturbation.iterations_change.map_or(0.0, f64::abs);
+            match swings.last_mut() {
+                Some((parameter, largest)) if *parameter == perturbation.parameter => {
+                    *largest = largest.max(swing)
+                }
+                _ => swings.push((perturbation.parameter, swing)),
+            }
+        }
+        assert_eq!(swings.len(), 3, "{:?}", swings);
+        assert!(swings.windows(2).all(|pair| pair[0].1 >= pair[1].1), "{:?}", swings);
+    }
+
+    #[test]
+    fn global_flags_alone_ask_for_a_subcommand() {
+        for args in [&["gossip", "--seed", "1"][..], &["gossip", "-v"], &["gossip", "--dry-run"]] {
+            let cli = Cli::try_parse_from(args).unwrap();
+            let e = subcommand(cli.command, cli.input, cli.output).err().unwrap();
+            assert_eq!(e.kind(), clap::error::ErrorKind::MissingSubcommand, "{:?}", args);
+        }
+        let cli = Cli::try_parse_from(["gossip", "-v", "p.csv"]).unwrap();
+        let command = subcommand(cli.command, cli.input, cli.output).unwrap();
+        assert!(matches!(command, Command::Run(RunArgs { ref input, .. }) if input == "p.csv"));
+    }
+
+    #[test]
+    fn parses_lists_of_row_ranges_from_the_command_line() {
+        let cli = Cli::try_parse_from(["gossip", "--rows", "3,10..20", "run", "p.csv"]).unwrap();
+        let ranges: Vec<_> = cli.options.rows.iter().map(|r| (r.first, r.last)).collect();
+        assert_eq!(ranges, vec![(3, 3), (10, 20)]);
+        assert!(Cli::try_parse_from(["gossip", "--rows", "3,0", "run", "p.csv"]).is_err());
+    }
 }
@@ -0,0 +1,616 @@
+//! A SWIM-style membership layer, in which some nodes crash and the rest find out by probing
+//! each other and gossiping what they've found alongside the votes.
+//!
+//! A row's `membership` column is `<crash fraction>[:<crash round>[:<indirect probes>[:<suspicion
+//! rounds>]]]`, such as `0.1:5:3:4`. That fraction of the nodes that don't vote, rounded to whole
+//! nodes and taken in an order shuffled by node ID, crash at the start of the crash round (0 by
+//! default) and never send or receive anything again. Only nodes that don't vote crash, so the
+//! votes for a quorum are always cast, and the nodes that crash without a quorum are left out of
+//! the nodes that the run waits for.
+//!
+//! Every round, each live node pings a random node that it doesn't believe dead. If the ping or
+//! its ack is lost, it asks that many other nodes (3 by default) to ping the target for it, and
+//! if none of them gets an ack back either, it suspects the target. A suspicion that isn't
+//! refuted within the suspicion rounds (3 by default) becomes a belief that the target is dead.
+//! Each node keeps its own view of the others, as alive, suspect or dead with the incarnation
+//! that the state is about. Views are merged over the direct pings and acks and every message of
+//! the round's vote exchanges that gets through, with SWIM's precedence: a later incarnation
+//! overrides an earlier one, and at the same incarnation dead overrides suspect, which overrides
+//! alive. A live node that hears it's suspected or believed dead refutes it by moving to a later
+//! incarnation, so that wrong beliefs die out rather than spreading to every node.
+//!
+//! Nodes choose another vote partner whenever they would choose one they believe dead, so the
+//! vote gossip speeds up as crashes are detected and slows down as live nodes are wrongly
+//! believed dead. A run goes on until every live node has a quorum and believes every crashed
+//! node dead, or until the deadline. Probes are lost at the same rate as votes, but aren't rate
+//! limited or charged for.
+
+use rand::Rng;
+use std::collections::BTreeMap;
+
+use crate::{choose_partner, is_lost, splitmix64, NodeId, Params, SimulationResult};
+
+/// Attempts at choosing a node that isn't believed dead before giving up for the round.
+const CHOICE_ATTEMPTS: usize = 8;
+
+/// The crashes and failure detection of a membership spec.
+#[derive(Clone, Debug)]
+pub struct MembershipConfig {
+    pub crashed: Vec<bool>,
+    pub crash_round: usize,
+    pub indirect_probes: usize,
+    pub suspicion_rounds: usize,
+}
+
+/// Parse a membership spec for `n` nodes of which `k` vote, in the format described in the
+/// module docs.
+pub fn parse_membership(spec: &str, n: usize, k: usize) -> Result<MembershipConfig, String> {
+    let fields: Vec<&str> = spec.trim().split(':').collect();
+    if fields.len() > 4 {
+        return Err(format!(
+            "membership {:?} should be <crash fraction>[:<crash round>[:<indirect probes>\
+             [:<suspicion rounds>]]]",
+            spec
+        ));
+    }
+    let fraction: f64 = fields[0]
+        .parse()
+        .map_err(|_| format!("invalid crash fraction in membership {:?}", spec))?;
+    let field = |i: usize, name: &str, default: usize| -> Result<usize, String> {
+        fields.get(i).map_or(Ok(default), |field| {
+            field.parse().map_err(|_| format!("invalid {} in membership {:?}", name, spec))
+        })
+    };
+    let crash_round = field(1, "crash round", 0)?;
+    let indirect_probes = field(2, "number of indirect probes", 3)?;
+    let suspicion_rounds = field(3, "number of suspicion rounds", 3)?;
+    if !(0.0..=1.0).contains(&fraction) {
+        return Err(format!("crash fraction {} should be in [0, 1]", fraction));
+    }
+    if indirect_probes > n.saturating_sub(2) {
+        return Err(format!(
+            "{} indirect probes need more than the {} nodes besides the prober and the target",
+            indirect_probes,
+            n.saturating_sub(2)
+        ));
+    }
+    if suspicion_rounds == 0 {
+        return Err("suspicion rounds must be at least 1".to_string());
+    }
+
+    // Shuffled by node ID, so that the same nodes crash in every run.
+    let mut non_voters: Vec<usize> = (k..n).collect();
+    non_voters.sort_by_key(|&node| splitmix64(&mut (node as u64)));
+    let num_crashed = (fraction * non_voters.len() as f64).round() as usize;
+    let mut crashed = vec![false; n];
+    for &node in &non_voters[..num_crashed] {
+        crashed[node] = true;
+    }
+    Ok(MembershipConfig { crashed, crash_round, indirect_probes, suspicion_rounds })
+}
+
+/// What a node believes about another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Status {
+    Alive,
+    /// Suspected since the given round, as far as this node is concerned.
+    Suspect(usize),
+    Dead,
+}
+
+impl Status {
+    /// Which status overrides which at the same incarnation.
+    fn rank(self) -> u8 {
+        match self {
+            Status::Alive => 0,
+            Status::Suspect(_) => 1,
+            Status::Dead => 2,
+        }
+    }
+}
+
+/// A node's view of another, at the incarnation that the status is about.
+#[derive(Clone, Copy, Debug)]
+struct Entry {
+    status: Status,
+    incarnation: u32,
+}
+
+impl Entry {
+    /// Whether a node with the view `old`, or none, should adopt `self` instead.
+    fn overrides(self, old: Option<Entry>) -> bool {
+        old.is_none_or(|old| {
+            (self.incarnation, self.status.rank()) > (old.incarnation, old.status.rank())
+        })
+    }
+}
+
+/// The membership state of a run, and what it measured.
+pub struct Membership {
+    config: MembershipConfig,
+    n: usize,
+    /// The round the crashes happened in, once they have.
+    crashed_at: Option<usize>,
+    num_crashed: usize,
+    /// Crashed nodes without a quorum, which can never get one.
+    num_stranded: usize,
+    /// Each node's view of the nodes it has heard anything about, itself included. Nodes it
+    /// hasn't are believed alive at incarnation 0.
+    views: Vec<BTreeMap<NodeId, Entry>>,
+    incarnations: Vec<u32>,
+    /// How many other live nodes believe each node dead.
+    num_believing_dead: Vec<usize>,
+    /// Whether some other live node has believed each live node dead.
+    wrongly_dead: Vec<bool>,
+    /// The round by which every live node believed each crashed node dead.
+    detected_at: Vec<Option<usize>>,
+    num_false_suspicions: usize,
+    num_messages: usize,
+}
+
+impl Membership {
+    /// The membership layer of `params`, if they have one.
+    pub fn new(params: &Params) -> Result<Option<Self>, String> {
+        let Some(spec) = &params.membership else {
+            return Ok(None);
+        };
+        let n = params.n;
+        Ok(Some(Membership {
+            config: parse_membership(spec, n, params.k)?,
+            n,
+            crashed_at: None,
+            num_crashed: 0,
+            num_stranded: 0,
+            views: vec![BTreeMap::new(); n],
+            incarnations: vec![0; n],
+            num_believing_dead: vec![0; n],
+            wrongly_dead: vec![false; n],
+            detected_at: vec![None; n],
+            num_false_suspicions: 0,
+            num_messages: 0,
+        }))
+    }
+
+    fn is_crashed(&self, node: usize) -> bool {
+        self.crashed_at.is_some() && self.config.crashed[node]
+    }
+
+    fn believes_dead(&self, node: usize, other: NodeId) -> bool {
+        self.views[node].get(&other).is_some_and(|entry| entry.status == Status::Dead)
+    }
+
+    /// The crashed nodes without a quorum, which will never get one.
+    pub fn num_stranded(&self) -> usize {
+        self.num_stranded
+    }
+
+    /// Whether the nodes have crashed and every live node believes every one of them dead.
+    pub fn is_settled(&self) -> bool {
+        let detected = |node: usize| !self.config.crashed[node] || self.detected_at[node].is_some();
+        self.crashed_at.is_some() && (0..self.n).all(detected)
+    }
+
+    /// Crash the nodes if `round` is the crash round, given which nodes have a quorum.
+    pub fn crash(&mut self, round: usize, has_quorum: impl Fn(usize) -> bool) {
+        if round != self.config.crash_round {
+            return;
+        }
+        self.crashed_at = Some(round);
+        for node in 0..self.n {
+            if !self.config.crashed[node] {
+                continue;
+            }
+            self.num_crashed += 1;
+            self.num_stranded += usize::from(!has_quorum(node));
+            // Only live nodes' beliefs count towards detection.
+            for (&other, entry) in &self.views[node] {
+                if entry.status == Status::Dead && other as usize != node {
+                    self.num_believing_dead[other as usize] -= 1;
+                }
+            }
+        }
+        self.check_detected(round);
+    }
+
+    /// Have every live node choose another partner in place of one it believes dead.
+    pub fn avoid_dead<R: Rng>(&self, partners: &mut [NodeId], rng: &mut R) {
+        for (node, partner) in partners.iter_mut().enumerate() {
+            if self.is_crashed(node) || !self.believes_dead(node, *partner) {
+                continue;
+            }
+            if let Some(other) = self.choose_live(node, rng) {
+                *partner = other;
+            }
+        }
+    }
+
+    /// A node other than `node` that it doesn't believe dead, if one turns up quickly.
+    fn choose_live<R: Rng>(&self, node: usize, rng: &mut R) -> Option<NodeId> {
+        (0..CHOICE_ATTEMPTS)
+            .map(|_| choose_partner(node as NodeId, self.n, rng))
+            .find(|&other| !self.believes_dead(node, other))
+    }
+
+    /// Mark the messages of the exchanges with crashed nodes in `unsent`, in the order that
+    /// `is_lost` looks them up.
+    pub fn mark_unsent(&self, partners: &[NodeId], unsent: &mut Vec<bool>) {
+        if self.crashed_at.is_none() {
+            return;
+        }
+        unsent.resize(2 * self.n, false);
+        for (node, &partner) in partners.iter().enumerate() {
+            if self.config.crashed[node] || self.config.crashed[partner as usize] {
+                unsent[2 * node] = true;
+                unsent[2 * node + 1] = true;
+            }
+        }
+    }
+
+    /// Run round `round` of the membership protocol: probe, time out suspicions, spread the
+    /// views over the messages of the vote exchanges with `partners` that got through, and
+    /// refute suspicions.
+    pub fn step<R: Rng>(
+        &mut self,
+        round: usize,
+        partners: &[NodeId],
+        lost: &[bool],
+        unsent: &[bool],
+        loss: f64,
+        rng: &mut R,
+    ) {
+        // Every message carries the sender's view as it was at the start of the round.
+        let views = self.views.clone();
+        for node in 0..self.n {
+            if !self.is_crashed(node) {
+                self.probe(node, round, &views, loss, rng);
+            }
+        }
+
+        for node in 0..self.n {
+            let timed_out: Vec<NodeId> = self.views[node]
+                .iter()
+                .filter(|(_, entry)| match entry.status {
+                    Status::Suspect(since) => round - since >= self.config.suspicion_rounds,
+                    _ => false,
+                })
+                .map(|(&other, _)| other)
+                .collect();
+            for other in timed_out {
+                let incarnation = self.views[node][&other].incarnation;
+                self.update(node, other, Entry { status: Status::Dead, incarnation });
+            }
+        }
+
+        for (node, &partner) in partners.iter().enumerate() {
+            let partner = partner as usize;
+            if !is_lost(lost, node, 1) && !is_lost(unsent, node, 1) {
+                self.merge(partner, &views[node], round);
+            }
+            if !is_lost(lost, node, 0) && !is_lost(unsent, node, 0) {
+                self.merge(node, &views[partner], round);
+            }
+        }
+
+        for node in 0..self.n {
+            let Some(&entry) = self.views[node].get(&(node as NodeId)) else {
+                continue;
+            };
+            if self.is_crashed(node) || entry.status == Status::Alive {
+                continue;
+            }
+            self.incarnations[node] = self.incarnations[node].max(entry.incarnation + 1);
+            let alive = Entry { status: Status::Alive, incarnation: self.incarnations[node] };
+            self.views[node].insert(node as NodeId, alive);
+        }
+        if self.crashed_at.is_some() {
+            self.check_detected(round + 1);
+        }
+    }
+
+    /// Whether a message of the membership protocol gets through, counting it as sent.
+    fn deliver<R: Rng>(&mut self, loss: f64, rng: &mut R) -> bool {
+        self.num_messages += 1;
+        !(loss > 0.0 && rng.next_f64() < loss)
+    }
+
+    /// Have `node` probe a random node it doesn't believe dead, directly and then through the
+    /// indirect probes, and suspect it if no ack comes back. A direct ping and its ack carry
+    /// the sender's view in `views`, as SWIM piggybacks its updates on them.
+    fn probe<R: Rng>(
+        &mut self,
+        node: usize,
+        round: usize,
+        views: &[BTreeMap<NodeId, Entry>],
+        loss: f64,
+        rng: &mut R,
+    ) {
+        let Some(target) = self.choose_live(node, rng) else {
+            return;
+        };
+        let target_alive = !self.is_crashed(target as usize);
+        let mut acked = false;
+        if self.deliver(loss, rng) && target_alive {
+            self.merge(target as usize, &views[node], round);
+            if self.deliver(loss, rng) {
+                self.merge(node, &views[target as usize], round);
+                acked = true;
+            }
+        }
+        for _ in 0..self.config.indirect_probes {
+            if acked {
+                break;
+            }
+            let helper = loop {
+                let helper = choose_partner(node as NodeId, self.n, rng);
+                if helper != target {
+                    break helper as usize;
+                }
+            };
+            acked = self.deliver(loss, rng)
+                && !self.is_crashed(helper)
+                && self.deliver(loss, rng)
+                && target_alive
+                && self.deliver(loss, rng)
+                && self.deliver(loss, rng);
+        }
+        if acked {
+            return;
+        }
+        let known = self.views[node].get(&target).copied();
+        if known.is_some_and(|entry| entry.status != Status::Alive) {
+            return;
+        }
+        let incarnation = known.map_or(0, |entry| entry.incarnation);
+        self.num_false_suspicions += usize::from(target_alive);
+        let suspect = Entry { status: Status::Suspect(round), incarnation };
+        self.update(node, target, suspect);
+    }
+
+    /// Merge `view` into `node`'s view, starting the timers of new suspicions in `round`.
+    fn merge(&mut self, node: usize, view: &BTreeMap<NodeId, Entry>, round: usize) {
+        for (&other, &entry) in view {
+            let entry = match entry.status {
+                Status::Suspect(_) => Entry { status: Status::Suspect(round), ..entry },
+                _ => entry,
+            };
+            // A suspicion of the same incarnation doesn't override another, so it doesn't
+            // restart the timer.
+            if entry.overrides(self.views[node].get(&other).copied()) {
+                self.update(node, other, entry);
+            }
+        }
+    }
+
+    /// Set `node`'s view of `other` to `entry`.
+    fn update(&mut self, node: usize, other: NodeId, entry: Entry) {
+        let old = self.views[node].insert(other, entry);
+        let was_dead = old.is_some_and(|old| old.status == Status::Dead);
+        let is_dead = entry.status == Status::Dead;
+        if was_dead == is_dead || self.is_crashed(node) || other as usize == node {
+            return;
+        }
+        let other = other as usize;
+        if is_dead {
+            self.num_believing_dead[other] += 1;
+            self.wrongly_dead[other] |= !self.is_crashed(other);
+        } else {
+            self.num_believing_dead[other] -= 1;
+        }
+    }
+
+    /// Record which crashed nodes every live node believes dead after `rounds` rounds.
+    fn check_detected(&mut self, rounds: usize) {
+        let num_live = self.n - self.num_crashed;
+        for node in 0..self.n {
+            if self.config.crashed[node]
+                && self.detected_at[node].is_none()
+                && self.num_believing_dead[node] == num_live
+            {
+                self.detected_at[node] = Some(rounds);
+            }
+        }
+    }
+
+    /// `result` with how long the crashes took to detect, the mistakes made along the way and
+    /// the messages that the probes took.
+    pub fn apply_to(&self, result: SimulationResult) -> SimulationResult {
+        let crashed_at = self.crashed_at.unwrap_or(0);
+        let detection_rounds: Option<Vec<usize>> = (0..self.n)
+            .filter(|&node| self.config.crashed[node] && self.crashed_at.is_some())
+            .map(|node| self.detected_at[node].map(|round| round - crashed_at))
+            .collect();
+        let detection_rounds = detection_rounds.filter(|rounds| !rounds.is_empty());
+        let false_positives = self.wrongly_dead.iter().filter(|&&wrongly| wrongly).count();
+        SimulationResult {
+            detection_rounds: detection_rounds
+                .as_ref()
+                .and_then(|rounds| rounds.iter().max())
+                .map(|&rounds| rounds as f64),
+            mean_detection_rounds: detection_rounds.as_ref().map(|rounds| {
+                rounds.iter().sum::<usize>() as f64 / rounds.len() as f64
+            }),
+            false_suspicions: self.num_false_suspicions as f64,
+            false_positives: false_positives as f64,
+            num_membership_messages: self.num_messages,
+            ..result
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::seeded_rng;
+    use rand::XorShiftRng;
+    use serde_json::json;
+
+    fn params(n: usize, k: usize, spec: &str) -> Params {
+        serde_json::from_value(json!({"n": n, "k": k, "voting_steps": 1, "membership": spec}))
+            .unwrap()
+    }
+
+    fn membership(n: usize, k: usize, spec: &str) -> Membership {
+        Membership::new(&params(n, k, spec)).unwrap().unwrap()
+    }
+
+    fn entry(status: Status, incarnation: u32) -> Entry {
+        Entry { status, incarnation }
+    }
+
+    fn view(entries: &[(NodeId, Entry)]) -> BTreeMap<NodeId, Entry> {
+        entries.iter().copied().collect()
+    }
+
+    /// Run a round of the membership layer as `Simulation::step` does, with every message of
+    /// the vote exchanges lost at rate `loss`.
+    fn step(membership: &mut Membership, round: usize, loss: f64, rng: &mut XorShiftRng) {
+        if membership.crashed_at.is_none() {
+            membership.crash(round, |_| true);
+        }
+        let n = membership.n;
+        let mut partners: Vec<NodeId> =
+            (0..n as NodeId).map(|node| choose_partner(node, n, rng)).collect();
+        membership.avoid_dead(&mut partners, rng);
+        let mut unsent = vec![];
+        membership.mark_unsent(&partners, &mut unsent);
+        let lost: Vec<bool> = (0..2 * n).map(|_| loss > 0.0 && rng.next_f64() < loss).collect();
+        membership.step(round, &partners, &lost, &unsent, loss, rng);
+    }
+
+    #[test]
+    fn rejects_malformed_specs() {
+        for (spec, error) in [
+            ("0.1:5:3:4:1", "should be <crash fraction>"),
+            ("", "invalid crash fraction"),
+            ("0.1:-1", "invalid crash round"),
+            ("0.1:0:three", "invalid number of indirect probes"),
+            ("1.01", "crash fraction 1.01 should be in [0, 1]"),
+            ("0.1:0:9", "9 indirect probes need more than the 8 nodes"),
+            ("0.1:0:3:0", "suspicion rounds must be at least 1"),
+        ] {
+            let e = parse_membership(spec, 10, 5).err().unwrap();
+            assert!(e.contains(error), "{:?} gave {:?}, expected {:?}", spec, e, error);
+        }
+    }
+
+    #[test]
+    fn accepts_the_ends_of_its_ranges() {
+        let config = parse_membership("0.4:2", 10, 5).unwrap();
+        let rounds = (config.crash_round, config.indirect_probes, config.suspicion_rounds);
+        assert_eq!(rounds, (2, 3, 3));
+        // Only the nodes that don't vote crash, all of them with a fraction of 1.
+        assert_eq!(config.crashed.iter().filter(|&&crashed| crashed).count(), 2);
+        assert!(config.crashed[..5].iter().all(|&crashed| !crashed));
+        let config = parse_membership("1", 10, 5).unwrap();
+        assert_eq!(config.crashed, [[false; 5], [true; 5]].concat());
+        assert!(!parse_membership("0", 10, 5).unwrap().crashed.contains(&true));
+
+        // Probing through every other node, and suspecting for a single round.
+        let config = parse_membership("0.1:0:8:1", 10, 5).unwrap();
+        assert_eq!((config.indirect_probes, config.suspicion_rounds), (8, 1));
+        // Two nodes have no one to probe through.
+        assert!(parse_membership("0:0:0", 2, 1).is_ok());
+        assert!(parse_membership("0:0:1", 2, 1).is_err());
+    }
+
+    #[test]
+    fn dead_overrides_suspect_at_the_same_incarnation() {
+        let mut membership = membership(5, 5, "0");
+        membership.merge(0, &view(&[(3, entry(Status::Suspect(0), 0))]), 1);
+        assert_eq!(membership.views[0][&3].status, Status::Suspect(1));
+
+        // Alive and suspicion don't override a suspicion of the same incarnation, or restart
+        // its timer.
+        membership.merge(0, &view(&[(3, entry(Status::Alive, 0))]), 2);
+        membership.merge(0, &view(&[(3, entry(Status::Suspect(0), 0))]), 2);
+        assert_eq!(membership.views[0][&3].status, Status::Suspect(1));
+
+        membership.merge(0, &view(&[(3, entry(Status::Dead, 0))]), 2);
+        assert!(membership.believes_dead(0, 3));
+        assert_eq!(membership.num_believing_dead[3], 1);
+        assert!(membership.wrongly_dead[3]);
+
+        // Nor does suspicion override dead.
+        membership.merge(0, &view(&[(3, entry(Status::Suspect(0), 0))]), 3);
+        assert!(membership.believes_dead(0, 3));
+    }
+
+    #[test]
+    fn a_later_incarnation_refutes_suspicion() {
+        let mut membership = membership(5, 5, "0");
+        membership.merge(0, &view(&[(3, entry(Status::Dead, 0))]), 1);
+        membership.merge(1, &view(&[(3, entry(Status::Suspect(0), 0))]), 1);
+        assert_eq!(membership.num_believing_dead[3], 1);
+
+        membership.merge(0, &view(&[(3, entry(Status::Alive, 1))]), 2);
+        membership.merge(1, &view(&[(3, entry(Status::Alive, 1))]), 2);
+        assert_eq!(membership.views[0][&3].status, Status::Alive);
+        assert_eq!(membership.views[1][&3].status, Status::Alive);
+        assert_eq!(membership.num_believing_dead[3], 0);
+
+        // A node that hears it's suspected moves to a later incarnation in the next round.
+        let mut rng = seeded_rng(1);
+        membership.merge(3, &view(&[(3, entry(Status::Suspect(0), 1))]), 2);
+        step(&mut membership, 2, 0.0, &mut rng);
+        assert_eq!(membership.incarnations[3], 2);
+        assert_eq!(membership.views[3][&3].status, Status::Alive);
+        assert_eq!(membership.views[3][&3].incarnation, 2);
+    }
+
+    #[test]
+    fn every_live_node_detects_a_crash() {
+        let mut membership = membership(20, 10, "0.3:2:3:2");
+        let crashed: Vec<usize> = (0..20).filter(|&node| membership.config.crashed[node]).collect();
+        assert_eq!(crashed.len(), 3);
+
+        let mut rng = seeded_rng(2);
+        let mut round = 0;
+        while !membership.is_settled() {
+            assert!(round < 100, "the crashes weren't detected within 100 rounds");
+            if round == 2 {
+                // A crashed node's beliefs stop counting once it crashes.
+                membership.update(crashed[0], 1, entry(Status::Dead, 0));
+                assert_eq!(membership.num_believing_dead[1], 1);
+                membership.crash(2, |_| true);
+                assert_eq!(membership.num_believing_dead[1], 0);
+            }
+            step(&mut membership, round, 0.0, &mut rng);
+            round += 1;
+        }
+        assert!(round > 2);
+        for node in (0..20).filter(|node| !crashed.contains(node)) {
+            for &other in &crashed {
+                assert!(membership.believes_dead(node, other as NodeId), "{} of {}", node, other);
+            }
+        }
+        for &node in &crashed {
+            assert_eq!(membership.num_believing_dead[node], 17);
+            assert!(membership.detected_at[node].is_some());
+        }
+
+        let result = membership.apply_to(SimulationResult::for_params(&params(20, 10, "0.3")));
+        let detection_rounds = result.detection_rounds.unwrap();
+        assert_eq!(detection_rounds, (round - 2) as f64);
+        assert!(result.mean_detection_rounds.unwrap() <= detection_rounds);
+        // Only the belief made up above, before the crash.
+        assert_eq!(result.false_positives, 1.0);
+        assert!(result.num_membership_messages > 0);
+    }
+
+    #[test]
+    fn counts_live_nodes_wrongly_believed_dead() {
+        // Every probe is lost, so every node suspects the nodes it probes and then believes
+        // them dead, although none crash.
+        let mut membership = membership(5, 5, "0:0:3:2");
+        let mut rng = seeded_rng(3);
+        for round in 0..3 {
+            step(&mut membership, round, 1.0, &mut rng);
+        }
+        let result = membership.apply_to(SimulationResult::for_params(&params(5, 5, "0")));
+        assert!(result.false_suspicions > 0.0);
+        assert!(result.false_positives > 0.0);
+        assert_eq!(result.detection_rounds, None);
+        // With nothing to detect, the run is settled as soon as the crash round passes.
+        assert!(membership.is_settled());
+    }
+}
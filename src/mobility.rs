@@ -0,0 +1,384 @@
+//! Nodes that move around and can only gossip with the nodes in radio range, as in an ad-hoc or
+//! mesh network, so that every node's neighbours change from round to round.
+//!
+//! A row's `mobility` column is `<model>:<radius>:<speed>`, such as `waypoint:0.1:0.01`. Nodes
+//! start at uniformly random positions in a unit square and move `speed` each round after the
+//! first, under one of two models:
+//!
+//! * `waypoint`, the random waypoint model: each node heads for a random point in the square,
+//!   and picks another as soon as it gets there, without pausing.
+//! * `velocity`: each node keeps moving in a random direction, bouncing off the edges of the
+//!   square.
+//!
+//! Every round, each node chooses its partner uniformly from the nodes within `radius` of it,
+//! in place of the row's strategy. A node with no other node in range makes no exchange that
+//! round, though others can still reach it if it's in their range. Nodes that never move may
+//! never all be connected, so a speed of 0 needs a `max_rounds` deadline.
+
+use rand::Rng;
+
+use crate::{NodeId, Params, SimulationResult};
+
+/// How nodes move.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MobilityModel {
+    Waypoint,
+    Velocity,
+}
+
+impl MobilityModel {
+    /// The name of the model, as parsed by `from_str`.
+    pub fn name(self) -> &'static str {
+        match self {
+            MobilityModel::Waypoint => "waypoint",
+            MobilityModel::Velocity => "velocity",
+        }
+    }
+}
+
+impl std::str::FromStr for MobilityModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "waypoint" => Ok(MobilityModel::Waypoint),
+            "velocity" => Ok(MobilityModel::Velocity),
+            _ => Err(format!("unknown mobility model {:?}, expected waypoint or velocity", s)),
+        }
+    }
+}
+
+/// The movement and range of a mobility spec.
+#[derive(Clone, Copy, Debug)]
+pub struct MobilityConfig {
+    pub model: MobilityModel,
+    pub radius: f64,
+    pub speed: f64,
+}
+
+/// Parse a mobility spec, in the format described in the module docs.
+pub fn parse_mobility(spec: &str) -> Result<MobilityConfig, String> {
+    let fields: Vec<&str> = spec.trim().split(':').collect();
+    let [model, radius, speed] = fields[..] else {
+        return Err(format!("mobility {:?} should be <model>:<radius>:<speed>", spec));
+    };
+    let model = model.parse()?;
+    let radius: f64 = radius
+        .parse()
+        .map_err(|_| format!("invalid radius in mobility {:?}", spec))?;
+    let speed: f64 = speed
+        .parse()
+        .map_err(|_| format!("invalid speed in mobility {:?}", spec))?;
+    if !(radius > 0.0 && radius.is_finite()) {
+        return Err(format!("mobility radius {} should be positive", radius));
+    }
+    if !(0.0..=1.0).contains(&speed) {
+        return Err(format!("mobility speed {} should be in [0, 1], the side of the square", speed));
+    }
+    Ok(MobilityConfig { model, radius, speed })
+}
+
+/// A point in the unit square, or a velocity.
+#[derive(Clone, Copy, Debug)]
+struct Point {
+    x: f64,
+    y: f64,
+}
+
+impl Point {
+    fn random<R: Rng>(rng: &mut R) -> Self {
+        Point { x: rng.next_f64(), y: rng.next_f64() }
+    }
+
+    fn distance_squared(self, other: Point) -> f64 {
+        (self.x - other.x).powi(2) + (self.y - other.y).powi(2)
+    }
+}
+
+/// Move `position` by `velocity`, bouncing off the edges of the unit square.
+fn bounce(position: &mut f64, velocity: &mut f64) {
+    *position += *velocity;
+    if *position < 0.0 {
+        *position = -*position;
+        *velocity = -*velocity;
+    } else if *position > 1.0 {
+        *position = 2.0 - *position;
+        *velocity = -*velocity;
+    }
+}
+
+/// The positions and movement of the nodes of a run, and the neighbours they had.
+pub struct Mobility {
+    config: MobilityConfig,
+    n: usize,
+    positions: Vec<Point>,
+    /// Each node's waypoint or velocity, depending on the model.
+    headings: Vec<Point>,
+    /// Cells of the grid that the square is split into, along each side, no narrower than the
+    /// radius, so that every node in range is in the same cell or one of the eight around it.
+    cells_per_side: usize,
+    /// The nodes in each cell, row by row.
+    cells: Vec<Vec<NodeId>>,
+    /// Buffer for the nodes in range of a node.
+    in_range: Vec<NodeId>,
+    isolated: Vec<bool>,
+    num_neighbours: usize,
+    num_isolated: usize,
+    num_node_rounds: usize,
+}
+
+impl Mobility {
+    /// The mobility of the nodes of `params`, if they move.
+    pub fn new(params: &Params) -> Result<Option<Self>, String> {
+        let Some(spec) = &params.mobility else {
+            return Ok(None);
+        };
+        let config = parse_mobility(spec)?;
+        let n = params.n;
+        // Enough cells for the radius, but no more than about one per node.
+        let cells_per_side =
+            ((1.0 / config.radius).floor() as usize).clamp(1, (n as f64).sqrt().ceil() as usize);
+        Ok(Some(Mobility {
+            config,
+            n,
+            positions: vec![],
+            headings: vec![],
+            cells_per_side,
+            cells: vec![vec![]; cells_per_side * cells_per_side],
+            in_range: vec![],
+            isolated: vec![false; n],
+            num_neighbours: 0,
+            num_isolated: 0,
+            num_node_rounds: 0,
+        }))
+    }
+
+    /// Place the nodes at the start of a run, or move them on.
+    fn advance<R: Rng>(&mut self, rng: &mut R) {
+        let speed = self.config.speed;
+        if self.positions.is_empty() {
+            self.positions = (0..self.n).map(|_| Point::random(rng)).collect();
+            self.headings = match self.config.model {
+                MobilityModel::Waypoint => (0..self.n).map(|_| Point::random(rng)).collect(),
+                MobilityModel::Velocity => (0..self.n)
+                    .map(|_| {
+                        let angle = rng.next_f64() * std::f64::consts::TAU;
+                        Point { x: speed * angle.cos(), y: speed * angle.sin() }
+                    })
+                    .collect(),
+            };
+            return;
+        }
+        for (position, heading) in self.positions.iter_mut().zip(&mut self.headings) {
+            match self.config.model {
+                MobilityModel::Waypoint => {
+                    let distance = position.distance_squared(*heading).sqrt();
+                    if distance <= speed {
+                        *position = *heading;
+                        *heading = Point::random(rng);
+                    } else {
+                        position.x += (heading.x - position.x) / distance * speed;
+                        position.y += (heading.y - position.y) / distance * speed;
+                    }
+                }
+                MobilityModel::Velocity => {
+                    bounce(&mut position.x, &mut heading.x);
+                    bounce(&mut position.y, &mut heading.y);
+                }
+            }
+        }
+    }
+
+    /// The cell of the grid that `point` is in, as a column and row.
+    fn cell(&self, point: Point) -> (usize, usize) {
+        let side = self.cells_per_side;
+        let index = |coordinate: f64| ((coordinate * side as f64) as usize).min(side - 1);
+        (index(point.x), index(point.y))
+    }
+
+    /// Move the nodes for the round, then have each choose a partner in range in place of its
+    /// partner in `partners`. Nodes with none in range keep a partner, whose exchange
+    /// `mark_unsent` marks.
+    pub fn choose_partners<R: Rng>(&mut self, partners: &mut [NodeId], rng: &mut R) {
+        self.advance(rng);
+        let side = self.cells_per_side;
+        for cell in &mut self.cells {
+            cell.clear();
+        }
+        for (node, &position) in self.positions.iter().enumerate() {
+            let (column, row) = self.cell(position);
+            self.cells[row * side + column].push(node as NodeId);
+        }
+
+        let radius_squared = self.config.radius * self.config.radius;
+        for (node, partner) in partners.iter_mut().enumerate() {
+            let position = self.positions[node];
+            let (column, row) = self.cell(position);
+            self.in_range.clear();
+            for cell_row in row.saturating_sub(1)..(row + 2).min(side) {
+                for cell_column in column.saturating_sub(1)..(column + 2).min(side) {
+                    let nearby = self.cells[cell_row * side + cell_column].iter().filter(|&&other| {
+                        other as usize != node
+                            && self.positions[other as usize].distance_squared(position)
+                                <= radius_squared
+                    });
+                    self.in_range.extend(nearby);
+                }
+            }
+            self.num_neighbours += self.in_range.len();
+            self.isolated[node] = self.in_range.is_empty();
+            if self.in_range.is_empty() {
+                self.num_isolated += 1;
+                *partner = ((node + 1) % self.n) as NodeId;
+            } else {
+                *partner = self.in_range[rng.gen_range(0, self.in_range.len())];
+            }
+        }
+        self.num_node_rounds += self.n;
+    }
+
+    /// Where `node` is in the unit square, as of the last round, or `None` before the first round
+    /// places the nodes.
+    pub fn position(&self, node: usize) -> Option<(f64, f64)> {
+        self.positions.get(node).map(|point| (point.x, point.y))
+    }
+
+    /// Mark the exchanges of the nodes with none in range in `unsent`, in the order that
+    /// `is_lost` looks them up.
+    pub fn mark_unsent(&self, unsent: &mut Vec<bool>) {
+        unsent.resize(2 * self.n, false);
+        for (node, &isolated) in self.isolated.iter().enumerate() {
+            unsent[2 * node] |= isolated;
+            unsent[2 * node + 1] |= isolated;
+        }
+    }
+
+    /// `result` with the mean number of nodes in range of each node each round, and how often
+    /// there were none.
+    pub fn apply_to(&self, result: SimulationResult) -> SimulationResult {
+        let node_rounds = self.num_node_rounds.max(1) as f64;
+        SimulationResult {
+            mean_neighbours: self.num_neighbours as f64 / node_rounds,
+            isolated_fraction: self.num_isolated as f64 / node_rounds,
+            ..result
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::seeded_rng;
+    use serde_json::json;
+
+    fn mobility(n: usize, spec: &str) -> Mobility {
+        let params: Params =
+            serde_json::from_value(json!({"n": n, "k": 1, "voting_steps": 1, "mobility": spec}))
+                .unwrap();
+        Mobility::new(&params).unwrap().unwrap()
+    }
+
+    #[test]
+    fn rejects_malformed_specs() {
+        for (spec, error) in [
+            ("waypoint:0.1", "should be <model>:<radius>:<speed>"),
+            ("teleport:0.1:0.01", "unknown mobility model"),
+            ("waypoint::0.01", "invalid radius"),
+            ("waypoint:0:0.01", "radius 0 should be positive"),
+            ("waypoint:-0:0.01", "should be positive"),
+            ("waypoint:-0.1:0.01", "radius -0.1 should be positive"),
+            ("waypoint:inf:0.01", "radius inf should be positive"),
+            ("waypoint:NaN:0.01", "radius NaN should be positive"),
+            ("velocity:0.1:1.0001", "speed 1.0001 should be in [0, 1]"),
+            ("velocity:0.1:-0.01", "should be in [0, 1]"),
+        ] {
+            let e = parse_mobility(spec).unwrap_err();
+            assert!(e.contains(error), "{:?} gave {:?}, expected {:?}", spec, e, error);
+        }
+    }
+
+    #[test]
+    fn accepts_the_ends_of_its_ranges() {
+        // Nodes that stand still, and that cross the square in a round.
+        let config = parse_mobility("velocity:0.2:0").unwrap();
+        assert_eq!(config.model, MobilityModel::Velocity);
+        assert_eq!((config.radius, config.speed), (0.2, 0.0));
+        assert_eq!(parse_mobility("waypoint:0.2:1").unwrap().speed, 1.0);
+        // A radius too small to reach anyone, or wide enough to reach across the square.
+        assert_eq!(parse_mobility("waypoint:1e-9:0.1").unwrap().radius, 1e-9);
+        assert_eq!(parse_mobility("waypoint:2:0.1").unwrap().radius, 2.0);
+    }
+
+    #[test]
+    fn bounces_back_into_the_square() {
+        for (start, velocity, end, reflected) in [
+            (0.5, 0.25, 0.75, 0.25),
+            (0.9, 0.25, 0.85, -0.25),
+            (0.1, -0.25, 0.15, 0.25),
+            (1.0, 1.0, 0.0, -1.0),
+            (0.0, -1.0, 1.0, 1.0),
+        ] {
+            let (mut position, mut heading) = (start, velocity);
+            bounce(&mut position, &mut heading);
+            assert!((position - end).abs() < 1e-12, "{} + {} gave {}", start, velocity, position);
+            assert_eq!(heading, reflected);
+        }
+
+        let mut rng = seeded_rng(1);
+        for _ in 0..1000 {
+            let mut position = rng.next_f64();
+            let mut velocity = 2.0 * rng.next_f64() - 1.0;
+            for _ in 0..20 {
+                bounce(&mut position, &mut velocity);
+                assert!((0.0..=1.0).contains(&position), "{} left the square", position);
+            }
+        }
+    }
+
+    #[test]
+    fn chooses_partners_in_range() {
+        for spec in ["waypoint:0.15:0.05", "velocity:0.15:0.05"] {
+            let mut mobility = mobility(200, spec);
+            let mut rng = seeded_rng(2);
+            let mut partners = vec![0; 200];
+            for _ in 0..10 {
+                mobility.choose_partners(&mut partners, &mut rng);
+                for (node, &partner) in partners.iter().enumerate() {
+                    if mobility.isolated[node] {
+                        continue;
+                    }
+                    assert_ne!(node, partner as usize);
+                    let distance = mobility.positions[node]
+                        .distance_squared(mobility.positions[partner as usize])
+                        .sqrt();
+                    assert!(distance <= 0.15, "{} chose {} at {}", node, partner, distance);
+                    let (x, y) = mobility.position(node).unwrap();
+                    assert!((0.0..=1.0).contains(&x) && (0.0..=1.0).contains(&y));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn isolated_nodes_make_no_exchanges() {
+        // Out of the range of each other, unless two land within a billionth.
+        let mut mobility = mobility(5, "waypoint:1e-9:0.1");
+        let mut partners = vec![0; 5];
+        assert_eq!(mobility.position(0), None);
+        mobility.choose_partners(&mut partners, &mut seeded_rng(3));
+        assert_eq!(mobility.isolated, vec![true; 5]);
+        assert_eq!(partners, vec![1, 2, 3, 4, 0]);
+        assert_eq!((mobility.num_neighbours, mobility.num_isolated), (0, 5));
+
+        let mut unsent = vec![];
+        mobility.mark_unsent(&mut unsent);
+        assert_eq!(unsent, vec![true; 10]);
+
+        // Only the first node is isolated.
+        mobility.isolated = vec![true, false, false, false, false];
+        let mut unsent = vec![false; 10];
+        mobility.mark_unsent(&mut unsent);
+        let marked: Vec<usize> = (0..10).filter(|&i| unsent[i]).collect();
+        assert_eq!(marked, vec![0, 1]);
+    }
+}
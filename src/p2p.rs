@@ -0,0 +1,243 @@
+//! Run the voting workload over a swarm of real libp2p gossipsub nodes.
+//!
+//! All nodes live in one process, connected by libp2p's in-memory transport. Each vote is
+//! published as its own gossipsub message on a single topic, and a node has a quorum once the
+//! votes it has received satisfy the row's quorum rule. Rounds are measured in gossipsub heartbeats, so
+//! that `num_iterations` is comparable with the simulator's rounds. Gossipsub doesn't batch
+//! votes, so every delivery counts as both an exchange and a vote exchange.
+//!
+//! Signing and verifying messages is slow enough in debug builds to stretch rounds out, so
+//! comparisons should be run with `--release`.
+
+use libp2p::core::transport::{MemoryTransport, Transport};
+use libp2p::core::upgrade::Version;
+use libp2p::futures::StreamExt;
+use libp2p::gossipsub::{self, IdentTopic, MessageAuthenticity};
+use libp2p::swarm::SwarmEvent;
+use libp2p::{noise, yamux, Multiaddr, Swarm, SwarmBuilder};
+use rand::{weak_rng, Rng};
+use std::error::Error;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::voters::VoterSet;
+use crate::{
+    construct_voting_schedule, load_quorum_rule, Milestones, NodeId, Params, SimulationResult,
+};
+
+/// Length of a round, which is also the gossipsub heartbeat interval.
+const HEARTBEAT: Duration = Duration::from_millis(100);
+
+/// Number of random peers each node dials to build the swarm.
+const DIAL_PEERS: usize = 8;
+
+/// Rounds to wait for the gossipsub mesh to form once every node has a subscribed peer.
+const WARMUP_ROUNDS: usize = 20;
+
+/// Give up on a run that hasn't converged after this many rounds.
+const MAX_ROUNDS: usize = 1000;
+
+const TOPIC: &str = "rumour-0";
+
+enum Command {
+    Dial(Multiaddr),
+    Vote,
+}
+
+enum NodeEvent {
+    Listening(usize, Multiaddr),
+    PeerSubscribed(usize),
+    Received(usize, NodeId),
+    PublishFailed(usize, String),
+}
+
+fn build_swarm() -> Result<Swarm<gossipsub::Behaviour>, Box<dyn Error>> {
+    let swarm = SwarmBuilder::with_new_identity()
+        .with_tokio()
+        .with_other_transport(|key| {
+            Ok(MemoryTransport::default()
+                .upgrade(Version::V1)
+                .authenticate(noise::Config::new(key)?)
+                .multiplex(yamux::Config::default())
+                .boxed())
+        })?
+        .with_behaviour(|key| {
+            let config = gossipsub::ConfigBuilder::default()
+                .heartbeat_interval(HEARTBEAT)
+                .build()?;
+            Ok(gossipsub::Behaviour::new(MessageAuthenticity::Signed(key.clone()), config)?)
+        })?
+        .with_swarm_config(|config| config.with_idle_connection_timeout(Duration::from_secs(60)))
+        .build();
+    Ok(swarm)
+}
+
+async fn run_node(
+    id: usize,
+    mut swarm: Swarm<gossipsub::Behaviour>,
+    mut commands: mpsc::UnboundedReceiver<Command>,
+    events: mpsc::UnboundedSender<NodeEvent>,
+) {
+    let topic = IdentTopic::new(TOPIC);
+    loop {
+        tokio::select! {
+            command = commands.recv() => match command {
+                Some(Command::Dial(addr)) => {
+                    let _ = swarm.dial(addr);
+                }
+                Some(Command::Vote) => {
+                    let vote = (id as u32).to_le_bytes().to_vec();
+                    if let Err(e) = swarm.behaviour_mut().publish(topic.clone(), vote) {
+                        let _ = events.send(NodeEvent::PublishFailed(id, e.to_string()));
+                    }
+                }
+                // The run is over.
+                None => return,
+            },
+            event = swarm.select_next_some() => match event {
+                SwarmEvent::NewListenAddr { address, .. } => {
+                    let _ = events.send(NodeEvent::Listening(id, address));
+                }
+                SwarmEvent::Behaviour(gossipsub::Event::Subscribed { .. }) => {
+                    let _ = events.send(NodeEvent::PeerSubscribed(id));
+                }
+                SwarmEvent::Behaviour(gossipsub::Event::Message { message, .. }) => {
+                    if let [a, b, c, d] = message.data[..] {
+                        let voter = NodeId::from_le_bytes([a, b, c, d]);
+                        let _ = events.send(NodeEvent::Received(id, voter));
+                    }
+                }
+                _ => (),
+            },
+        }
+    }
+}
+
+async fn run_swarm(params: &Params) -> Result<SimulationResult, Box<dyn Error>> {
+    let n = params.n;
+    let mut rng = weak_rng();
+    let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+    let mut commands = vec![];
+
+    for id in 0..n {
+        let mut swarm = build_swarm()?;
+        swarm.behaviour_mut().subscribe(&IdentTopic::new(TOPIC))?;
+        swarm.listen_on("/memory/0".parse()?)?;
+        let (command_tx, command_rx) = mpsc::unbounded_channel();
+        commands.push(command_tx);
+        tokio::spawn(run_node(id, swarm, command_rx, event_tx.clone()));
+    }
+
+    let mut addrs = vec![None; n];
+    while addrs.iter().any(Option::is_none) {
+        if let Some(NodeEvent::Listening(id, addr)) = event_rx.recv().await {
+            addrs[id] = Some(addr);
+        }
+    }
+    for (id, command) in commands.iter().enumerate() {
+        for _ in 0..DIAL_PEERS.min(n - 1) {
+            let peer = loop {
+                let p = rng.gen_range(0, n);
+                if p != id {
+                    break p;
+                }
+            };
+            let _ = command.send(Command::Dial(addrs[peer].clone().unwrap()));
+        }
+    }
+
+    let mut has_subscribed_peer = vec![false; n];
+    while has_subscribed_peer.contains(&false) {
+        if let Some(NodeEvent::PeerSubscribed(id)) = event_rx.recv().await {
+            has_subscribed_peer[id] = true;
+        }
+    }
+
+    let mut heartbeat = tokio::time::interval(HEARTBEAT);
+    for _ in 0..WARMUP_ROUNDS {
+        heartbeat.tick().await;
+    }
+    // Drop anything received during warmup.
+    while event_rx.try_recv().is_ok() {}
+
+    let quorum = load_quorum_rule(params)?;
+    let voting_schedule = construct_voting_schedule(params.k, params.voting_steps);
+    let mut votes_held = vec![VoterSet::new(n); n];
+    let mut next_voter = 0;
+    let mut milestones = Milestones::default();
+    let mut num_deliveries = 0;
+    let mut round = 0;
+
+    let mut missed_deadline = false;
+    while !votes_held.iter().all(|voters| quorum.has_quorum(voters, n)) {
+        if Some(round) == params.max_rounds {
+            missed_deadline = true;
+            break;
+        }
+        if round == MAX_ROUNDS {
+            return Err(From::from(format!("libp2p swarm didn't converge within {} rounds", MAX_ROUNDS)));
+        }
+        if let Some(&num_voters) = voting_schedule.get(&round) {
+            for voter in next_voter..next_voter + num_voters {
+                votes_held[voter].insert(voter as NodeId);
+                let _ = commands[voter].send(Command::Vote);
+            }
+            next_voter += num_voters;
+        }
+
+        heartbeat.tick().await;
+        while let Ok(event) = event_rx.try_recv() {
+            match event {
+                NodeEvent::Received(id, voter) => {
+                    num_deliveries += 1;
+                    votes_held[id].insert(voter);
+                }
+                NodeEvent::PublishFailed(id, e) => {
+                    return Err(From::from(format!("node {} failed to publish its vote: {}", id, e)));
+                }
+                NodeEvent::Listening(..) | NodeEvent::PeerSubscribed(_) => (),
+            }
+        }
+        round += 1;
+        let with_quorum = votes_held.iter().filter(|voters| quorum.has_quorum(voters, n)).count();
+        let with_all_voters = votes_held.iter().filter(|voters| voters.len() == params.k).count();
+        milestones.record(n, round, with_quorum, with_all_voters);
+    }
+
+    let total_votes_held: usize = votes_held.iter().map(VoterSet::len).sum();
+
+    Ok(milestones.apply_to(SimulationResult {
+        num_iterations: round as f64,
+        num_exchanges: num_deliveries,
+        num_vote_exchanges: num_deliveries,
+        average_votes_held: total_votes_held as f64 / n as f64,
+        missed_deadline: if missed_deadline { 1.0 } else { 0.0 },
+        ..SimulationResult::for_params(params)
+    }))
+}
+
+/// Check that the swarm can run `params`, which it can't if they need the simulator to lose,
+/// limit or route messages, since gossipsub does all of that itself.
+pub fn validate(params: &Params) -> Result<(), String> {
+    if params.election.is_some() {
+        return Err("the gossipsub swarm only runs the vote workload, not elections".to_string());
+    }
+    let unsupported = [
+        (params.loss > 0.0, "message loss"),
+        (params.max_messages_per_round.is_some(), "rate limits"),
+        (params.cohorts.is_some(), "cohorts"),
+        (params.subnets.is_some(), "subnets"),
+        (params.membership.is_some(), "membership"),
+        (params.mobility.is_some(), "mobility"),
+    ];
+    match unsupported.iter().find(|(set, _)| *set) {
+        Some((_, name)) => Err(format!("the gossipsub swarm doesn't support {}", name)),
+        None => Ok(()),
+    }
+}
+
+/// Run the vote workload described by `params` over a fresh libp2p swarm.
+pub fn run(params: &Params) -> Result<SimulationResult, Box<dyn Error>> {
+    validate(params)?;
+    tokio::runtime::Runtime::new()?.block_on(run_swarm(params))
+}
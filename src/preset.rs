@@ -0,0 +1,71 @@
+//! Named scenarios that fill in the parameters that rows leave out, as `--preset` selects.
+//!
+//! `eth-attestation` approximates the attestations of a slot of Ethereum's consensus layer, with
+//! each row's `k` voters as the slot's attesters. It only fills in the columns of the models that
+//! already exist, so it leaves out what they don't model:
+//!
+//! * `quorum` is `count:<c>` for two thirds of the voters, as a checkpoint needs.
+//! * `subnets` splits the nodes into the 64 attestation subnets, or as many as there are nodes for
+//!   with at least two each, with 16 nodes of each, as many as the aggregators that a committee
+//!   aims for, as its bridges. Bridges forward every vote they've gathered rather than combining
+//!   them, so no aggregate of a subnet's votes is formed, and each vote counts towards a quorum
+//!   on its own once it arrives.
+//! * The voters are the first `k` nodes, as in every run, so they're spread evenly over the
+//!   subnets rather than each being in the subnet of its committee.
+//! * `max_rounds` is 17, the rounds of gossipsub's 0.7 second heartbeat in a 12 second slot, by
+//!   the end of which the next block should include the attestations.
+//! * `cost` is `0:1:aggregate`, so that the costs are the bytes sent, priced as if every message
+//!   were an aggregate carrying one signature, although the votes in it are never combined.
+
+use crate::Params;
+
+/// Attestation subnets on Ethereum's consensus layer.
+const ATTESTATION_SUBNETS: usize = 64;
+/// Aggregators that each committee aims for.
+const TARGET_AGGREGATORS_PER_COMMITTEE: usize = 16;
+/// Heartbeats of 0.7 seconds in a slot of 12 seconds.
+const SLOT_ROUNDS: usize = 17;
+
+/// A named scenario.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preset {
+    EthAttestation,
+}
+
+impl Preset {
+    /// The name of the preset, as parsed by `from_str`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::EthAttestation => "eth-attestation",
+        }
+    }
+
+    /// Fill in the parameters of the scenario that `params` leave out.
+    pub fn apply(self, params: &mut Params) {
+        match self {
+            Preset::EthAttestation => {
+                let (n, k) = (params.n, params.k);
+                params.quorum.get_or_insert_with(|| format!("count:{}", (2 * k).div_ceil(3)));
+                params.subnets.get_or_insert_with(|| {
+                    let num_subnets = ATTESTATION_SUBNETS.min(n / 2).max(1);
+                    let subnet_size = n.div_ceil(num_subnets);
+                    let fraction = TARGET_AGGREGATORS_PER_COMMITTEE as f64 / subnet_size as f64;
+                    format!("{}:{}", num_subnets, fraction.min(1.0))
+                });
+                params.max_rounds.get_or_insert(SLOT_ROUNDS);
+                params.cost.get_or_insert_with(|| "0:1:aggregate".to_string());
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for Preset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "eth-attestation" => Ok(Preset::EthAttestation),
+            _ => Err(format!("unknown preset {:?}, expected eth-attestation", s)),
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! Machine-readable progress events, for tools tracking long sweeps.
+//!
+//! Events are written as newline-delimited JSON, one object per line, with an `event` field
+//! naming the kind of event:
+//!
+//! ```text
+//! {"event":"row_started","row":1,"n":100,"k":70,"voting_steps":2,"repetitions":20}
+//! {"event":"milestone","row":1,"run":0,"round":3,"nodes_with_quorum":28,"percent":25}
+//! {"event":"row_finished","row":1,"rounds":6.6,"exchanges":949,"votes_sent":7635,"error":null}
+//! {"event":"finished","rows":1,"failed":0}
+//! ```
+
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::sync::Mutex;
+use tracing::warn;
+
+use crate::{Params, RoundMetrics, SimulationResult};
+
+/// Percentages of the nodes with a quorum at which each run reports a milestone.
+pub const MILESTONE_PERCENTS: &[usize] = &[25, 50, 75, 100];
+
+/// Formats that progress can be reported in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProgressFormat {
+    /// A JSON object per line.
+    Ndjson,
+}
+
+impl std::str::FromStr for ProgressFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "ndjson" => Ok(ProgressFormat::Ndjson),
+            _ => Err(format!("unknown progress format {:?}, expected ndjson", s)),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// A row's runs are about to start.
+    RowStarted { row: usize, n: usize, k: usize, voting_steps: usize, repetitions: usize },
+    /// A run reached a round in which at least `percent` of the nodes had a quorum.
+    Milestone { row: usize, run: usize, round: usize, nodes_with_quorum: usize, percent: usize },
+    /// A row's runs are over, with their averages, or the error that stopped them.
+    RowFinished {
+        row: usize,
+        rounds: f64,
+        exchanges: usize,
+        votes_sent: usize,
+        error: Option<&'a str>,
+    },
+    /// Every row is done.
+    Finished { rows: usize, failed: usize },
+}
+
+/// Where progress events are written.
+pub struct Progress {
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl Progress {
+    /// Report progress to the file or named pipe at `path`, or to stderr if there isn't one.
+    pub fn open(path: Option<&str>) -> io::Result<Progress> {
+        let writer: Box<dyn Write + Send> = match path {
+            // Not truncated, which a named pipe doesn't support.
+            Some(path) => Box::new(OpenOptions::new().create(true).append(true).open(path)?),
+            None => Box::new(io::stderr()),
+        };
+        Ok(Progress { writer: Mutex::new(writer) })
+    }
+
+    /// Write `event` on a line of its own. Progress is only a convenience, so failing to write
+    /// it is a warning rather than an error.
+    pub fn emit(&self, event: &Event) {
+        let mut writer = self.writer.lock().unwrap();
+        let written = serde_json::to_writer(&mut *writer, event)
+            .map_err(io::Error::from)
+            .and_then(|()| writeln!(writer))
+            .and_then(|()| writer.flush());
+        if let Err(e) = written {
+            warn!("failed to report progress: {}", e);
+        }
+    }
+
+    pub fn row_started(&self, row: usize, params: &Params, repetitions: usize) {
+        self.emit(&Event::RowStarted {
+            row,
+            n: params.n,
+            k: params.k,
+            voting_steps: params.voting_steps,
+            repetitions,
+        });
+    }
+
+    pub fn row_finished(&self, row: usize, result: &SimulationResult) {
+        self.emit(&Event::RowFinished {
+            row,
+            rounds: result.num_iterations,
+            exchanges: result.num_exchanges,
+            votes_sent: result.num_vote_exchanges,
+            error: result.error.as_deref(),
+        });
+    }
+
+    /// A callback for the rounds of row `row`, reporting each run's milestones.
+    pub fn milestones(&self, row: usize, n: usize) -> impl FnMut(&RoundMetrics) + '_ {
+        let mut run = None;
+        let mut next_milestone = 0;
+        move |metrics| {
+            if run != Some(metrics.repetition) {
+                run = Some(metrics.repetition);
+                next_milestone = 0;
+            }
+            while let Some(&percent) = MILESTONE_PERCENTS.get(next_milestone) {
+                if metrics.nodes_with_quorum * 100 < percent * n {
+                    break;
+                }
+                self.emit(&Event::Milestone {
+                    row,
+                    run: metrics.repetition,
+                    round: metrics.round,
+                    nodes_with_quorum: metrics.nodes_with_quorum,
+                    percent,
+                });
+                next_milestone += 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,335 @@
+//! Rules deciding when a set of votes is enough to confirm a rumour.
+//!
+//! A rule can be selected per parameter row with the `quorum` column, using one of these specs:
+//!
+//! * `majority`: more than half of all nodes (the default).
+//! * `threshold:<fraction>`: more than `fraction` of all nodes.
+//! * `count:<voters>`: at least `voters` voters, such as two thirds of a committee.
+//! * `weighted:<fraction>:<weights file>`: more than `fraction` of the total weight, reading the
+//!   weight of node `i` from line `i` of the file.
+//! * `regions:<regions>:<required>`: a majority within at least `required` of `regions` regions,
+//!   with node `i` in region `i % regions`.
+//!
+//! Specs joined with `+` must all be satisfied, e.g. `regions:3:2+threshold:0.6`.
+
+use std::fmt::Debug;
+use std::fs;
+use std::sync::Arc;
+
+use crate::voters::VoterSet;
+
+/// Decides whether a set of voters is a quorum.
+///
+/// Implement this to try out quorum definitions beyond the ones provided here, and pass it to
+/// `run_simulation`.
+pub trait QuorumRule: Debug + Send + Sync {
+    /// Whether `voters` form a quorum of the nodes with IDs `0..num_nodes`.
+    fn has_quorum(&self, voters: &VoterSet, num_nodes: usize) -> bool;
+
+    /// Check that the rule makes sense for a network of `num_nodes` nodes.
+    fn validate(&self, _num_nodes: usize) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// If whether voters form a quorum depends only on how many there are, the smallest number
+    /// that does.
+    fn min_voters(&self, _num_nodes: usize) -> Option<usize> {
+        None
+    }
+}
+
+/// More than half of all nodes.
+#[derive(Debug)]
+pub struct Majority;
+
+impl QuorumRule for Majority {
+    fn has_quorum(&self, voters: &VoterSet, num_nodes: usize) -> bool {
+        2 * voters.len() > num_nodes
+    }
+
+    fn min_voters(&self, num_nodes: usize) -> Option<usize> {
+        Some(num_nodes / 2 + 1)
+    }
+}
+
+fn validate_fraction(fraction: f64) -> Result<(), String> {
+    if !(0.0..1.0).contains(&fraction) {
+        return Err(format!("quorum fraction {} should be in [0, 1)", fraction));
+    }
+    Ok(())
+}
+
+/// More than `fraction` of all nodes.
+#[derive(Debug)]
+pub struct Threshold {
+    pub fraction: f64,
+}
+
+impl QuorumRule for Threshold {
+    fn has_quorum(&self, voters: &VoterSet, num_nodes: usize) -> bool {
+        voters.len() as f64 > self.fraction * num_nodes as f64
+    }
+
+    fn validate(&self, _num_nodes: usize) -> Result<(), String> {
+        validate_fraction(self.fraction)
+    }
+
+    fn min_voters(&self, num_nodes: usize) -> Option<usize> {
+        Some((self.fraction * num_nodes as f64).floor() as usize + 1)
+    }
+}
+
+/// At least `voters` voters, however many nodes there are.
+#[derive(Debug)]
+pub struct Count {
+    pub voters: usize,
+}
+
+impl QuorumRule for Count {
+    fn has_quorum(&self, voters: &VoterSet, _num_nodes: usize) -> bool {
+        voters.len() >= self.voters
+    }
+
+    fn validate(&self, num_nodes: usize) -> Result<(), String> {
+        if self.voters == 0 || self.voters > num_nodes {
+            return Err(format!("a quorum of {} voters can't be met by {} nodes", self.voters, num_nodes));
+        }
+        Ok(())
+    }
+
+    fn min_voters(&self, _num_nodes: usize) -> Option<usize> {
+        Some(self.voters)
+    }
+}
+
+/// More than `fraction` of the total weight, where node `i` has weight `weights[i]`.
+#[derive(Debug)]
+pub struct Weighted {
+    weights: Vec<f64>,
+    fraction: f64,
+    /// The sum of `weights`, which every check for a quorum compares against.
+    total_weight: f64,
+}
+
+impl Weighted {
+    /// A rule giving node `i` weight `weights[i]`.
+    pub fn new(weights: Vec<f64>, fraction: f64) -> Self {
+        let total_weight = weights.iter().sum();
+        Weighted { weights, fraction, total_weight }
+    }
+}
+
+impl QuorumRule for Weighted {
+    fn has_quorum(&self, voters: &VoterSet, _num_nodes: usize) -> bool {
+        let voted: f64 = voters.iter().map(|v| self.weights[v as usize]).sum();
+        voted > self.fraction * self.total_weight
+    }
+
+    fn validate(&self, num_nodes: usize) -> Result<(), String> {
+        if self.weights.len() != num_nodes {
+            return Err(format!("got {} weights for {} nodes", self.weights.len(), num_nodes));
+        }
+        if self.weights.iter().any(|&w| w < 0.0) {
+            return Err("weights can't be negative".to_string());
+        }
+        validate_fraction(self.fraction)
+    }
+}
+
+/// A majority within at least `required` of `num_regions` regions, where node `i` is in region
+/// `i % num_regions`.
+#[derive(Debug)]
+pub struct Regions {
+    pub num_regions: usize,
+    pub required: usize,
+}
+
+impl QuorumRule for Regions {
+    fn has_quorum(&self, voters: &VoterSet, num_nodes: usize) -> bool {
+        let mut votes_per_region = vec![0; self.num_regions];
+        for voter in voters.iter() {
+            votes_per_region[voter as usize % self.num_regions] += 1;
+        }
+        let regions_with_majority = (0..self.num_regions)
+            .filter(|&region| {
+                let region_size = (num_nodes + self.num_regions - 1 - region) / self.num_regions;
+                2 * votes_per_region[region] > region_size
+            })
+            .count();
+        regions_with_majority >= self.required
+    }
+
+    fn validate(&self, num_nodes: usize) -> Result<(), String> {
+        if self.num_regions == 0 || self.num_regions > num_nodes {
+            return Err(format!("can't split {} nodes into {} regions", num_nodes, self.num_regions));
+        }
+        if self.required > self.num_regions {
+            return Err(format!("can't require {} of {} regions", self.required, self.num_regions));
+        }
+        Ok(())
+    }
+}
+
+/// Every one of several rules.
+#[derive(Debug)]
+pub struct All(pub Vec<Arc<dyn QuorumRule>>);
+
+impl QuorumRule for All {
+    fn has_quorum(&self, voters: &VoterSet, num_nodes: usize) -> bool {
+        self.0.iter().all(|rule| rule.has_quorum(voters, num_nodes))
+    }
+
+    fn validate(&self, num_nodes: usize) -> Result<(), String> {
+        self.0.iter().try_for_each(|rule| rule.validate(num_nodes))
+    }
+
+    fn min_voters(&self, num_nodes: usize) -> Option<usize> {
+        self.0.iter().map(|rule| rule.min_voters(num_nodes)).try_fold(0, |max, min| Some(max.max(min?)))
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(field: Option<&str>, name: &str, spec: &str) -> Result<T, String> {
+    field
+        .and_then(|f| f.parse().ok())
+        .ok_or_else(|| format!("missing or invalid {} in quorum rule {:?}", name, spec))
+}
+
+fn read_weights(filename: &str) -> Result<Vec<f64>, String> {
+    let contents = fs::read_to_string(filename)
+        .map_err(|e| format!("failed to read weights file {}: {}", filename, e))?;
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| line.parse().map_err(|e| format!("bad weight {:?} in {}: {}", line, filename, e)))
+        .collect()
+}
+
+/// Parse a quorum rule from a spec, in the format described in the module docs.
+pub fn parse_quorum_rule(spec: &str) -> Result<Arc<dyn QuorumRule>, String> {
+    if spec.contains('+') {
+        let rules = spec.split('+').map(parse_quorum_rule).collect::<Result<_, _>>()?;
+        return Ok(Arc::new(All(rules)));
+    }
+
+    let mut fields = spec.trim().splitn(3, ':');
+    match fields.next() {
+        Some("majority") => Ok(Arc::new(Majority)),
+        Some("threshold") => Ok(Arc::new(Threshold {
+            fraction: parse_number(fields.next(), "fraction", spec)?,
+        })),
+        Some("count") => Ok(Arc::new(Count {
+            voters: parse_number(fields.next(), "number of voters", spec)?,
+        })),
+        Some("weighted") => {
+            let fraction = parse_number(fields.next(), "fraction", spec)?;
+            let filename = fields.next().ok_or_else(|| format!("missing weights file in quorum rule {:?}", spec))?;
+            Ok(Arc::new(Weighted::new(read_weights(filename)?, fraction)))
+        }
+        Some("regions") => Ok(Arc::new(Regions {
+            num_regions: parse_number(fields.next(), "number of regions", spec)?,
+            required: parse_number(fields.next(), "number of required regions", spec)?,
+        })),
+        _ => Err(format!("unknown quorum rule {:?}", spec)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NodeId;
+
+    fn voters(ids: impl IntoIterator<Item = NodeId>) -> VoterSet {
+        ids.into_iter().collect()
+    }
+
+    #[test]
+    fn rejects_malformed_specs() {
+        for (spec, error) in [
+            ("supermajority", "unknown quorum rule"),
+            ("majority+", "unknown quorum rule \"\""),
+            ("threshold", "missing or invalid fraction"),
+            ("count:-1", "missing or invalid number of voters"),
+            ("weighted:0.5", "missing weights file"),
+            ("weighted:0.5:/nonexistent/weights.txt", "failed to read weights file"),
+            ("regions:3", "missing or invalid number of required regions"),
+            ("majority+count", "missing or invalid number of voters"),
+        ] {
+            let e = parse_quorum_rule(spec).unwrap_err();
+            assert!(e.contains(error), "{:?} gave {:?}, expected {:?}", spec, e, error);
+        }
+    }
+
+    #[test]
+    fn rejects_rules_that_cant_be_met() {
+        for (spec, error) in [
+            ("threshold:1", "quorum fraction 1 should be in [0, 1)"),
+            ("threshold:-0.1", "should be in [0, 1)"),
+            ("count:0", "a quorum of 0 voters can't be met by 10 nodes"),
+            ("count:11", "a quorum of 11 voters can't be met by 10 nodes"),
+            ("regions:0:0", "can't split 10 nodes into 0 regions"),
+            ("regions:11:1", "can't split 10 nodes into 11 regions"),
+            ("regions:3:4", "can't require 4 of 3 regions"),
+            ("majority+count:20", "a quorum of 20 voters"),
+        ] {
+            let e = parse_quorum_rule(spec).unwrap().validate(10).unwrap_err();
+            assert!(e.contains(error), "{:?} gave {:?}, expected {:?}", spec, e, error);
+        }
+        // The largest quorums that 10 nodes can meet, and a threshold that any voter meets.
+        for spec in ["threshold:0.99", "threshold:0", "count:10", "regions:10:10", "regions:3:0"] {
+            assert_eq!(parse_quorum_rule(spec).unwrap().validate(10), Ok(()), "{:?}", spec);
+        }
+        assert_eq!(parse_quorum_rule("threshold:0").unwrap().min_voters(10), Some(1));
+        assert_eq!(parse_quorum_rule("threshold:0.99").unwrap().min_voters(10), Some(10));
+    }
+
+    #[test]
+    fn counts_voters() {
+        let rule = parse_quorum_rule("majority").unwrap();
+        assert!(!rule.has_quorum(&voters(0..5), 10));
+        assert!(rule.has_quorum(&voters(0..6), 10));
+        assert_eq!(rule.min_voters(10), Some(6));
+
+        let rule = parse_quorum_rule("threshold:0.6").unwrap();
+        assert!(!rule.has_quorum(&voters(0..6), 10));
+        assert!(rule.has_quorum(&voters(0..7), 10));
+        assert_eq!(rule.min_voters(10), Some(7));
+
+        let rule = parse_quorum_rule("count:3+threshold:0.25").unwrap();
+        assert!(rule.validate(10).is_ok());
+        assert!(!rule.has_quorum(&voters(0..2), 4));
+        assert!(rule.has_quorum(&voters(0..3), 10));
+        assert_eq!(rule.min_voters(10), Some(3));
+        assert_eq!(rule.min_voters(20), Some(6));
+    }
+
+    #[test]
+    fn regions_need_a_majority_in_enough_of_them() {
+        // Region 0 has nodes 0, 3, 6 and 9, region 1 has 1, 4 and 7, and region 2 has 2, 5 and 8.
+        let rule = parse_quorum_rule("regions:3:2").unwrap();
+        assert!(rule.validate(10).is_ok());
+        assert!(!rule.has_quorum(&voters([0, 3, 6]), 10));
+        assert!(rule.has_quorum(&voters([0, 3, 6, 1, 4]), 10));
+        assert_eq!(rule.min_voters(10), None);
+    }
+
+    #[test]
+    fn weighs_voters() {
+        let path = std::env::temp_dir().join(format!("gossip-weights-{}.txt", std::process::id()));
+        fs::write(&path, "1\n2\n\n3\n4\n").unwrap();
+        let rule = parse_quorum_rule(&format!("weighted:0.5:{}", path.display())).unwrap();
+        fs::write(&path, "1\n-1\n").unwrap();
+        let negative = parse_quorum_rule(&format!("weighted:0.5:{}", path.display())).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(negative.validate(2), Err("weights can't be negative".to_string()));
+
+        assert!(rule.validate(4).is_ok());
+        assert!(rule.validate(5).is_err());
+        // Nodes 0 and 2 have 4 of the 10, node 3 alone 4, and with node 1 it has 6.
+        assert!(!rule.has_quorum(&voters([0, 2]), 4));
+        assert!(!rule.has_quorum(&voters([3]), 4));
+        assert!(rule.has_quorum(&voters([1, 3]), 4));
+        assert!(rule.has_quorum(&voters([0, 1, 2]), 4));
+        assert_eq!(rule.min_voters(4), None);
+    }
+}
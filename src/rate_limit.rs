@@ -0,0 +1,306 @@
+//! Per-node limits on the messages sent and received in each round.
+//!
+//! An exchange is a request from the node that starts it, carrying the node's votes, and a reply
+//! from its partner with the partner's. With `max_messages_per_round` set, every node sends (or
+//! receives, or both, as `rate_limit` says) at most that many messages a round, taking the
+//! round's exchanges in a random order so that no node gets to go first every round.
+//!
+//! A message over its sender's limit isn't sent, and one over its receiver's limit is sent but
+//! refused, like a lost one. A partner that doesn't get a request doesn't reply, so the whole
+//! exchange fails. Under the `defer` policy, a node whose request didn't get through retries the
+//! same partner the next round rather than choosing a new one as under `drop`.
+
+use rand::Rng;
+
+use crate::{NodeId, Params};
+
+/// Which of each node's messages `max_messages_per_round` limits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RateLimit {
+    /// The messages it receives.
+    Inbound,
+    /// The messages it sends.
+    Outbound,
+    /// Both, each up to the limit.
+    #[default]
+    Both,
+}
+
+impl RateLimit {
+    /// The name of the limit, as parsed by `from_str`.
+    pub fn name(self) -> &'static str {
+        match self {
+            RateLimit::Inbound => "inbound",
+            RateLimit::Outbound => "outbound",
+            RateLimit::Both => "both",
+        }
+    }
+}
+
+impl std::str::FromStr for RateLimit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "inbound" => Ok(RateLimit::Inbound),
+            "outbound" => Ok(RateLimit::Outbound),
+            "both" => Ok(RateLimit::Both),
+            _ => Err(format!("unknown rate limit {:?}, expected inbound, outbound or both", s)),
+        }
+    }
+}
+
+/// What happens to an exchange that a rate limit stops.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RateLimitPolicy {
+    /// The node gives up on it, and chooses a partner as usual the next round.
+    #[default]
+    Drop,
+    /// The node tries the same partner again the next round.
+    Defer,
+}
+
+impl RateLimitPolicy {
+    /// The name of the policy, as parsed by `from_str`.
+    pub fn name(self) -> &'static str {
+        match self {
+            RateLimitPolicy::Drop => "drop",
+            RateLimitPolicy::Defer => "defer",
+        }
+    }
+}
+
+impl std::str::FromStr for RateLimitPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "drop" => Ok(RateLimitPolicy::Drop),
+            "defer" => Ok(RateLimitPolicy::Defer),
+            _ => Err(format!("unknown rate limit policy {:?}, expected drop or defer", s)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Fate {
+    Delivered,
+    /// Sent, but over the receiver's limit.
+    Refused,
+    /// Over the sender's limit, or the reply to a request that didn't get through.
+    Unsent,
+}
+
+/// Applies the rate limit of a run to each of its rounds, reusing its buffers between them.
+pub struct RateLimiter {
+    max_messages: usize,
+    limit: RateLimit,
+    policy: RateLimitPolicy,
+    order: Vec<usize>,
+    sent: Vec<usize>,
+    received: Vec<usize>,
+    /// The partner that each node's request didn't get through to last round, under `defer`.
+    deferred: Vec<Option<NodeId>>,
+    /// Messages that the limit has stopped so far, whether unsent or refused.
+    pub num_limited: usize,
+}
+
+impl RateLimiter {
+    /// A limiter for the rounds of `params`, if they have a rate limit.
+    pub fn new(params: &Params) -> Option<Self> {
+        Some(RateLimiter {
+            max_messages: params.max_messages_per_round?,
+            limit: params.rate_limit.unwrap_or_default(),
+            policy: params.rate_limit_policy.unwrap_or_default(),
+            order: Vec::with_capacity(params.n),
+            sent: vec![0; params.n],
+            received: vec![0; params.n],
+            deferred: vec![None; params.n],
+            num_limited: 0,
+        })
+    }
+
+    /// Have every node whose exchange was deferred last round retry the same partner, in place of
+    /// the one in `partners`.
+    pub fn retry_deferred(&self, partners: &mut [NodeId]) {
+        for (partner, deferred) in partners.iter_mut().zip(&self.deferred) {
+            if let Some(deferred) = *deferred {
+                *partner = deferred;
+            }
+        }
+    }
+
+    /// Decide which of the round's messages the limit stops, given every node's partner.
+    ///
+    /// Messages that receivers refuse are marked in `lost`, as `choose_losses` marks lost ones,
+    /// and those that aren't sent at all in `unsent`, both in the order that `is_lost` looks them
+    /// up.
+    pub fn limit<R: Rng>(
+        &mut self,
+        partners: &[NodeId],
+        rng: &mut R,
+        lost: &mut Vec<bool>,
+        unsent: &mut Vec<bool>,
+    ) {
+        let n = partners.len();
+        self.sent.fill(0);
+        self.received.fill(0);
+        lost.resize(2 * n, false);
+        unsent.resize(2 * n, false);
+        self.order.clear();
+        self.order.extend(0..n);
+        rng.shuffle(&mut self.order);
+
+        for i in 0..n {
+            let node = self.order[i];
+            let partner = partners[node] as usize;
+            let request = self.admit(node, partner);
+            let reply = match request {
+                Fate::Delivered => self.admit(partner, node),
+                _ => Fate::Unsent,
+            };
+            // Direction 1 is the node's votes to its partner, and 0 the partner's reply.
+            for (direction, fate) in [(1, request), (0, reply)] {
+                match fate {
+                    Fate::Delivered => continue,
+                    Fate::Refused => lost[2 * node + direction] = true,
+                    Fate::Unsent => unsent[2 * node + direction] = true,
+                }
+                self.num_limited += 1;
+            }
+            let retry = request != Fate::Delivered && self.policy == RateLimitPolicy::Defer;
+            self.deferred[node] = retry.then_some(partners[node]);
+        }
+    }
+
+    /// Count a message from `sender` to `receiver` against their limits, if it gets through them.
+    fn admit(&mut self, sender: usize, receiver: usize) -> Fate {
+        if self.limit != RateLimit::Inbound && self.sent[sender] >= self.max_messages {
+            return Fate::Unsent;
+        }
+        self.sent[sender] += 1;
+        if self.limit != RateLimit::Outbound && self.received[receiver] >= self.max_messages {
+            return Fate::Refused;
+        }
+        self.received[receiver] += 1;
+        Fate::Delivered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::seeded_rng;
+    use serde_json::json;
+
+    /// Every node but the first starts an exchange with the first, which starts one with the
+    /// second, so the first node has many more messages than the rest.
+    const STAR: [NodeId; 6] = [1, 0, 0, 0, 0, 0];
+
+    fn limiter(max_messages: usize, limit: &str, policy: &str) -> RateLimiter {
+        let params: Params = serde_json::from_value(json!({
+            "n": STAR.len(),
+            "k": 1,
+            "voting_steps": 1,
+            "max_messages_per_round": max_messages,
+            "rate_limit": limit,
+            "rate_limit_policy": policy,
+        }))
+        .unwrap();
+        RateLimiter::new(&params).unwrap()
+    }
+
+    /// The messages each node sent and received in a round limited by `limiter`, with the
+    /// requests and replies that didn't get through.
+    fn limit(limiter: &mut RateLimiter, seed: u64) -> (Vec<usize>, Vec<usize>, Vec<bool>, Vec<bool>)
+    {
+        let (mut lost, mut unsent) = (vec![], vec![]);
+        limiter.limit(&STAR, &mut seeded_rng(seed), &mut lost, &mut unsent);
+        let (mut sent, mut received) = (vec![0; STAR.len()], vec![0; STAR.len()]);
+        for (node, &partner) in STAR.iter().enumerate() {
+            let partner = partner as usize;
+            for (direction, sender, receiver) in [(1, node, partner), (0, partner, node)] {
+                let i = 2 * node + direction;
+                sent[sender] += usize::from(!unsent[i]);
+                received[receiver] += usize::from(!unsent[i] && !lost[i]);
+            }
+        }
+        (sent, received, lost, unsent)
+    }
+
+    #[test]
+    fn limits_the_messages_received() {
+        for seed in 0..20 {
+            let mut limiter = limiter(2, "inbound", "drop");
+            let (sent, received, lost, unsent) = limit(&mut limiter, seed);
+            assert!(received.iter().all(|&received| received <= 2), "{:?}", received);
+            assert_eq!(received[0], 2);
+            // Senders aren't limited, so only the replies to refused requests go unsent.
+            for node in 0..STAR.len() {
+                assert_eq!(unsent[2 * node], lost[2 * node + 1]);
+                assert!(!unsent[2 * node + 1]);
+            }
+            // The first node sends its own request, and replies to each request it takes.
+            let taken = (1..STAR.len()).filter(|&node| !lost[2 * node + 1]).count();
+            assert!(taken <= 2);
+            assert_eq!(sent[0], 1 + taken);
+            let stopped = lost.iter().chain(&unsent).filter(|&&stopped| stopped).count();
+            assert_eq!(limiter.num_limited, stopped);
+        }
+    }
+
+    #[test]
+    fn limits_the_messages_sent() {
+        for seed in 0..20 {
+            let mut limiter = limiter(2, "outbound", "drop");
+            let (sent, received, lost, _) = limit(&mut limiter, seed);
+            assert!(sent.iter().all(|&sent| sent <= 2), "{:?}", sent);
+            assert_eq!(sent[0], 2);
+            // Receivers aren't limited, so nothing is refused.
+            assert!(lost.iter().all(|&lost| !lost));
+            assert!(received[0] > 2);
+        }
+    }
+
+    #[test]
+    fn limits_both_ways() {
+        for seed in 0..20 {
+            let mut limiter = limiter(2, "both", "drop");
+            let (sent, received, _, _) = limit(&mut limiter, seed);
+            assert!(sent.iter().all(|&sent| sent <= 2), "{:?}", sent);
+            assert!(received.iter().all(|&received| received <= 2), "{:?}", received);
+        }
+        let mut limiter = limiter(STAR.len(), "both", "drop");
+        let (_, _, lost, unsent) = limit(&mut limiter, 0);
+        assert!(lost.iter().chain(&unsent).all(|&stopped| !stopped));
+        assert_eq!(limiter.num_limited, 0);
+    }
+
+    #[test]
+    fn defer_retries_the_same_partner() {
+        let mut limiter = limiter(2, "inbound", "defer");
+        let (_, _, lost, unsent) = limit(&mut limiter, 1);
+        let mut partners: Vec<NodeId> = vec![2, 3, 4, 5, 1, 1];
+        limiter.retry_deferred(&mut partners);
+        for node in 0..STAR.len() {
+            let request_failed = lost[2 * node + 1] || unsent[2 * node + 1];
+            let expected = if request_failed { STAR[node] } else { [2, 3, 4, 5, 1, 1][node] };
+            assert_eq!(partners[node], expected, "node {}", node);
+        }
+        assert!(partners.iter().filter(|&&partner| partner == 0).count() >= 3);
+
+        // A round in which every request gets through clears the retries.
+        limiter.max_messages = STAR.len();
+        limit(&mut limiter, 2);
+        let mut partners: Vec<NodeId> = vec![2, 3, 4, 5, 1, 1];
+        limiter.retry_deferred(&mut partners);
+        assert_eq!(partners, vec![2, 3, 4, 5, 1, 1]);
+
+        let mut limiter = self::limiter(2, "inbound", "drop");
+        limit(&mut limiter, 1);
+        limiter.retry_deferred(&mut partners);
+        assert_eq!(partners, vec![2, 3, 4, 5, 1, 1]);
+    }
+}
@@ -0,0 +1,99 @@
+//! Gossip strategies defined by a Rhai script.
+//!
+//! A script may define either or both of the following functions, falling back to the
+//! default behaviour for any that are missing:
+//!
+//! ```text
+//! // Return the ID of the node that `id` should gossip with this round.
+//! fn choose_partner(id, n, round) { ... }
+//!
+//! // Return true if `num_voters` new voters for `vote_id` should be sent sender => receiver.
+//! fn filter_diff(vote_id, sender, receiver, num_voters) { ... }
+//! ```
+//!
+//! Scripts can draw random numbers with `rand_int(lo, hi)`, which returns a value in `[lo, hi)`
+//! and fails the run if `lo >= hi`.
+
+use rand::{Rng, XorShiftRng};
+use rhai::{Engine, EvalAltResult, Scope, AST};
+use std::cell::RefCell;
+use std::error::Error;
+use std::rc::Rc;
+
+use crate::voters::Difference;
+use crate::{choose_partner, Node, NodeId, Strategy, VoteId};
+
+pub struct ScriptStrategy {
+    engine: Engine,
+    ast: AST,
+    has_choose_partner: bool,
+    has_filter_diff: bool,
+}
+
+impl ScriptStrategy {
+    /// Compile the script at `path`, seeding its random number generator from `rng`.
+    pub fn load<R: Rng>(path: &str, rng: &mut R) -> Result<Self, Box<dyn Error>> {
+        let mut engine = Engine::new();
+
+        let script_rng: Rc<RefCell<XorShiftRng>> = Rc::new(RefCell::new(rng.gen()));
+        let rand_int = move |lo: i64, hi: i64| -> Result<i64, Box<EvalAltResult>> {
+            if lo >= hi {
+                return Err(format!("rand_int({}, {}) has an empty range", lo, hi).into());
+            }
+            Ok(script_rng.borrow_mut().gen_range(lo, hi))
+        };
+        engine.register_fn("rand_int", rand_int);
+
+        let ast = engine
+            .compile_file(path.into())
+            .map_err(|e| format!("failed to load strategy script {}: {}", path, e))?;
+
+        let defines = |name: &str| ast.iter_functions().any(|f| f.name == name);
+        let has_choose_partner = defines("choose_partner");
+        let has_filter_diff = defines("filter_diff");
+
+        Ok(ScriptStrategy {
+            engine,
+            ast,
+            has_choose_partner,
+            has_filter_diff,
+        })
+    }
+}
+
+impl<R: Rng> Strategy<R> for ScriptStrategy {
+    fn choose_partner(&self, our_id: NodeId, n: usize, round: usize, rng: &mut R)
+        -> Result<NodeId, Box<dyn Error>>
+    {
+        if !self.has_choose_partner {
+            return Ok(choose_partner(our_id, n, rng));
+        }
+        let partner: i64 = self.engine.call_fn(
+            &mut Scope::new(),
+            &self.ast,
+            "choose_partner",
+            (our_id as i64, n as i64, round as i64),
+        )?;
+        NodeId::try_from(partner)
+            .map_err(|_| From::from(format!("script chose invalid partner ID {}", partner)))
+    }
+
+    fn include_in_diff(
+        &self,
+        vote_id: VoteId,
+        sender: &Node,
+        receiver: &Node,
+        new_voters: &Difference,
+    ) -> Result<bool, Box<dyn Error>> {
+        if !self.has_filter_diff {
+            return Ok(true);
+        }
+        let include = self.engine.call_fn(
+            &mut Scope::new(),
+            &self.ast,
+            "filter_diff",
+            (vote_id as i64, sender.id as i64, receiver.id as i64, new_voters.len() as i64),
+        )?;
+        Ok(include)
+    }
+}
@@ -0,0 +1,436 @@
+//! Significance tests for comparing the runs of two sets of results, and confidence intervals and
+//! distributions fitted to the measurements of one.
+
+/// Sample sizes up to which the p-value of a test without ties is computed exactly, rather than
+/// with the normal approximation.
+const EXACT_MAX_RUNS: usize = 30;
+
+/// The outcome of a Mann–Whitney U test of whether the values of `b` tend to differ from `a`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MannWhitney {
+    /// The number of pairs of a value from each in which `b`'s is larger, counting ties as half.
+    pub u: f64,
+    /// The two-sided p-value, the chance of a `u` at least this far from even if `a` and `b`
+    /// come from the same distribution.
+    pub p_value: f64,
+    /// The rank-biserial correlation, from -1 if every value of `b` is below every value of `a`,
+    /// through 0 for no difference, to 1 if every value of `b` is above.
+    pub effect_size: f64,
+}
+
+/// Test whether `a` and `b`, which must both be non-empty, come from the same distribution.
+pub fn mann_whitney(a: &[f64], b: &[f64]) -> MannWhitney {
+    let (m, n) = (a.len() as f64, b.len() as f64);
+    let mut values: Vec<(f64, bool)> =
+        a.iter().map(|&x| (x, false)).chain(b.iter().map(|&x| (x, true))).collect();
+    values.sort_by(|x, y| x.0.total_cmp(&y.0));
+
+    // Tied values share the average of their ranks, counting from 1.
+    let mut b_rank_sum = 0.0;
+    let mut tie_correction = 0.0;
+    let mut start = 0;
+    while start < values.len() {
+        let end = start + values[start..].iter().take_while(|x| x.0 == values[start].0).count();
+        let rank = (start + end + 1) as f64 / 2.0;
+        b_rank_sum += rank * values[start..end].iter().filter(|x| x.1).count() as f64;
+        let ties = (end - start) as f64;
+        tie_correction += ties * ties * ties - ties;
+        start = end;
+    }
+
+    let u = b_rank_sum - n * (n + 1.0) / 2.0;
+    let p_value = if tie_correction == 0.0 && a.len().max(b.len()) <= EXACT_MAX_RUNS {
+        exact_p_value(a.len(), b.len(), u as usize)
+    } else {
+        let total = m + n;
+        let variance = m * n / 12.0 * (total + 1.0 - tie_correction / (total * (total - 1.0)));
+        match variance > 0.0 {
+            true => {
+                let z = ((u - m * n / 2.0).abs() - 0.5).max(0.0) / variance.sqrt();
+                erfc(z / std::f64::consts::SQRT_2)
+            }
+            // Every value is the same.
+            false => 1.0,
+        }
+    };
+    MannWhitney { u, p_value: p_value.min(1.0), effect_size: 2.0 * u / (m * n) - 1.0 }
+}
+
+/// The two-sided p-value of `u` from samples of `m` and `n` distinct values, from the number of
+/// orderings of the values giving each `u`.
+fn exact_p_value(m: usize, n: usize, u: usize) -> f64 {
+    // `counts[i][j][u]` orderings of `i` values of one and `j` of the other have `u` pairs in
+    // which the second's value is larger, with the largest value coming from either.
+    let mut counts = vec![vec![vec![]; n + 1]; m + 1];
+    for i in 0..=m {
+        for j in 0..=n {
+            let mut ways = vec![0.0; i * j + 1];
+            if i == 0 || j == 0 {
+                ways[0] = 1.0;
+            } else {
+                for (u, ways) in ways.iter_mut().enumerate() {
+                    let first_largest = counts[i - 1][j].get(u).copied().unwrap_or(0.0);
+                    let second_largest =
+                        u.checked_sub(i).and_then(|u| counts[i][j - 1].get(u)).copied();
+                    *ways = first_largest + second_largest.unwrap_or(0.0);
+                }
+            }
+            counts[i][j] = ways;
+        }
+    }
+    let ways = &counts[m][n];
+    let total: f64 = ways.iter().sum();
+    let below: f64 = ways[..=u].iter().sum();
+    let above: f64 = ways[u..].iter().sum();
+    2.0 * below.min(above) / total
+}
+
+/// The complementary error function, to within 1.2e-7.
+fn erfc(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.5 * x.abs());
+    let coefficients = [
+        -1.26551223, 1.00002368, 0.37409196, 0.09678418, -0.18628806, 0.27886807, -1.13520398,
+        1.48851587, -0.82215223, 0.17087277,
+    ];
+    let series = coefficients.iter().rev().fold(0.0, |sum, c| c + t * sum);
+    let y = t * (-x * x + series).exp();
+    if x >= 0.0 {
+        y
+    } else {
+        2.0 - y
+    }
+}
+
+/// The number of standard deviations either side of the mean that 95% of a normal distribution
+/// falls within.
+pub const Z_95: f64 = 1.959964;
+
+/// The Wilson score interval for a probability, from `successes` of `trials` and the `z` of the
+/// confidence wanted, such as `Z_95`. Unlike the normal approximation it stays within 0 and 1,
+/// even for probabilities near them.
+pub fn wilson_interval(successes: usize, trials: usize, z: f64) -> (f64, f64) {
+    if trials == 0 {
+        return (0.0, 1.0);
+    }
+    let n = trials as f64;
+    let p = successes as f64 / n;
+    let centre = p + z * z / (2.0 * n);
+    let spread = z * (p * (1.0 - p) / n + z * z / (4.0 * n * n)).sqrt();
+    let scale = 1.0 + z * z / n;
+    (((centre - spread) / scale).max(0.0), ((centre + spread) / scale).min(1.0))
+}
+
+/// The mean of `values` and the normal approximation of the interval around it for the `z` of
+/// the confidence wanted.
+pub fn mean_interval(values: &[f64], z: f64) -> (f64, f64, f64) {
+    let count = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / count;
+    if values.len() < 2 {
+        return (mean, mean, mean);
+    }
+    let variance = values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (count - 1.0);
+    let spread = z * (variance / count).sqrt();
+    (mean, mean - spread, mean + spread)
+}
+
+/// The mean, variance and skewness of a sample.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Moments {
+    pub mean: f64,
+    /// The sample variance, dividing by one less than the number of values.
+    pub variance: f64,
+    /// The adjusted Fisher–Pearson skewness, positive when the values have a long upper tail.
+    pub skewness: f64,
+}
+
+/// The moments of `values`, of which there must be at least 3.
+pub fn moments(values: &[f64]) -> Moments {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let central = |power: i32| values.iter().map(|x| (x - mean).powi(power)).sum::<f64>() / n;
+    let (m2, m3) = (central(2), central(3));
+    let skewness = match m2 > 0.0 {
+        true => m3 / m2.powf(1.5) * (n * (n - 1.0)).sqrt() / (n - 2.0),
+        false => 0.0,
+    };
+    Moments { mean, variance: m2 * n / (n - 1.0), skewness }
+}
+
+/// Distributions that `fit` can fit to a sample.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Distribution {
+    Normal,
+    /// A normal distribution of the logarithms of the values, which must all be positive.
+    LogNormal,
+    /// The distribution of the largest of many values, such as the last node to converge.
+    Gumbel,
+}
+
+impl Distribution {
+    pub const ALL: &'static [Distribution] =
+        &[Distribution::Normal, Distribution::LogNormal, Distribution::Gumbel];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Distribution::Normal => "normal",
+            Distribution::LogNormal => "lognormal",
+            Distribution::Gumbel => "gumbel",
+        }
+    }
+}
+
+/// Euler's constant, the mean of the standard Gumbel distribution.
+const EULER_GAMMA: f64 = 0.577_215_664_901_532_9;
+
+/// A distribution fitted to a sample.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fit {
+    pub distribution: Distribution,
+    /// The mean and standard deviation for a normal distribution (of the logarithms, for a
+    /// log-normal one), or the mode and scale of a Gumbel distribution.
+    pub location: f64,
+    pub scale: f64,
+    /// The Kolmogorov–Smirnov statistic, the largest gap between the fitted distribution and the
+    /// sample's, from 0 for a perfect fit to 1.
+    pub ks_statistic: f64,
+}
+
+impl Fit {
+    /// The chance of a value at most `x`.
+    pub fn cdf(&self, x: f64) -> f64 {
+        match self.distribution {
+            Distribution::Normal => normal_cdf((x - self.location) / self.scale),
+            Distribution::LogNormal if x <= 0.0 => 0.0,
+            Distribution::LogNormal => normal_cdf((x.ln() - self.location) / self.scale),
+            Distribution::Gumbel => (-(-(x - self.location) / self.scale).exp()).exp(),
+        }
+    }
+
+    /// The value that a fraction `p` of the distribution is at most, for `p` between 0 and 1.
+    pub fn quantile(&self, p: f64) -> f64 {
+        match self.distribution {
+            Distribution::Normal => self.location + self.scale * inverse_normal_cdf(p),
+            Distribution::LogNormal => (self.location + self.scale * inverse_normal_cdf(p)).exp(),
+            Distribution::Gumbel => self.location - self.scale * (-p.ln()).ln(),
+        }
+    }
+}
+
+/// Fit `distribution` to `values` by the method of moments, unless it can't describe them: when
+/// they're all the same, or some aren't positive for a log-normal distribution.
+pub fn fit(distribution: Distribution, values: &[f64]) -> Option<Fit> {
+    let n = values.len() as f64;
+    // Rounding can leave a little spread in the logs of identical values, so rule them out first.
+    if values.len() < 2 || values.iter().all(|&x| x == values[0]) {
+        return None;
+    }
+    let (location, scale) = match distribution {
+        Distribution::LogNormal => {
+            if values.iter().any(|&x| x <= 0.0) {
+                return None;
+            }
+            let logs: Vec<f64> = values.iter().map(|x| x.ln()).collect();
+            let mean = logs.iter().sum::<f64>() / n;
+            (mean, (logs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt())
+        }
+        _ => {
+            let mean = values.iter().sum::<f64>() / n;
+            let sd = (values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt();
+            match distribution {
+                Distribution::Gumbel => {
+                    let scale = sd * 6f64.sqrt() / std::f64::consts::PI;
+                    (mean - EULER_GAMMA * scale, scale)
+                }
+                _ => (mean, sd),
+            }
+        }
+    };
+    if scale.is_nan() || scale <= 0.0 {
+        return None;
+    }
+
+    let mut fit = Fit { distribution, location, scale, ks_statistic: 0.0 };
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    // The sample's distribution jumps at each value, so compare either side of the jump.
+    for (i, &x) in sorted.iter().enumerate() {
+        let cdf = fit.cdf(x);
+        let gap = (cdf - i as f64 / n).max((i + 1) as f64 / n - cdf);
+        fit.ks_statistic = fit.ks_statistic.max(gap);
+    }
+    Some(fit)
+}
+
+/// Every distribution in `Distribution::ALL` that can be fitted to `values`, fitted to them.
+pub fn fit_all(values: &[f64]) -> Vec<Fit> {
+    Distribution::ALL.iter().filter_map(|&distribution| fit(distribution, values)).collect()
+}
+
+/// The fit in `fits` with the smallest Kolmogorov–Smirnov statistic, if there are any.
+pub fn best_fit(fits: &[Fit]) -> Option<&Fit> {
+    fits.iter().min_by(|a, b| a.ks_statistic.total_cmp(&b.ks_statistic))
+}
+
+/// The chance that a standard normal variable is at most `z`.
+fn normal_cdf(z: f64) -> f64 {
+    erfc(-z / std::f64::consts::SQRT_2) / 2.0
+}
+
+/// The inverse of `normal_cdf`, to a relative error of about 1e-9, by Acklam's rational
+/// approximation.
+fn inverse_normal_cdf(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969683028665376e1, 2.209460984245205e2, -2.759285104469687e2,
+        1.38357751867269e2, -3.066479806614716e1, 2.506628277459239,
+    ];
+    const B: [f64; 5] = [
+        -5.447609879822406e1, 1.615858368580409e2, -1.556989798598866e2,
+        6.680131188771972e1, -1.328068155288572e1,
+    ];
+    const C: [f64; 6] = [
+        -7.784894002430293e-3, -3.223964580411365e-1, -2.400758277161838,
+        -2.549732539343734, 4.374664141464968, 2.938163982698783,
+    ];
+    const D: [f64; 4] =
+        [7.784695709041462e-3, 3.224671290700398e-1, 2.445134137142996, 3.754408661907416];
+    let polynomial = |coefficients: &[f64], x: f64| {
+        coefficients.iter().fold(0.0, |sum, c| sum * x + c)
+    };
+
+    const LOW: f64 = 0.02425;
+    if p < LOW {
+        let q = (-2.0 * p.ln()).sqrt();
+        polynomial(&C, q) / (polynomial(&D, q) * q + 1.0)
+    } else if p <= 1.0 - LOW {
+        let q = p - 0.5;
+        let r = q * q;
+        polynomial(&A, r) * q / (polynomial(&B, r) * r + 1.0)
+    } else {
+        -inverse_normal_cdf(1.0 - p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(x: f64, expected: f64, tolerance: f64) {
+        let message = format!("{} isn't within {} of {}", x, tolerance, expected);
+        assert!((x - expected).abs() <= tolerance, "{}", message);
+    }
+
+    #[test]
+    fn exact_mann_whitney_p_values() {
+        // Two-sided p-values from the tables of the exact distribution of U.
+        for (a, b, u, p_value) in [
+            (&[1.0, 2.0, 3.0][..], &[4.0, 5.0, 6.0][..], 9.0, 2.0 / 20.0),
+            (&[1.0, 2.0, 4.0], &[3.0, 5.0, 6.0], 8.0, 4.0 / 20.0),
+            (&[1.0, 2.0, 3.0, 4.0, 5.0], &[6.0, 7.0, 8.0, 9.0, 10.0], 25.0, 2.0 / 252.0),
+            (&[6.0, 7.0, 8.0, 9.0, 10.0], &[1.0, 2.0, 3.0, 4.0, 5.0], 0.0, 2.0 / 252.0),
+            (&[1.0, 4.0], &[2.0, 3.0], 2.0, 1.0),
+        ] {
+            let test = mann_whitney(a, b);
+            assert_eq!(test.u, u, "{:?} {:?}", a, b);
+            assert_close(test.p_value, p_value, 1e-12);
+        }
+
+        let test = mann_whitney(&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0]);
+        assert_eq!(test.effect_size, 1.0);
+        assert_eq!(mann_whitney(&[4.0, 5.0, 6.0], &[1.0, 2.0, 3.0]).effect_size, -1.0);
+    }
+
+    #[test]
+    fn normal_mann_whitney_p_values() {
+        // Ties, with the tie correction and continuity correction: z = 1.7186.
+        let test = mann_whitney(&[1.0, 2.0, 2.0, 3.0, 3.0], &[2.0, 3.0, 4.0, 4.0, 5.0]);
+        assert_eq!(test.u, 21.0);
+        assert_close(test.p_value, 0.085673, 1e-6);
+
+        // Too many runs to count the orderings of: z = 3.5059.
+        let a: Vec<f64> = (0..40).map(|i| 2.0 * i as f64).collect();
+        let b: Vec<f64> = (0..40).map(|i| 2.0 * i as f64 + 21.0).collect();
+        let test = mann_whitney(&a, &b);
+        assert_eq!(test.u, 1165.0);
+        assert_close(test.p_value, 0.00045250, 1e-7);
+
+        let test = mann_whitney(&[3.0, 3.0], &[3.0, 3.0, 3.0]);
+        assert_eq!((test.u, test.p_value, test.effect_size), (3.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn erfc_matches_known_values() {
+        let known = [(0.0, 1.0), (0.5, 0.479500122), (1.0, 0.157299207), (2.0, 0.004677735)];
+        for (x, expected) in known {
+            assert_close(erfc(x), expected, 1.2e-7);
+            assert_close(erfc(-x), 2.0 - expected, 1.2e-7);
+        }
+    }
+
+    #[test]
+    fn moments_of_a_known_sample() {
+        let moments = moments(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]);
+        assert_eq!(moments.mean, 5.0);
+        assert_close(moments.variance, 32.0 / 7.0, 1e-12);
+        // The central moments are 4 and 5.25, adjusted for a sample of 8.
+        assert_close(moments.skewness, 5.25 / 8.0 * 56f64.sqrt() / 6.0, 1e-12);
+
+        let symmetric = super::moments(&[1.0, 2.0, 3.0, 4.0, 5.0]);
+        assert_eq!((symmetric.mean, symmetric.variance, symmetric.skewness), (3.0, 2.5, 0.0));
+        assert_eq!(super::moments(&[7.0, 7.0, 7.0]).skewness, 0.0);
+    }
+
+    #[test]
+    fn inverse_normal_cdf_matches_known_values() {
+        for (p, z) in [(0.5, 0.0), (0.975, Z_95), (0.01, -2.326348), (0.999, 3.090232)] {
+            assert_close(inverse_normal_cdf(p), z, 1e-6);
+            assert_close(normal_cdf(inverse_normal_cdf(p)), p, 1e-7);
+        }
+    }
+
+    /// `n` evenly spread quantiles of `fit`, a sample with exactly its shape.
+    fn sample(fit: Fit, n: usize) -> Vec<f64> {
+        (0..n).map(|i| fit.quantile((i as f64 + 0.5) / n as f64)).collect()
+    }
+
+    #[test]
+    fn fits_recover_their_parameters_and_quantiles() {
+        for (distribution, location, scale) in [
+            (Distribution::Normal, 40.0, 5.0),
+            (Distribution::LogNormal, 3.0, 0.2),
+            (Distribution::Gumbel, 20.0, 3.0),
+        ] {
+            let shape = Fit { distribution, location, scale, ks_statistic: 0.0 };
+            let fit = fit(distribution, &sample(shape, 1000)).unwrap();
+            assert_close(fit.location, location, 0.01 * location);
+            assert_close(fit.scale, scale, 0.01 * scale);
+            assert!(fit.ks_statistic < 0.01, "{:?}", fit);
+            assert_close(fit.cdf(fit.quantile(0.99)), 0.99, 1e-6);
+        }
+        assert_eq!(fit(Distribution::Normal, &[5.0, 5.0, 5.0]), None);
+        assert_eq!(fit(Distribution::LogNormal, &[0.0, 1.0, 2.0]), None);
+    }
+
+    #[test]
+    fn picks_the_distribution_of_the_sample() {
+        let shape = |distribution, location, scale| Fit {
+            distribution,
+            location,
+            scale,
+            ks_statistic: 0.0,
+        };
+        let normal = shape(Distribution::Normal, 40.0, 5.0);
+        let gumbel = shape(Distribution::Gumbel, 20.0, 3.0);
+        for (shape, rounded) in [(normal, false), (normal, true), (gumbel, false), (gumbel, true)] {
+            let mut values = sample(shape, 200);
+            if rounded {
+                values.iter_mut().for_each(|x| *x = x.round());
+            }
+            let fits = fit_all(&values);
+            assert_eq!(fits.len(), 3);
+            let best = best_fit(&fits).unwrap();
+            let message = format!("rounded: {}, fits: {:?}", rounded, fits);
+            assert_eq!(best.distribution, shape.distribution, "{}", message);
+        }
+        assert_eq!(best_fit(&fit_all(&[5.0, 5.0, 5.0])), None);
+    }
+}
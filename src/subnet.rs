@@ -0,0 +1,270 @@
+//! Subnets that only gossip among themselves, joined up by bridge nodes, as attestations are
+//! gossiped on subnets before aggregators carry them to the rest of the network.
+//!
+//! A row's `subnets` column is `<subnets>:<bridge fraction>`, such as `4:0.1`. Node `i` is in
+//! subnet `i % subnets`, as in the `regions` quorum rule, so every subnet gets its share of the
+//! voters, and that fraction of each subnet's nodes, rounded to whole nodes and taken in an order
+//! shuffled by node ID, are bridges. Other nodes only choose partners in their own subnet, so
+//! the votes cast in a subnet only spread within it until a bridge, which chooses partners from
+//! the whole network, carries every vote it has gathered to another subnet and brings back the
+//! other's.
+
+use rand::Rng;
+use std::error::Error;
+
+use crate::voters::Difference;
+use crate::{choose_partner, splitmix64, Node, NodeId, Params, SimulationResult, Strategy, VoteId};
+
+/// The subnets of a network, and which of its nodes are bridges.
+#[derive(Clone, Debug)]
+pub struct Subnets {
+    pub num_subnets: usize,
+    pub bridges: Vec<bool>,
+}
+
+impl Subnets {
+    /// The subnet that `node` is in.
+    pub fn subnet(&self, node: usize) -> usize {
+        node % self.num_subnets
+    }
+
+    /// The number of nodes in `subnet`.
+    fn size(&self, subnet: usize) -> usize {
+        (self.bridges.len() - subnet).div_ceil(self.num_subnets)
+    }
+
+    /// Whether an exchange between `node` and `partner` crosses from one subnet to another.
+    pub fn crosses(&self, node: usize, partner: usize) -> bool {
+        self.subnet(node) != self.subnet(partner)
+    }
+}
+
+/// Parse a subnets spec for `n` nodes, in the format described in the module docs.
+pub fn parse_subnets(spec: &str, n: usize) -> Result<Subnets, String> {
+    let (num_subnets, fraction) = spec
+        .trim()
+        .split_once(':')
+        .ok_or_else(|| format!("subnets {:?} should be <subnets>:<bridge fraction>", spec))?;
+    let num_subnets: usize = num_subnets
+        .parse()
+        .map_err(|_| format!("invalid number of subnets in {:?}", spec))?;
+    let fraction: f64 = fraction
+        .parse()
+        .map_err(|_| format!("invalid bridge fraction in subnets {:?}", spec))?;
+    if num_subnets == 0 || 2 * num_subnets > n {
+        return Err(format!(
+            "can't split {} nodes into {} subnets of at least 2 nodes",
+            n, num_subnets
+        ));
+    }
+    if !(0.0..=1.0).contains(&fraction) {
+        return Err(format!("bridge fraction {} should be in [0, 1]", fraction));
+    }
+
+    // Shuffled by node ID, so that the same nodes are bridges in every run.
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by_key(|&node| splitmix64(&mut (node as u64)));
+    let mut subnets = Subnets { num_subnets, bridges: vec![false; n] };
+    for subnet in 0..num_subnets {
+        let num_bridges = (fraction * subnets.size(subnet) as f64).round() as usize;
+        if num_bridges == 0 && num_subnets > 1 {
+            return Err(format!(
+                "subnet {} of {} nodes gets no bridges with a bridge fraction of {}, so its \
+                 votes would never leave it",
+                subnet,
+                subnets.size(subnet),
+                fraction
+            ));
+        }
+        let members = order.iter().filter(|&&node| node % num_subnets == subnet);
+        for &node in members.take(num_bridges) {
+            subnets.bridges[node] = true;
+        }
+    }
+    Ok(subnets)
+}
+
+/// The standard protocol with partners restricted to each node's subnet, other than for bridges.
+pub struct SubnetStrategy {
+    subnets: Subnets,
+}
+
+impl SubnetStrategy {
+    pub fn new(subnets: Subnets) -> Self {
+        SubnetStrategy { subnets }
+    }
+}
+
+impl<R: Rng> Strategy<R> for SubnetStrategy {
+    fn choose_partner(&self, our_id: NodeId, n: usize, _round: usize, rng: &mut R)
+        -> Result<NodeId, Box<dyn Error>>
+    {
+        let node = our_id as usize;
+        if self.subnets.bridges[node] {
+            return Ok(choose_partner(our_id, n, rng));
+        }
+        // Choose from the rest of the subnet, whose nodes are `subnet + i * num_subnets`.
+        let num_subnets = self.subnets.num_subnets;
+        let subnet = self.subnets.subnet(node);
+        let index = choose_partner((node / num_subnets) as NodeId, self.subnets.size(subnet), rng);
+        Ok((subnet + index as usize * num_subnets) as NodeId)
+    }
+
+    fn include_in_diff(&self, _: VoteId, _: &Node, _: &Node, _: &Difference)
+        -> Result<bool, Box<dyn Error>>
+    {
+        Ok(true)
+    }
+
+    fn as_sync(&self) -> Option<&(dyn Strategy<R> + Sync)> {
+        Some(self)
+    }
+}
+
+/// The convergence of each subnet of a run, and the exchanges between subnets.
+pub struct SubnetStats {
+    subnets: Subnets,
+    quorum_rounds: Vec<Option<usize>>,
+    num_cross_exchanges: usize,
+}
+
+impl SubnetStats {
+    /// Stats for the subnets of `params`, if they have any.
+    pub fn new(params: &Params) -> Result<Option<Self>, String> {
+        let Some(spec) = &params.subnets else {
+            return Ok(None);
+        };
+        Ok(Some(SubnetStats {
+            subnets: parse_subnets(spec, params.n)?,
+            quorum_rounds: vec![None; params.n],
+            num_cross_exchanges: 0,
+        }))
+    }
+
+    /// Count the exchanges of a round with every node's partner that cross between subnets.
+    pub fn record_partners(&mut self, partners: &[NodeId]) {
+        let crossing = partners
+            .iter()
+            .enumerate()
+            .filter(|&(node, &partner)| self.subnets.crosses(node, partner as usize));
+        self.num_cross_exchanges += crossing.count();
+    }
+
+    /// Record which nodes have a quorum after `rounds` rounds.
+    pub fn record(&mut self, rounds: usize, has_quorum: impl Fn(usize) -> bool) {
+        for (node, round) in self.quorum_rounds.iter_mut().enumerate() {
+            if round.is_none() && has_quorum(node) {
+                *round = Some(rounds);
+            }
+        }
+    }
+
+    /// `result` with the rounds until every node of the fastest and slowest subnets had a
+    /// quorum, and the exchanges between subnets.
+    pub fn apply_to(&self, result: SimulationResult) -> SimulationResult {
+        let mut subnet_rounds = vec![Some(0); self.subnets.num_subnets];
+        for (node, &round) in self.quorum_rounds.iter().enumerate() {
+            let subnet_round = &mut subnet_rounds[self.subnets.subnet(node)];
+            *subnet_round = subnet_round.zip(round).map(|(a, b)| a.max(b));
+        }
+        let subnet_rounds: Option<Vec<usize>> = subnet_rounds.into_iter().collect();
+        let (fastest, slowest) = match subnet_rounds {
+            Some(rounds) => (rounds.iter().min().copied(), rounds.iter().max().copied()),
+            None => (None, None),
+        };
+        SimulationResult {
+            fastest_subnet_rounds: fastest.map(|rounds| rounds as f64),
+            slowest_subnet_rounds: slowest.map(|rounds| rounds as f64),
+            num_cross_subnet_exchanges: self.num_cross_exchanges,
+            ..result
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::seeded_rng;
+
+    fn bridges_per_subnet(subnets: &Subnets) -> Vec<usize> {
+        let mut counts = vec![0; subnets.num_subnets];
+        for node in (0..subnets.bridges.len()).filter(|&node| subnets.bridges[node]) {
+            counts[subnets.subnet(node)] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn rejects_malformed_specs() {
+        for (spec, n, error) in [
+            ("4", 100, "should be <subnets>:<bridge fraction>"),
+            ("-1:0.1", 100, "invalid number of subnets"),
+            ("4:", 100, "invalid bridge fraction"),
+            ("0:0.1", 100, "can't split 100 nodes into 0 subnets"),
+            ("5:0.5", 9, "can't split 9 nodes into 5 subnets of at least 2 nodes"),
+            ("4:1.01", 100, "bridge fraction 1.01 should be in [0, 1]"),
+            ("4:-0.0001", 100, "should be in [0, 1]"),
+            ("4:NaN", 100, "should be in [0, 1]"),
+        ] {
+            let e = parse_subnets(spec, n).unwrap_err();
+            assert!(e.contains(error), "{:?} gave {:?}, expected {:?}", spec, e, error);
+        }
+    }
+
+    #[test]
+    fn accepts_the_ends_of_its_ranges() {
+        // Every node a bridge, which gossips with anyone as if there were no subnets.
+        let subnets = parse_subnets("4:1", 100).unwrap();
+        assert!(subnets.bridges.iter().all(|&bridge| bridge));
+        // Subnets of exactly 2 nodes, one of them a bridge.
+        let subnets = parse_subnets("5:0.5", 10).unwrap();
+        assert_eq!(bridges_per_subnet(&subnets), vec![1; 5]);
+        assert_eq!((0..10).map(|node| subnets.size(subnets.subnet(node))).max(), Some(2));
+    }
+
+    #[test]
+    fn rejects_subnets_without_bridges() {
+        for spec in ["4:0", "4:0.01"] {
+            let e = parse_subnets(spec, 100).unwrap_err();
+            let error = "subnet 0 of 25 nodes gets no bridges";
+            assert!(e.contains(error), "{:?} gave {:?}", spec, e);
+        }
+        // A lone subnet has nowhere for its votes to go anyway.
+        let subnets = parse_subnets("1:0", 10).unwrap();
+        assert!(subnets.bridges.iter().all(|&bridge| !bridge));
+    }
+
+    #[test]
+    fn rounds_each_subnets_bridges() {
+        // Subnets of 26, 26, 26 and 25 nodes, with a quarter of each as bridges.
+        let subnets = parse_subnets("4:0.25", 103).unwrap();
+        assert_eq!(bridges_per_subnet(&subnets), vec![7, 7, 7, 6]);
+        let subnets = parse_subnets("3:1", 10).unwrap();
+        assert_eq!(bridges_per_subnet(&subnets), vec![4, 3, 3]);
+        // The same nodes are bridges every time.
+        let again = parse_subnets("4:0.25", 103).unwrap();
+        assert_eq!(again.bridges, parse_subnets("4:0.25", 103).unwrap().bridges);
+    }
+
+    #[test]
+    fn only_bridges_leave_their_subnet() {
+        let n = 103;
+        let subnets = parse_subnets("4:0.1", n).unwrap();
+        let strategy = SubnetStrategy::new(subnets.clone());
+        let mut rng = seeded_rng(1);
+        let mut bridges_crossed = 0;
+        for _ in 0..20 {
+            for node in 0..n {
+                let partner = strategy.choose_partner(node as NodeId, n, 0, &mut rng).unwrap();
+                let partner = partner as usize;
+                assert!(partner < n && partner != node, "{} chose {}", node, partner);
+                if subnets.bridges[node] {
+                    bridges_crossed += usize::from(subnets.crosses(node, partner));
+                } else {
+                    assert!(!subnets.crosses(node, partner), "{} chose {}", node, partner);
+                }
+            }
+        }
+        assert!(bridges_crossed > 0);
+    }
+}
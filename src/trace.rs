@@ -0,0 +1,260 @@
+//! Traces of a message spreading through a real network, for calibrating the simulation.
+//!
+//! A trace is a CSV with a `node,time` row for every time a node received the message, in any
+//! unit of time and any order. Nodes may be named however the network names them, and may
+//! receive the message many times; the first counts as the time it arrived.
+//!
+//! The simulation runs in rounds rather than time, and every node exchanges with one partner a
+//! round, so a trace is fitted by a loss rate and the length of a round: spreading one vote from
+//! a single voter to every node, as the message spread from the first node to receive it, the
+//! simulated fraction of nodes that have it after each round is compared with the trace's at the
+//! same number of round lengths before or after half the nodes had it. Lining up the middles
+//! leaves out how long the first few nodes took, which varies a lot from run to run.
+//!
+//! Loss slows the spread down much as longer rounds do, so it's only told apart by the shape of
+//! the spread, mostly how quickly the last nodes get the message. Every message of a round
+//! arrives at once, so there's no distribution of latencies to fit, and the number of times each
+//! node received the message is only reported, not fitted.
+
+use std::collections::HashMap;
+use std::error::Error;
+
+use crate::{open_input, Params, MILESTONES};
+
+#[derive(Deserialize)]
+struct Delivery {
+    node: String,
+    time: f64,
+}
+
+/// When a message reached each node of a trace.
+pub struct Trace {
+    /// The time each node first received the message after the first node did, in order.
+    arrivals: Vec<f64>,
+    num_nodes: usize,
+    num_deliveries: usize,
+}
+
+/// How well a round length fits a trace to a simulated spread.
+#[derive(Clone, Copy, Debug)]
+pub struct RoundFit {
+    pub round_duration: f64,
+    /// The mean over the runs of the root mean square difference between the fractions of nodes
+    /// with the message in the trace and the run, over the rounds until both are done.
+    pub error: f64,
+}
+
+/// Candidate round lengths tried for each fit, between a quarter and four times the trace's span
+/// over the mean rounds of the runs.
+const ROUND_DURATIONS: usize = 400;
+
+impl Trace {
+    /// Read a trace from `filename`, of a network of `num_nodes` nodes, or by default just the
+    /// nodes that the trace shows receiving the message.
+    pub fn read(filename: &str, num_nodes: Option<usize>) -> Result<Self, Box<dyn Error>> {
+        let mut reader = csv::Reader::from_reader(open_input(filename)?);
+        let mut first_arrivals: HashMap<String, f64> = HashMap::new();
+        let mut num_deliveries = 0;
+        for (i, delivery) in reader.deserialize().enumerate() {
+            let delivery: Delivery = delivery
+                .map_err(|e| format!("invalid delivery on line {} of {}: {}", i + 2, filename, e))?;
+            if !delivery.time.is_finite() {
+                return Err(From::from(format!("invalid time on line {} of {}", i + 2, filename)));
+            }
+            let arrival = first_arrivals.entry(delivery.node).or_insert(delivery.time);
+            *arrival = arrival.min(delivery.time);
+            num_deliveries += 1;
+        }
+
+        let mut arrivals: Vec<f64> = first_arrivals.into_values().collect();
+        arrivals.sort_by(f64::total_cmp);
+        let num_nodes = num_nodes.unwrap_or(arrivals.len());
+        if arrivals.len() > num_nodes {
+            return Err(From::from(format!(
+                "{} has deliveries to {} nodes, more than the {} in the network",
+                filename,
+                arrivals.len(),
+                num_nodes
+            )));
+        }
+        let start = *arrivals.first().ok_or_else(|| format!("{} has no deliveries", filename))?;
+        if arrivals.last() == Some(&start) {
+            return Err(From::from(format!(
+                "{} needs deliveries at more than one time to fit",
+                filename
+            )));
+        }
+        for arrival in &mut arrivals {
+            *arrival -= start;
+        }
+        Ok(Trace { arrivals, num_nodes, num_deliveries })
+    }
+
+    pub fn num_nodes(&self) -> usize {
+        self.num_nodes
+    }
+
+    /// The mean number of times each node received the message.
+    pub fn deliveries_per_node(&self) -> f64 {
+        self.num_deliveries as f64 / self.num_nodes as f64
+    }
+
+    /// The time from the first delivery until the last node received the message.
+    fn span(&self) -> f64 {
+        self.arrivals[self.arrivals.len() - 1]
+    }
+
+    /// The fraction of the nodes that had the message by `time` after the first delivery.
+    fn informed(&self, time: f64) -> f64 {
+        self.arrivals.partition_point(|&arrival| arrival <= time) as f64 / self.num_nodes as f64
+    }
+
+    /// The time from the first delivery until each of `MILESTONES` percent of the nodes had the
+    /// message, if they ever did.
+    pub fn milestones(&self) -> [Option<f64>; 4] {
+        MILESTONES.map(|percent| {
+            let count = (percent * self.num_nodes).div_ceil(100);
+            self.arrivals.get(count.max(1) - 1).copied()
+        })
+    }
+
+    /// The round length that best fits the trace to `curves`, the simulated fraction of nodes
+    /// with the message after each round of each run, counting from round 0 in which only the
+    /// first has it. Runs are compared with the trace one at a time and their errors averaged, as
+    /// an average of the runs would be flatter in the middle than any of them.
+    pub fn fit(&self, curves: &[Vec<f64>]) -> RoundFit {
+        let mean_rounds = curves.iter().map(|curve| curve.len() - 1).sum::<usize>() as f64
+            / curves.len().max(1) as f64;
+        (0..=ROUND_DURATIONS)
+            .map(|i| {
+                // Spaced evenly on a log scale from a quarter to four times.
+                let scale = 4f64.powf(2.0 * i as f64 / ROUND_DURATIONS as f64 - 1.0);
+                let round_duration = self.span() / mean_rounds.max(1.0) * scale;
+                let errors = curves.iter().map(|curve| self.error(curve, round_duration));
+                let error = errors.sum::<f64>() / curves.len().max(1) as f64;
+                RoundFit { round_duration, error }
+            })
+            .min_by(|a, b| a.error.total_cmp(&b.error))
+            .expect("at least one round length is tried")
+    }
+
+    /// The error of `curve` against the trace with rounds of `round_duration`, lining up the
+    /// times at which half the nodes had the message.
+    fn error(&self, curve: &[f64], round_duration: f64) -> f64 {
+        let (Some(trace_middle), Some(middle)) = (self.milestones()[0], middle_round(curve)) else {
+            return f64::INFINITY;
+        };
+        let time = |round: usize| trace_middle + (round as f64 - middle) * round_duration;
+        let mut rounds = curve.len() - 1;
+        while time(rounds) < self.span() {
+            rounds += 1;
+        }
+        let squares: f64 = (0..=rounds)
+            .map(|round| {
+                let simulated = curve.get(round).or(curve.last()).copied().unwrap_or(1.0);
+                (self.informed(time(round)) - simulated).powi(2)
+            })
+            .sum();
+        (squares / (rounds + 1) as f64).sqrt()
+    }
+}
+
+/// The round, between whole ones, at which half the nodes of `curve` had the message.
+fn middle_round(curve: &[f64]) -> Option<f64> {
+    let after = curve.iter().position(|&fraction| fraction >= 0.5)?;
+    let before = after.checked_sub(1)?;
+    let (low, high) = (curve[before], curve[after]);
+    Some(before as f64 + (0.5 - low) / (high - low))
+}
+
+/// The parameters that spread one vote from a single voter to the nodes of `trace`, with no
+/// loss, so that a node has a quorum as soon as it has the vote.
+pub fn trace_params(trace: &Trace) -> Params {
+    let params = serde_json::json!({
+        "n": trace.num_nodes(),
+        "k": 1,
+        "voting_steps": 1,
+        "quorum": "threshold:0",
+    });
+    serde_json::from_value(params).expect("the other parameters have defaults")
+}
+
+/// The fraction of `num_nodes` nodes with the message after each round of a run, from the counts
+/// of nodes with a quorum at the end of each round, starting with round 0 in which only one has it.
+pub fn spread_curve(nodes_with_quorum: &[usize], num_nodes: usize) -> Vec<f64> {
+    let counts = std::iter::once(1).chain(nodes_with_quorum.iter().copied());
+    counts.map(|count| count as f64 / num_nodes as f64).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A trace of `csv` of a network of `num_nodes` nodes, read from a file of the test's own.
+    fn read(name: &str, csv: &str, num_nodes: Option<usize>) -> Result<Trace, String> {
+        let name = format!("gossip-trace-{}-{}.csv", name, std::process::id());
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, csv).unwrap();
+        let trace = Trace::read(path.to_str().unwrap(), num_nodes).map_err(|e| e.to_string());
+        std::fs::remove_file(&path).unwrap();
+        trace
+    }
+
+    #[test]
+    fn reads_first_arrivals() {
+        let csv = "node,time\nb,5\na,12\nb,3\nc,7\na,9\n";
+        let trace = read("duplicates", csv, None).unwrap();
+        // Later deliveries to a node count towards its deliveries, but not its arrival.
+        assert_eq!(trace.arrivals, vec![0.0, 4.0, 6.0]);
+        assert_eq!(trace.num_nodes(), 3);
+        assert_eq!(trace.deliveries_per_node(), 5.0 / 3.0);
+        assert_eq!(trace.milestones(), [Some(4.0), Some(6.0), Some(6.0), Some(6.0)]);
+
+        // Nodes that never received the message hold back the later milestones.
+        let trace = read("unreached", csv, Some(4)).unwrap();
+        assert_eq!(trace.deliveries_per_node(), 5.0 / 4.0);
+        assert_eq!(trace.milestones(), [Some(4.0), None, None, None]);
+    }
+
+    #[test]
+    fn rejects_traces_it_cant_fit() {
+        for (name, csv, num_nodes, error) in [
+            ("empty", "node,time\n", None, "has no deliveries"),
+            ("instant", "node,time\na,4\nb,4\na,4\n", None, "more than one time"),
+            ("too-many", "node,time\na,1\nb,2\nc,3\n", Some(2), "deliveries to 3 nodes"),
+            ("bad-time", "node,time\na,1\nb,x\n", None, "invalid delivery on line 3"),
+            ("infinite", "node,time\na,1\nb,inf\n", None, "invalid time on line 3"),
+        ] {
+            let e = read(name, csv, num_nodes).err().unwrap();
+            assert!(e.contains(error), "{:?} gave {:?}, expected {:?}", csv, e, error);
+        }
+    }
+
+    #[test]
+    fn finds_the_middle_between_rounds() {
+        assert_eq!(middle_round(&[0.1, 0.5, 0.9, 1.0]), Some(1.0));
+        assert_eq!(middle_round(&[0.1, 0.2, 0.4, 0.8, 1.0]), Some(2.25));
+        // Half the nodes never had it, or had it from the start.
+        assert_eq!(middle_round(&[0.1, 0.3, 0.4]), None);
+        assert_eq!(middle_round(&[0.6, 1.0]), None);
+    }
+
+    #[test]
+    fn fits_the_round_length_of_a_matching_spread() {
+        // 1, 5, 9 and then all 10 nodes have the message after each 2.5 units of time.
+        let csv = "node,time\n0,10\n1,12.5\n2,12.5\n3,12.5\n4,12.5\n\
+                   5,15\n6,15\n7,15\n8,15\n9,17.5\n";
+        let trace = read("synthetic", csv, None).unwrap();
+        let curves = [vec![0.1, 0.5, 0.9, 1.0], vec![0.1, 0.2, 0.5, 0.7, 0.9, 0.95, 1.0]];
+        let fit = trace.fit(&curves[..1]);
+        assert_eq!(fit.round_duration, 2.5);
+        assert_eq!(fit.error, 0.0);
+
+        // A spread that takes twice the rounds needs rounds of about half the length.
+        let fit = trace.fit(&curves[1..]);
+        assert!(fit.error > 0.0);
+        assert!((fit.round_duration - 1.25).abs() < 0.25, "{:?}", fit);
+        // Averaging over runs fits each one, so a worse run raises the error.
+        assert!(trace.fit(&curves).error > 0.0);
+    }
+}
@@ -0,0 +1,360 @@
+//! Voter sets stored as bitsets indexed by node ID.
+//!
+//! The number of nodes is known before a simulation starts, so a set can be allocated with one
+//! bit per node and diffs become word-at-a-time AND-NOT and OR operations. Most sets are tiny
+//! early in a run though, so sets of up to `INLINE_CAPACITY` voters are kept inline as a sorted
+//! list, and only switch to a bitset (allocated at its full size) once they outgrow it.
+
+use crate::NodeId;
+
+const WORD_BITS: usize = 64;
+
+/// Most voters held inline before switching to a bitset.
+const INLINE_CAPACITY: usize = 8;
+
+/// Iterate over the positions of the set bits in `words`, in increasing order.
+fn ones(words: impl Iterator<Item = u64>) -> impl Iterator<Item = NodeId> {
+    words.enumerate().flat_map(|(i, word)| {
+        let mut remaining = word;
+        std::iter::from_fn(move || {
+            if remaining == 0 {
+                return None;
+            }
+            let bit = remaining.trailing_zeros() as usize;
+            remaining &= remaining - 1;
+            Some((i * WORD_BITS + bit) as NodeId)
+        })
+    })
+}
+
+/// The words of `ours & !theirs`, treating any words missing from `theirs` as empty.
+fn and_not<'a>(ours: &'a [u64], theirs: &'a [u64]) -> impl Iterator<Item = u64> + 'a {
+    let (shared, rest) = ours.split_at(ours.len().min(theirs.len()));
+    shared.iter().zip(theirs).map(|(ours, theirs)| ours & !theirs).chain(rest.iter().cloned())
+}
+
+fn word_bit(voter: NodeId) -> (usize, u64) {
+    let voter = voter as usize;
+    (voter / WORD_BITS, 1 << (voter % WORD_BITS))
+}
+
+#[derive(Clone, Debug)]
+enum Repr {
+    /// The first `len` elements are the voters, in increasing order.
+    Inline { len: u8, voters: [NodeId; INLINE_CAPACITY] },
+    Bits(Vec<u64>),
+}
+
+/// A set of node IDs.
+///
+/// Sets created with `new` have room for every node once they switch to a bitset, but inserting
+/// a larger ID (or taking the union with a larger set) grows the set rather than panicking, so
+/// `Default` is an empty set.
+#[derive(Clone, Debug)]
+pub struct VoterSet {
+    /// Number of words to allocate when switching to a bitset.
+    num_words: usize,
+    repr: Repr,
+}
+
+impl Default for VoterSet {
+    fn default() -> Self {
+        VoterSet::new(0)
+    }
+}
+
+impl VoterSet {
+    /// A set with no voters, for comparing against missing entries without allocating.
+    pub fn empty() -> &'static VoterSet {
+        static EMPTY: VoterSet = VoterSet {
+            num_words: 0,
+            repr: Repr::Inline { len: 0, voters: [0; INLINE_CAPACITY] },
+        };
+        &EMPTY
+    }
+
+    /// An empty set with room for the nodes `0..num_nodes`.
+    pub fn new(num_nodes: usize) -> Self {
+        VoterSet::with_words(num_nodes.div_ceil(WORD_BITS))
+    }
+
+    fn with_words(num_words: usize) -> Self {
+        // A bitset that's no bigger than the inline voters might as well be used from the start.
+        let repr = if num_words * 2 <= INLINE_CAPACITY {
+            Repr::Bits(vec![0; num_words])
+        } else {
+            Repr::Inline { len: 0, voters: [0; INLINE_CAPACITY] }
+        };
+        VoterSet { num_words, repr }
+    }
+
+    /// Switch to a bitset of at least `min_words` words, if we aren't one already.
+    fn bits_mut(&mut self, min_words: usize) -> &mut Vec<u64> {
+        if let Repr::Inline { len, voters } = self.repr {
+            let voters = &voters[..len as usize];
+            let needed = voters.last().map_or(0, |&max| max as usize / WORD_BITS + 1);
+            let mut words = vec![0; self.num_words.max(min_words).max(needed)];
+            for &voter in voters {
+                let (word, bit) = word_bit(voter);
+                words[word] |= bit;
+            }
+            self.repr = Repr::Bits(words);
+        }
+        match self.repr {
+            Repr::Bits(ref mut words) => {
+                if words.len() < min_words {
+                    words.resize(min_words, 0);
+                }
+                words
+            }
+            Repr::Inline { .. } => unreachable!(),
+        }
+    }
+
+    /// The `i`th word of the set as a bitset.
+    fn word(&self, i: usize) -> u64 {
+        match self.repr {
+            Repr::Inline { len, ref voters } => voters[..len as usize]
+                .iter()
+                .map(|&voter| word_bit(voter))
+                .filter(|&(word, _)| word == i)
+                .fold(0, |acc, (_, bit)| acc | bit),
+            Repr::Bits(ref words) => words.get(i).cloned().unwrap_or(0),
+        }
+    }
+
+    /// Add `voter` to the set, returning whether it was newly added.
+    pub fn insert(&mut self, voter: NodeId) -> bool {
+        if let Repr::Inline { ref mut len, ref mut voters } = self.repr {
+            let n = *len as usize;
+            match voters[..n].binary_search(&voter) {
+                Ok(_) => return false,
+                Err(pos) if n < INLINE_CAPACITY => {
+                    voters.copy_within(pos..n, pos + 1);
+                    voters[pos] = voter;
+                    *len += 1;
+                    return true;
+                }
+                Err(_) => (),
+            }
+        }
+        let (word, bit) = word_bit(voter);
+        let words = self.bits_mut(word + 1);
+        let added = words[word] & bit == 0;
+        words[word] |= bit;
+        added
+    }
+
+    pub fn contains(&self, voter: NodeId) -> bool {
+        match self.repr {
+            Repr::Inline { len, ref voters } => voters[..len as usize].contains(&voter),
+            Repr::Bits(ref words) => {
+                let (word, bit) = word_bit(voter);
+                words.get(word).map(|w| w & bit != 0).unwrap_or(false)
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self.repr {
+            Repr::Inline { len, .. } => len as usize,
+            Repr::Bits(ref words) => words.iter().map(|word| word.count_ones() as usize).sum(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self.repr {
+            Repr::Inline { len, .. } => len == 0,
+            Repr::Bits(ref words) => words.iter().all(|&word| word == 0),
+        }
+    }
+
+    /// Iterate over the voters in increasing order.
+    pub fn iter(&self) -> Iter<'_> {
+        match self.repr {
+            Repr::Inline { len, ref voters } => Iter::Inline(voters[..len as usize].iter()),
+            Repr::Bits(ref words) => Iter::Bits { words, index: 0, remaining: 0 },
+        }
+    }
+
+    /// Remove every voter, keeping the set's memory.
+    pub fn clear(&mut self) {
+        match self.repr {
+            Repr::Inline { ref mut len, .. } => *len = 0,
+            Repr::Bits(ref mut words) => words.iter_mut().for_each(|word| *word = 0),
+        }
+    }
+
+    /// The voters in `self` that aren't in `other`, computed lazily without copying either set.
+    pub fn difference<'a>(&'a self, other: &'a VoterSet) -> Difference<'a> {
+        Difference { ours: self, theirs: other }
+    }
+
+    /// Add every voter in `other` to `self`, returning the number that were newly added.
+    pub fn union_with(&mut self, other: &VoterSet) -> usize {
+        match other.repr {
+            Repr::Inline { len, ref voters } => {
+                voters[..len as usize].iter().filter(|&&voter| self.insert(voter)).count()
+            }
+            Repr::Bits(ref other_words) => {
+                let words = self.bits_mut(other_words.len());
+                let mut added = 0;
+                for (word, &other_word) in words.iter_mut().zip(other_words) {
+                    added += (other_word & !*word).count_ones() as usize;
+                    *word |= other_word;
+                }
+                added
+            }
+        }
+    }
+
+    /// Add every voter in `difference` to `self`.
+    pub fn union_with_difference(&mut self, difference: &Difference) {
+        let (ours, theirs) = (difference.ours, difference.theirs);
+        let our_words = match ours.repr {
+            Repr::Inline { len, ref voters } => {
+                for &voter in &voters[..len as usize] {
+                    if !theirs.contains(voter) {
+                        self.insert(voter);
+                    }
+                }
+                return;
+            }
+            Repr::Bits(ref words) => words,
+        };
+        match theirs.repr {
+            Repr::Bits(ref their_words) => {
+                let words = self.bits_mut(our_words.len());
+                for (word, new_word) in words.iter_mut().zip(and_not(our_words, their_words)) {
+                    *word |= new_word;
+                }
+            }
+            Repr::Inline { len, ref voters } => {
+                // Add all of our voters, then take back the few of theirs that we didn't have.
+                let theirs = &voters[..len as usize];
+                let mut had = [false; INLINE_CAPACITY];
+                for (had, &voter) in had.iter_mut().zip(theirs) {
+                    *had = self.contains(voter);
+                }
+                let words = self.bits_mut(our_words.len());
+                for (word, &our_word) in words.iter_mut().zip(our_words) {
+                    *word |= our_word;
+                }
+                for (&voter, had) in theirs.iter().zip(had) {
+                    let (word, bit) = word_bit(voter);
+                    if !had && word < words.len() {
+                        words[word] &= !bit;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Iterator over the voters in a `VoterSet`.
+pub enum Iter<'a> {
+    Inline(std::slice::Iter<'a, NodeId>),
+    Bits { words: &'a [u64], index: usize, remaining: u64 },
+}
+
+impl Iterator for Iter<'_> {
+    type Item = NodeId;
+
+    fn next(&mut self) -> Option<NodeId> {
+        match self {
+            Iter::Inline(voters) => voters.next().cloned(),
+            Iter::Bits { words, index, remaining } => {
+                while *remaining == 0 {
+                    *remaining = *words.get(*index)?;
+                    *index += 1;
+                }
+                let bit = remaining.trailing_zeros() as usize;
+                *remaining &= *remaining - 1;
+                Some(((*index - 1) * WORD_BITS + bit) as NodeId)
+            }
+        }
+    }
+}
+
+/// A borrowed view of the voters in one set but not another.
+#[derive(Clone, Copy, Debug)]
+pub struct Difference<'a> {
+    ours: &'a VoterSet,
+    theirs: &'a VoterSet,
+}
+
+impl Difference<'_> {
+    /// The voters in `ours` (which are held inline) that aren't in their set.
+    fn missing<'a>(&'a self, ours: &'a [NodeId]) -> impl Iterator<Item = NodeId> + 'a {
+        ours.iter().cloned().filter(move |&voter| !self.theirs.contains(voter))
+    }
+
+    pub fn contains(&self, voter: NodeId) -> bool {
+        self.ours.contains(voter) && !self.theirs.contains(voter)
+    }
+
+    pub fn len(&self) -> usize {
+        match (&self.ours.repr, &self.theirs.repr) {
+            (Repr::Inline { len, voters }, _) => self.missing(&voters[..*len as usize]).count(),
+            (Repr::Bits(ours), Repr::Bits(theirs)) => {
+                and_not(ours, theirs).map(|word| word.count_ones() as usize).sum()
+            }
+            (Repr::Bits(_), Repr::Inline { .. }) => {
+                let shared = self.theirs.iter().filter(|&voter| self.ours.contains(voter)).count();
+                self.ours.len() - shared
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match (&self.ours.repr, &self.theirs.repr) {
+            (Repr::Bits(ours), Repr::Bits(theirs)) => and_not(ours, theirs).all(|word| word == 0),
+            (Repr::Inline { len, voters }, _) => {
+                self.missing(&voters[..*len as usize]).next().is_none()
+            }
+            (Repr::Bits(_), Repr::Inline { .. }) => self.len() == 0,
+        }
+    }
+
+    /// Iterate over the voters in increasing order.
+    pub fn iter(&self) -> Box<dyn Iterator<Item = NodeId> + '_> {
+        match self.ours.repr {
+            Repr::Inline { len, ref voters } => Box::new(self.missing(&voters[..len as usize])),
+            Repr::Bits(ref ours) => Box::new(ones(
+                ours.iter().enumerate().map(move |(i, word)| word & !self.theirs.word(i)),
+            )),
+        }
+    }
+
+    /// Copy the difference into a set of its own.
+    pub fn to_set(&self) -> VoterSet {
+        let mut set = VoterSet::with_words(self.ours.num_words);
+        set.union_with_difference(self);
+        set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn holds_up_to_eight_voters_inline() {
+        let mut set = VoterSet::new(10_000);
+        for voter in 0..INLINE_CAPACITY as NodeId {
+            set.insert(voter * 1000);
+            assert!(matches!(set.repr, Repr::Inline { .. }));
+        }
+        set.insert(1);
+        match set.repr {
+            Repr::Bits(ref words) => assert_eq!(words.len(), 10_000usize.div_ceil(WORD_BITS)),
+            Repr::Inline { .. } => panic!("nine voters should be a bitset"),
+        }
+    }
+
+    #[test]
+    fn small_networks_start_as_bitsets() {
+        assert!(matches!(VoterSet::new(256).repr, Repr::Bits(_)));
+        assert!(matches!(VoterSet::new(257).repr, Repr::Inline { .. }));
+    }
+}
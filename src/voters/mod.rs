@@ -0,0 +1,157 @@
+//! Sets of voters.
+//!
+//! By default a set is a bitset with one bit per node, which is compact and fast for dense sets,
+//! while sets of only a handful of voters are kept inline without allocating.
+//! For networks with millions of nodes where most voter sets are sparse, the `roaring` feature
+//! swaps in compressed Roaring bitmaps, whose memory use grows with the number of voters rather
+//! than the number of nodes.
+//!
+//! Both provide a `VoterSet` and a borrowed `Difference` between two sets with the same API.
+
+#[cfg(not(feature = "roaring"))]
+mod bitset;
+#[cfg(feature = "roaring")]
+mod roaring;
+
+#[cfg(not(feature = "roaring"))]
+pub use self::bitset::{Difference, VoterSet};
+#[cfg(feature = "roaring")]
+pub use self::roaring::{Difference, VoterSet};
+
+use crate::NodeId;
+
+impl Extend<NodeId> for VoterSet {
+    fn extend<I: IntoIterator<Item = NodeId>>(&mut self, voters: I) {
+        for voter in voters {
+            self.insert(voter);
+        }
+    }
+}
+
+impl FromIterator<NodeId> for VoterSet {
+    fn from_iter<I: IntoIterator<Item = NodeId>>(voters: I) -> Self {
+        let mut set = VoterSet::default();
+        set.extend(voters);
+        set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    /// Sets of every size around the switch from inline voters to a bitset, in a network of
+    /// `num_nodes` nodes.
+    fn sets_around_boundary(num_nodes: usize, offset: NodeId) -> Vec<(VoterSet, BTreeSet<NodeId>)> {
+        [0, 1, 7, 8, 9, 10, 64, 200]
+            .iter()
+            .map(|&size| {
+                let voters: BTreeSet<NodeId> =
+                    (0..size).map(|i| (i * 3 + offset) % num_nodes as NodeId).collect();
+                let mut set = VoterSet::new(num_nodes);
+                // Inserted in reverse, so the inline voters have to be kept sorted.
+                for &voter in voters.iter().rev() {
+                    assert!(set.insert(voter));
+                }
+                (set, voters)
+            })
+            .collect()
+    }
+
+    fn assert_same(set: &VoterSet, expected: &BTreeSet<NodeId>) {
+        assert_eq!(set.len(), expected.len());
+        assert_eq!(set.is_empty(), expected.is_empty());
+        assert_eq!(set.iter().collect::<Vec<_>>(), expected.iter().cloned().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn switches_to_a_bitset_past_eight_voters() {
+        let mut set = VoterSet::new(1000);
+        for voter in (0..8).map(|i| 999 - i * 100) {
+            assert!(set.insert(voter));
+        }
+        assert_eq!(set.len(), 8);
+        assert!(!set.insert(299), "a voter already held inline is not new");
+
+        // The ninth voter moves the set to a bitset, which must keep all eight.
+        assert!(set.insert(500));
+        assert!(!set.insert(500));
+        assert_eq!(set.len(), 9);
+        for voter in (0..8).map(|i| 999 - i * 100).chain([500]) {
+            assert!(set.contains(voter));
+        }
+        assert!(!set.contains(501));
+        let mut expected: Vec<NodeId> = (0..8).map(|i| 999 - i * 100).chain([500]).collect();
+        expected.sort_unstable();
+        assert_eq!(set.iter().collect::<Vec<_>>(), expected);
+
+        set.clear();
+        assert!(set.is_empty());
+        assert!(set.insert(3));
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![3]);
+    }
+
+    #[test]
+    fn default_sets_grow_to_fit_any_voter() {
+        let mut set = VoterSet::default();
+        let voters: Vec<NodeId> = (0..20).map(|i| i * 1000).collect();
+        set.extend(voters.iter().cloned());
+        assert_eq!(set.iter().collect::<Vec<_>>(), voters);
+        assert!(set.contains(19000));
+        assert!(!set.contains(19001));
+    }
+
+    #[test]
+    fn union_counts_the_new_voters() {
+        for (ours, our_voters) in sets_around_boundary(300, 0) {
+            for (theirs, their_voters) in sets_around_boundary(300, 1) {
+                let mut union = ours.clone();
+                let added = union.union_with(&theirs);
+                assert_eq!(added, their_voters.difference(&our_voters).count());
+                assert_same(&union, &our_voters.union(&their_voters).cloned().collect());
+            }
+        }
+    }
+
+    #[test]
+    fn difference_matches_the_set_difference() {
+        // Offset 0 against 0 overlaps completely, 3 shifts by one voter, and 1 is disjoint.
+        for offset in [0, 1, 3] {
+            for (ours, our_voters) in sets_around_boundary(300, 0) {
+                for (theirs, their_voters) in sets_around_boundary(300, offset) {
+                    let difference = ours.difference(&theirs);
+                    let expected: BTreeSet<NodeId> =
+                        our_voters.difference(&their_voters).cloned().collect();
+
+                    assert_eq!(difference.len(), expected.len());
+                    assert_eq!(difference.is_empty(), expected.is_empty());
+                    assert_eq!(
+                        difference.iter().collect::<Vec<_>>(),
+                        expected.iter().cloned().collect::<Vec<_>>()
+                    );
+                    for voter in 0..300 {
+                        assert_eq!(difference.contains(voter), expected.contains(&voter));
+                    }
+                    assert_same(&difference.to_set(), &expected);
+
+                    let mut union = theirs.clone();
+                    union.union_with_difference(&difference);
+                    assert_same(&union, &their_voters.union(&our_voters).cloned().collect());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn disjoint_difference_is_everything() {
+        let ours: VoterSet = (0..50).map(|i| i * 2).collect();
+        let theirs: VoterSet = (0..50).map(|i| i * 2 + 1).collect();
+        let difference = ours.difference(&theirs);
+        assert_eq!(difference.len(), 50);
+        assert_eq!(difference.iter().collect::<Vec<_>>(), ours.iter().collect::<Vec<_>>());
+        assert!(ours.difference(&ours).is_empty());
+        assert!(VoterSet::empty().difference(&ours).is_empty());
+        assert_eq!(ours.difference(VoterSet::empty()).len(), 50);
+    }
+}
@@ -0,0 +1,108 @@
+//! Voter sets stored as Roaring bitmaps.
+//!
+//! Node IDs are `u32`s, so they're stored in the bitmaps as they are.
+
+use roaring::RoaringBitmap;
+use std::sync::OnceLock;
+
+use crate::NodeId;
+
+/// A set of node IDs.
+#[derive(Clone, Default, Debug)]
+pub struct VoterSet {
+    bitmap: RoaringBitmap,
+}
+
+impl VoterSet {
+    /// A set with no voters, for comparing against missing entries without allocating.
+    pub fn empty() -> &'static VoterSet {
+        static EMPTY: OnceLock<VoterSet> = OnceLock::new();
+        EMPTY.get_or_init(VoterSet::default)
+    }
+
+    /// An empty set. Roaring bitmaps grow as needed, so `num_nodes` is only for compatibility
+    /// with the bitset backend.
+    pub fn new(_num_nodes: usize) -> Self {
+        VoterSet::default()
+    }
+
+    /// Add `voter` to the set, returning whether it was newly added.
+    pub fn insert(&mut self, voter: NodeId) -> bool {
+        self.bitmap.insert(voter)
+    }
+
+    pub fn contains(&self, voter: NodeId) -> bool {
+        self.bitmap.contains(voter)
+    }
+
+    pub fn len(&self) -> usize {
+        self.bitmap.len() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bitmap.is_empty()
+    }
+
+    /// Iterate over the voters in increasing order.
+    pub fn iter(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.bitmap.iter()
+    }
+
+    /// Remove every voter.
+    pub fn clear(&mut self) {
+        self.bitmap.clear();
+    }
+
+    /// The voters in `self` that aren't in `other`, computed lazily without copying either set.
+    pub fn difference<'a>(&'a self, other: &'a VoterSet) -> Difference<'a> {
+        Difference {
+            ours: &self.bitmap,
+            theirs: &other.bitmap,
+        }
+    }
+
+    /// Add every voter in `other` to `self`, returning the number that were newly added.
+    pub fn union_with(&mut self, other: &VoterSet) -> usize {
+        let before = self.bitmap.len();
+        self.bitmap |= &other.bitmap;
+        (self.bitmap.len() - before) as usize
+    }
+
+    /// Add every voter in `difference` to `self`.
+    pub fn union_with_difference(&mut self, difference: &Difference) {
+        self.bitmap |= difference.ours - difference.theirs;
+    }
+}
+
+/// A borrowed view of the voters in one set but not another.
+#[derive(Clone, Copy, Debug)]
+pub struct Difference<'a> {
+    ours: &'a RoaringBitmap,
+    theirs: &'a RoaringBitmap,
+}
+
+impl Difference<'_> {
+    pub fn contains(&self, voter: NodeId) -> bool {
+        self.ours.contains(voter) && !self.theirs.contains(voter)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ours.difference_len(self.theirs) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ours.is_subset(self.theirs)
+    }
+
+    /// Iterate over the voters in increasing order.
+    pub fn iter(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.ours.iter().filter(move |&voter| !self.theirs.contains(voter))
+    }
+
+    /// Copy the difference into a set of its own.
+    pub fn to_set(&self) -> VoterSet {
+        VoterSet {
+            bitmap: self.ours - self.theirs,
+        }
+    }
+}